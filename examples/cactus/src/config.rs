@@ -0,0 +1,108 @@
+/// config.rs - reads the user's preferences from a TOML file
+use crossterm::event::{KeyCode as KCode, KeyEvent, KeyModifiers as KMod};
+use crossterm::style::Color;
+use std::collections::HashMap;
+use toml::{Table, Value};
+
+/// The preferences of the user, which fall back to the defaults where they aren't given
+///
+/// ```toml
+/// tab_width = 4
+///
+/// [colours]
+/// string = [54, 161, 102]
+///
+/// [keys]
+/// "ctrl+k" = "delete_line"
+/// "alt+g" = "goto 1"
+/// ```
+#[derive(Debug, Clone)]
+pub struct Config {
+    /// The number of columns a tab takes up
+    pub tab_width: usize,
+    /// The colours of the syntax highlighting tokens, by token name
+    pub colours: HashMap<String, Color>,
+    /// Extra keybindings, from a key (e.g. `ctrl+k`) to a kaolinite command line (e.g. `goto 1`)
+    pub keys: HashMap<String, String>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self { tab_width: 4, colours: HashMap::default(), keys: HashMap::default() }
+    }
+}
+
+impl Config {
+    /// Read the config file at a path, using the defaults if it doesn't exist
+    pub fn load(path: &str) -> Result<Self, String> {
+        match std::fs::read_to_string(path) {
+            Ok(text) => Self::parse(&text).map_err(|e| format!("{path}: {e}")),
+            Err(_) => Ok(Self::default()),
+        }
+    }
+
+    /// Read a config from the contents of a TOML file
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let table: Table = text.parse().map_err(|e: toml::de::Error| e.to_string())?;
+        let mut config = Self::default();
+        if let Some(tab_width) = table.get("tab_width") {
+            let tab_width = tab_width.as_integer().ok_or("tab_width should be a number")?;
+            config.tab_width = usize::try_from(tab_width).map_err(|e| e.to_string())?;
+        }
+        for (name, value) in table.get("colours").and_then(Value::as_table).into_iter().flatten() {
+            let colour = colour(value).ok_or(format!("bad colour: {name}"))?;
+            config.colours.insert(name.clone(), colour);
+        }
+        for (key, command) in table.get("keys").and_then(Value::as_table).into_iter().flatten() {
+            let command = command.as_str().ok_or(format!("bad command for key: {key}"))?;
+            config.keys.insert(key.to_lowercase(), command.to_string());
+        }
+        Ok(config)
+    }
+
+    /// Find the command bound to a key press, if there is one
+    pub fn command(&self, key: &KeyEvent) -> Option<&String> {
+        self.keys.get(&key_name(key)?)
+    }
+}
+
+/// Read a colour written as `[r, g, b]`
+fn colour(value: &Value) -> Option<Color> {
+    let parts = value.as_array()?;
+    let mut rgb = parts.iter().map(|p| p.as_integer().and_then(|p| u8::try_from(p).ok()));
+    match (rgb.next()??, rgb.next()??, rgb.next()??, rgb.next()) {
+        (r, g, b, None) => Some(Color::Rgb { r, g, b }),
+        _ => None,
+    }
+}
+
+/// Describe a key press the way keys are written in the config file (e.g. `ctrl+shift+left`)
+fn key_name(key: &KeyEvent) -> Option<String> {
+    let code = match key.code {
+        KCode::Char(' ') => "space".to_string(),
+        KCode::Char(ch) => ch.to_lowercase().to_string(),
+        KCode::F(n) => format!("f{n}"),
+        KCode::Up => "up".to_string(),
+        KCode::Down => "down".to_string(),
+        KCode::Left => "left".to_string(),
+        KCode::Right => "right".to_string(),
+        KCode::Home => "home".to_string(),
+        KCode::End => "end".to_string(),
+        KCode::PageUp => "pageup".to_string(),
+        KCode::PageDown => "pagedown".to_string(),
+        KCode::Tab => "tab".to_string(),
+        KCode::Backspace => "backspace".to_string(),
+        KCode::Delete => "delete".to_string(),
+        KCode::Enter => "enter".to_string(),
+        KCode::Esc => "esc".to_string(),
+        _ => return None,
+    };
+    let mut name = String::new();
+    let prefixes = [(KMod::CONTROL, "ctrl+"), (KMod::ALT, "alt+"), (KMod::SHIFT, "shift+")];
+    for (modifier, prefix) in prefixes {
+        if key.modifiers.contains(modifier) {
+            name.push_str(prefix);
+        }
+    }
+    Some(name + &code)
+}
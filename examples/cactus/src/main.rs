@@ -10,11 +10,14 @@
   - Search forward and backward in a document
   - Replace text in a document
   - Efficient syntax highlighting
+  - Preferences and extra keybindings read from a TOML config file
   - Compiles in under half a minute on most modern computers
 */
 
 #![allow(unused_must_use)]
 
+mod config;
+
 use crossterm::{
     cursor::{Hide, MoveTo, Show},
     event::{read, Event as CEvent, KeyCode as KCode, KeyModifiers as KMod},
@@ -22,9 +25,12 @@ use crossterm::{
     style::{Color, SetBackgroundColor as Bg, SetForegroundColor as Fg},
     terminal::{self, Clear, ClearType as ClType, EnterAlternateScreen, LeaveAlternateScreen, EnableLineWrap, DisableLineWrap},
 };
+use config::Config;
 use jargon_args::Jargon;
-use kaolinite::event::{Event, Result, Status};
-use kaolinite::utils::{filetype, Loc, Size};
+use kaolinite::commands::Commands;
+use kaolinite::event::{Event, GroupingPolicy, Result, Status};
+use kaolinite::layout::{Layout, LayoutOptions};
+use kaolinite::utils::{align_sides, width, Align, Loc, Size};
 use kaolinite::Document;
 use synoptic::{Highlighter, TokOpt, trim, from_extension};
 use std::io::{stdout, Stdout, Write};
@@ -32,20 +38,33 @@ use std::io::{stdout, Stdout, Write};
 /// Store the version number at compile time
 const VERSION: &str = env!("CARGO_PKG_VERSION");
 
+/// Screen layout, with line numbers and a separator to the left and a status line at the bottom
+const LAYOUT: LayoutOptions = LayoutOptions {
+    line_numbers: true,
+    sign_column: false,
+    separator: " │",
+    gutter_padding: 0,
+    top_lines: 0,
+    bottom_lines: 1,
+};
+
 /// Help text for the command line interface
 const HELP: &str = "\
 Cactus: A compact and complete kaolinite implementation
 
-USAGE: cactus [options] [files]
+USAGE: kaolinite-demo [options] [files]
 
 OPTIONS:
-    --help, -h    : Show this help message
-    --version, -v : Show the version number 
+    --help, -h          : Show this help message
+    --version, -v       : Show the version number 
+    --config, -c [path] : Read preferences from a TOML file
+                          (defaults to ~/.config/kaolinite-demo.toml)
 
 EXAMPLES:
-    cactus test.txt
-    cactus test.txt test2.txt
-    cactus /home/user/docs/test.txt
+    kaolinite-demo test.txt
+    kaolinite-demo test.txt test2.txt
+    kaolinite-demo --config config.toml test.txt
+    kaolinite-demo /home/user/docs/test.txt
 ";
 
 fn main() {
@@ -71,7 +90,18 @@ fn run() -> Result<()> {
     } else if args.contains(["-v", "--version"]) {
         println!("{}", VERSION);
     } else {
-        let mut e = Editor::new()?;
+        let path = args.option_arg::<String, _>(["-c", "--config"]).unwrap_or_else(|| {
+            let home = std::env::var("HOME").unwrap_or_default();
+            format!("{home}/.config/kaolinite-demo.toml")
+        });
+        let config = match Config::load(&path) {
+            Ok(config) => config,
+            Err(err) => {
+                println!("Couldn't read config file: {}", err);
+                return Ok(());
+            }
+        };
+        let mut e = Editor::new(config)?;
         let mut error = false;
         // Try to open the requested files
         for file in args.finish() {
@@ -106,33 +136,51 @@ pub struct Editor {
     doc: Vec<Document>,
     /// Syntax highlighting integration
     highlighter: Highlighter,
+    /// The preferences of the user
+    config: Config,
+    /// The commands that keys can be bound to in the config file
+    commands: Commands,
     /// Pointer to the document that is currently being edited
     ptr: usize,
     /// true if the editor is still running, false otherwise
     active: bool,
+    /// A message to show in the status line until the next key press, such as an error
+    message: Option<String>,
 }
 
 impl Editor {
     /// Create a new instance of the editor
-    pub fn new() -> Result<Self> {
+    pub fn new(config: Config) -> Result<Self> {
         Ok(Self {
             doc: vec![],
             ptr: 0,
             stdout: stdout(),
             active: true,
-            highlighter: Highlighter::new(4),
+            message: None,
+            highlighter: Highlighter::new(config.tab_width),
+            config,
+            commands: Commands::default(),
         })
     }
 
-    /// Function to open a document into the editor
+    /// Function to open a document into the editor, with an optional location after the file name
+    /// (such as `src/main.rs:42:7`)
     pub fn open(&mut self, file_name: String) -> Result<()> {
         let size = size()?;
-        let mut doc = Document::open(size, file_name.clone())?;
+        let mut doc = Document::open_at(size, &file_name)?;
+        // Wrap the cursor onto the next and previous lines when moving left and right
+        doc.set_wrap_cursor(true);
+        // Undo and redo a word at a time
+        doc.set_grouping(GroupingPolicy::Word);
+        // Draw guides at the conventional line length limits
+        doc.set_guides(vec![80, 120]);
+        doc.set_tab_width(self.config.tab_width);
         // Load all the lines within viewport into the document
         doc.load_to(size.h);
         // Update in the syntax highlighter
-        let ext = file_name.split('.').last().unwrap();
-        self.highlighter = from_extension(ext, 4).unwrap_or(Highlighter::new(4));
+        let ext = doc.status_info().extension.unwrap_or_default();
+        let tab_width = self.config.tab_width;
+        self.highlighter = from_extension(&ext, tab_width).unwrap_or(Highlighter::new(tab_width));
         self.highlighter.run(&doc.lines);
         // Add document to documents
         self.doc.push(doc);
@@ -165,7 +213,8 @@ impl Editor {
 
     /// Execute an edit event
     pub fn exe(&mut self, ev: Event) -> Result<()> {
-        self.doc_mut().exe(ev)
+        self.doc_mut().exe(ev)?;
+        Ok(())
     }
 
     /// Initialise, render and handle events as they come in
@@ -176,7 +225,17 @@ impl Editor {
         while self.active {
             self.render()?;
             // Wait for an event
-            match read()? {
+            let event = read()?;
+            self.message = None;
+            match event {
+                // Keys bound in the config file take priority
+                CEvent::Key(key) if self.config.command(&key).is_some() => {
+                    let line = self.config.command(&key).cloned().unwrap_or_default();
+                    // A command that is unknown or fails shouldn't close the editor
+                    if let Err(err) = self.run_command(&line) {
+                        self.message = Some(format!("{line}: {err}"));
+                    }
+                }
                 CEvent::Key(key) => match (key.modifiers, key.code) {
                     // Movement
                     (KMod::NONE, KCode::Up) => self.up(),
@@ -202,8 +261,8 @@ impl Editor {
                     (KMod::SHIFT, KCode::Left) => self.prev(),
                     (KMod::SHIFT, KCode::Right) => self.next(),
                     // Undo & Redo
-                    (KMod::CONTROL, KCode::Char('z')) => self.doc_mut().undo()?,
-                    (KMod::CONTROL, KCode::Char('y')) => self.doc_mut().redo()?,
+                    (KMod::CONTROL, KCode::Char('z')) => { self.doc_mut().undo()?; }
+                    (KMod::CONTROL, KCode::Char('y')) => { self.doc_mut().redo()?; }
                     // Editing
                     (KMod::SHIFT | KMod::NONE, KCode::Char(ch)) => self.character(ch),
                     (KMod::NONE, KCode::Tab) => self.character('\t'),
@@ -214,16 +273,15 @@ impl Editor {
                 },
                 CEvent::Resize(w, h) => {
                     // Ensure all lines in viewport are loaded
-                    let max = self.doc().len_lines().to_string().len() + 2;
-                    self.doc_mut().size.w = w.saturating_sub(max as u16) as usize;
-                    self.doc_mut().size.h = h.saturating_sub(1) as usize;
+                    let terminal = Size { w: w as usize, h: h as usize };
+                    Layout::new(terminal, self.doc(), &LAYOUT).apply(self.doc_mut());
                     let max = self.doc().offset.x + self.doc().size.h;
                     self.doc_mut().load_to(max);
                 }
                 _ => (),
             }
             // Append any missed lines to the syntax highlighter
-            let actual = self.doc.get(self.ptr).and_then(|d| Some(d.loaded_to)).unwrap_or(0);
+            let actual = self.doc.get(self.ptr).map_or(0, |d| d.loaded_to);
             let percieved = self.highlighter.line_ref.len();
             if percieved < actual {
                 let diff = actual - percieved;
@@ -241,31 +299,33 @@ impl Editor {
     pub fn render(&mut self) -> Result<()> {
         execute!(self.stdout, Hide)?;
         let Size { w, h } = size()?;
-        // Update the width of the document in case of update
-        let max = self.doc().len_lines().to_string().len() + 2;
-        self.doc_mut().size.w = w.saturating_sub(max) as usize;
+        // Update the size of the document in case of update
+        let layout = Layout::new(Size { w, h: h + 1 }, self.doc(), &LAYOUT);
+        layout.apply(self.doc_mut());
         // Run through each line of the terminal, rendering the correct line
         self.render_document(w, h)?;
         // Leave last line for status line
         self.render_status_line(w, h)?;
         // Move cursor to the correct location and perform render
-        let Loc { x, y } = self.doc().cursor;
-        execute!(self.stdout, Show, MoveTo((x + max) as u16, y as u16))?;
+        let cursor = kaolinite::terminal::cursor(&layout, self.doc());
+        execute!(self.stdout, Show, cursor)?;
         self.stdout.flush()?;
         Ok(())
     }
 
     /// Render the lines of the document
     fn render_document(&mut self, w: usize, h: usize) -> Result<()> {
+        let layout = Layout::new(Size { w, h: h + 1 }, self.doc(), &LAYOUT);
         for y in 0..(h as u16) {
             execute!(self.stdout, MoveTo(0, y))?;
-            // Write line number of document
-            let num = self.doc().line_number(y as usize + self.doc().offset.y);
+            // Write the gutter, with the line number of the document
+            let idx = y as usize + self.doc().offset.y;
+            let gutter = layout.gutter_render(self.doc(), &LAYOUT, idx);
             write!(
                 self.stdout,
-                "{}{} │{}{}",
+                "{}{}{}{}",
                 Fg(Color::Rgb { r: 150, g: 150, b: 150 }),
-                num,
+                gutter,
                 Fg(Color::Reset),
                 Clear(ClType::UntilNewLine),
             )?;
@@ -285,6 +345,21 @@ impl Editor {
                         TokOpt::None(text) => write!(self.stdout, "{text}"),
                     }?
                 }
+                // Draw any guides that the line doesn't reach
+                let max = layout.gutter;
+                let reach = width(&line, self.config.tab_width);
+                let reach = reach.saturating_sub(self.doc().offset.x);
+                for column in self.doc().guides_in_view() {
+                    if column >= reach {
+                        execute!(self.stdout, MoveTo((max + column) as u16, y))?;
+                        write!(
+                            self.stdout,
+                            "{}│{}",
+                            Fg(Color::Rgb { r: 60, g: 60, b: 60 }),
+                            Fg(Color::Reset),
+                        )?;
+                    }
+                }
             }
         }
         Ok(())
@@ -293,25 +368,25 @@ impl Editor {
     /// Render the status line at the bottom of the document
     fn render_status_line(&mut self, w: usize, h: usize) -> Result<()> {
         execute!(self.stdout, MoveTo(0, h as u16))?;
-        let ext = self.doc().file_name.as_ref().unwrap().split('.').last().unwrap().to_string();
-        // Form left hand side of status bar
-        let lhs = format!(
-            "{}{} │ {} │",
-            self.doc().file_name.as_ref().unwrap().split('/').last().unwrap(),
-            if self.doc().modified { "[+]" } else { "" },
-            filetype(&ext).unwrap_or(ext)
-        );
+        let info = self.doc().status_info();
+        // Form left hand side of status bar, or show the message if there is one
+        let lhs = self.message.clone().unwrap_or_else(|| format!(
+            "{}{}{} │ {} │",
+            info.file_name.unwrap_or_else(|| "[No Name]".to_string()),
+            if info.modified { "[+]" } else { "" },
+            if self.doc().insert_final_newline { "" } else { "[noeol]" },
+            info.file_type.or(info.extension).unwrap_or_else(|| "Unknown".to_string()),
+        ));
         // Form right hand side of status bar
         let rhs = format!(
             "│ {}/{} {} {}",
-            self.doc().loc().y + 1,
-            self.doc().len_lines(),
-            self.doc().char_ptr,
+            info.line,
+            info.total_lines,
+            info.column,
             self.doc().loc().x,
         );
-        // Use alinio to align left and right with padding between
-        let status_line = alinio::align::between(&[&lhs, &rhs], w.saturating_sub(2))
-            .unwrap_or_else(|| "".to_string());
+        // Align left and right with padding between, truncating the left on narrow terminals
+        let status_line = align_sides(&lhs, &rhs, w.saturating_sub(2), Align::Right, 4);
         // Write the status bar
         write!(
             self.stdout,
@@ -357,6 +432,9 @@ impl Editor {
 
     /// Find the appropriate syntax highlighting colour
     fn highlight_colour(&self, name: &str) -> String {
+        if let Some(colour) = self.config.colours.get(name) {
+            return Fg(*colour).to_string();
+        }
         match name {
             "string" => Fg(Color::Rgb { r: 54, g: 161, b: 102 }),
             "comment" => Fg(Color::Rgb { r: 108, g: 107, b: 90 }),
@@ -381,6 +459,15 @@ impl Editor {
         }.to_string()
     }
 
+    /// Run a kaolinite command line (e.g. `goto 1`) on the current document
+    fn run_command(&mut self, line: &str) -> Result<()> {
+        let doc = &mut self.doc[self.ptr];
+        self.commands.run_line(doc, line)?;
+        // The command could have changed any part of the document
+        self.highlighter.run(&self.doc[self.ptr].lines);
+        Ok(())
+    }
+
     /// Move to the next document opened in the editor
     fn next(&mut self) {
         if self.ptr + 1 < self.doc.len() {
@@ -407,22 +494,12 @@ impl Editor {
 
     /// Move the cursor left
     fn left(&mut self) {
-        let status = self.doc_mut().move_left();
-        // Cursor wrapping if cursor hits the start of the line
-        if status == Status::StartOfLine && self.doc().loc().y != 0 {
-            self.doc_mut().move_up();
-            self.doc_mut().move_end();
-        }
+        self.doc_mut().move_left();
     }
 
     /// Move the cursor right
     fn right(&mut self) {
-        let status = self.doc_mut().move_right();
-        // Cursor wrapping if cursor hits the end of a line
-        if status == Status::EndOfLine {
-            self.doc_mut().move_down();
-            self.doc_mut().move_home();
-        }
+        self.doc_mut().move_right();
     }
 
     /// Move the cursor to the previous word in the line
@@ -500,7 +577,7 @@ impl Editor {
     fn new_row(&mut self) {
         if self.doc().loc().y == self.doc().len_lines() {
             self.exe(Event::InsertLine(self.doc().loc().y, "".to_string()));
-            self.highlighter.append(&"".to_string());
+            self.highlighter.append("");
         }
     }
 
@@ -0,0 +1,46 @@
+/* kaolinite.h - C interface to kaolinite, enabled with the `ffi` feature */
+#ifndef KAOLINITE_H
+#define KAOLINITE_H
+
+#include <stddef.h>
+
+#define KAOLINITE_OK 0
+#define KAOLINITE_ERR -1
+
+typedef struct Document Document;
+
+typedef enum {
+    KAOLINITE_INSERT = 0,
+    KAOLINITE_DELETE = 1,
+    KAOLINITE_INSERT_LINE = 2,
+    KAOLINITE_DELETE_LINE = 3,
+    KAOLINITE_SPLIT_DOWN = 4,
+    KAOLINITE_SPLICE_UP = 5,
+} KaoliniteEventKind;
+
+typedef struct {
+    size_t x;
+    size_t y;
+} KaoliniteLoc;
+
+Document *kaolinite_document_new(size_t w, size_t h);
+Document *kaolinite_document_open(const char *path, size_t w, size_t h);
+void kaolinite_document_free(Document *doc);
+
+/* kind is one of KaoliniteEventKind, anything else returns KAOLINITE_ERR */
+int kaolinite_document_exe(Document *doc, int kind, size_t x, size_t y, const char *text);
+int kaolinite_document_commit(Document *doc);
+int kaolinite_document_undo(Document *doc);
+int kaolinite_document_redo(Document *doc);
+int kaolinite_document_save(Document *doc);
+void kaolinite_document_load_to(Document *doc, size_t to);
+
+char *kaolinite_document_line(const Document *doc, size_t y);
+size_t kaolinite_document_len_lines(const Document *doc);
+KaoliniteLoc kaolinite_document_cursor(const Document *doc);
+KaoliniteLoc kaolinite_document_char_loc(const Document *doc);
+void kaolinite_document_goto(Document *doc, size_t x, size_t y);
+
+void kaolinite_string_free(char *st);
+
+#endif
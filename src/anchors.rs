@@ -0,0 +1,118 @@
+/// anchors.rs - keeps track of lines as lines are inserted and removed around them
+use crate::event::Event;
+use std::collections::BTreeMap;
+use std::ops::Range;
+
+/// Identifies an anchor, given out by `Anchors::add`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct AnchorId(usize);
+
+/// Where an event inserts or removes a line, for moving things that are attached to lines
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LineShift {
+    /// A line was inserted at this index, moving it and the lines below it down
+    Inserted(usize),
+    /// The line at this index was removed, along with its text
+    Removed(usize),
+    /// The line at this index was joined onto the end of the line above
+    Joined(usize),
+}
+
+impl LineShift {
+    /// Work out how an event changes the lines, if it changes the number of them
+    #[must_use]
+    pub fn of(ev: &Event) -> Option<Self> {
+        match ev {
+            Event::InsertLine(y, _) => Some(Self::Inserted(*y)),
+            // Splitting at the start of a line moves all of its text down
+            Event::SplitDown(loc) if loc.x == 0 => Some(Self::Inserted(loc.y)),
+            Event::SplitDown(loc) => Some(Self::Inserted(loc.y + 1)),
+            Event::DeleteLine(y, _) => Some(Self::Removed(*y)),
+            Event::SpliceUp(loc) => Some(Self::Joined(loc.y + 1)),
+            Event::Insert(..) | Event::Delete(..) => None,
+        }
+    }
+}
+
+/// Line anchors are attached to a line and follow it as lines are inserted and removed above it,
+/// so that anything kept against a line (signs, diagnostics, folds, breakpoints) stays with its
+/// text as the document is edited.
+/// When the line of an anchor is removed, so is the anchor, and it is kept aside until it is
+/// collected with `Anchors::take_removed`.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Anchors {
+    /// The line each anchor is on
+    lines: BTreeMap<AnchorId, usize>,
+    /// Anchors whose lines have been removed, that haven't been collected yet
+    pub removed: Vec<AnchorId>,
+    /// The identifier the next anchor will be given
+    next: usize,
+}
+
+impl Anchors {
+    /// Attach a new anchor to a line
+    pub fn add(&mut self, y: usize) -> AnchorId {
+        let id = AnchorId(self.next);
+        self.next += 1;
+        self.lines.insert(id, y);
+        id
+    }
+
+    /// Remove an anchor, returning the line it was on, if it still exists
+    pub fn remove(&mut self, id: AnchorId) -> Option<usize> {
+        self.lines.remove(&id)
+    }
+
+    /// Get the line an anchor is on, if it still exists
+    #[must_use]
+    pub fn line(&self, id: AnchorId) -> Option<usize> {
+        self.lines.get(&id).copied()
+    }
+
+    /// Get the anchors on a line, oldest first
+    #[must_use]
+    pub fn at(&self, y: usize) -> Vec<AnchorId> {
+        self.lines.iter().filter(|(_, line)| **line == y).map(|(id, _)| *id).collect()
+    }
+
+    /// Get the anchors within a range of lines (such as those on the screen), as the line and
+    /// the anchor, in order of line
+    #[must_use]
+    pub fn within(&self, lines: Range<usize>) -> Vec<(usize, AnchorId)> {
+        let mut found: Vec<(usize, AnchorId)> = self
+            .lines
+            .iter()
+            .filter(|(_, line)| lines.contains(line))
+            .map(|(id, line)| (*line, *id))
+            .collect();
+        found.sort_unstable();
+        found
+    }
+
+    /// Determine if there are no anchors
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.lines.is_empty()
+    }
+
+    /// Move the anchors to follow their lines after the number of lines has changed.
+    /// Anchors on a joined line move to the line above, with its text.
+    pub fn shift(&mut self, shift: LineShift) {
+        for (id, line) in &mut self.lines {
+            match shift {
+                LineShift::Inserted(y) if *line >= y => *line += 1,
+                LineShift::Removed(y) if *line == y => self.removed.push(*id),
+                LineShift::Removed(y) | LineShift::Joined(y) if *line >= y => *line -= 1,
+                _ => (),
+            }
+        }
+        let removed = &self.removed;
+        self.lines.retain(|id, _| !removed.contains(id));
+    }
+
+    /// Collect the anchors that have been removed along with their lines, since this was last
+    /// called
+    pub fn take_removed(&mut self) -> Vec<AnchorId> {
+        std::mem::take(&mut self.removed)
+    }
+}
@@ -0,0 +1,168 @@
+/// commands.rs - maps command names to document operations, as a base for keybindings and palettes
+use crate::document::Document;
+use crate::event::{Error, Event, Result, Status};
+use crate::utils::Loc;
+use std::collections::HashMap;
+
+/// A command takes a document and a list of arguments and performs an operation on it
+pub type Command = fn(&mut Document, &[&str]) -> Result<Status>;
+
+/// A registry of named commands.
+/// The default registry contains all the built in document operations, and editors can register
+/// their own commands on top of them.
+#[derive(Clone, Debug)]
+pub struct Commands {
+    pub map: HashMap<String, Command>,
+}
+
+impl Default for Commands {
+    fn default() -> Self {
+        let mut cmds = Self::new();
+        // Movement
+        cmds.register("move_up", |doc, _| Ok(doc.move_up()));
+        cmds.register("move_down", |doc, _| Ok(doc.move_down()));
+        cmds.register("move_left", |doc, _| Ok(doc.move_left()));
+        cmds.register("move_right", |doc, _| Ok(doc.move_right()));
+        cmds.register("move_word_forward", |doc, _| Ok(doc.move_next_word()));
+        cmds.register("move_word_backward", |doc, _| Ok(doc.move_prev_word()));
+        cmds.register("move_home", |doc, _| { doc.move_home(); Ok(Status::None) });
+        cmds.register("move_end", |doc, _| { doc.move_end(); Ok(Status::None) });
+        cmds.register("move_top", |doc, _| { doc.move_top(); Ok(Status::None) });
+        cmds.register("move_bottom", |doc, _| { doc.move_bottom(); Ok(Status::None) });
+        cmds.register("move_page_up", |doc, _| { doc.move_page_up(); Ok(Status::None) });
+        cmds.register("move_page_down", |doc, _| { doc.move_page_down(); Ok(Status::None) });
+        cmds.register("move_half_page_up", |doc, _| Ok(doc.move_half_page_up()));
+        cmds.register("move_half_page_down", |doc, _| Ok(doc.move_half_page_down()));
+        cmds.register("goto", |doc, args| {
+            // Arguments are 1-based line and (optional) column numbers
+            let y = arg_num(args, 0)?.saturating_sub(1);
+            let x = if args.len() > 1 { arg_num(args, 1)?.saturating_sub(1) } else { 0 };
+            doc.goto(&Loc::at(x, y));
+            doc.old_cursor = doc.char_ptr;
+            Ok(Status::None)
+        });
+        // Editing
+        cmds.register("insert", |doc, args| {
+            doc.exe(Event::Insert(doc.char_loc(), args.join(" ")))?;
+            Ok(Status::None)
+        });
+        cmds.register("insert_line", |doc, args| {
+            doc.exe(Event::InsertLine(doc.loc().y, args.join(" ")))?;
+            Ok(Status::None)
+        });
+        cmds.register("delete_line", |doc, _| {
+            let y = doc.loc().y;
+            let line = doc.line(y).ok_or(Error::OutOfRange)?;
+            doc.exe(Event::DeleteLine(y, line))?;
+            Ok(Status::None)
+        });
+        cmds.register("split_down", |doc, _| {
+            doc.exe(Event::SplitDown(doc.char_loc()))?;
+            Ok(Status::None)
+        });
+        cmds.register("replace_all", |doc, args| {
+            let target = args.first().ok_or(Error::InvalidArguments)?;
+            doc.replace_all(target, &args[1..].join(" "))?;
+            Ok(Status::None)
+        });
+        cmds.register("search", |doc, args| {
+            if let Some(mtch) = doc.next_match(&args.join(" "), 1) {
+                doc.goto(&mtch.loc);
+                doc.old_cursor = doc.char_ptr;
+            }
+            Ok(Status::None)
+        });
+        // History
+        cmds.register("commit", |doc, _| { doc.event_mgmt.commit(); Ok(Status::None) });
+        cmds.register("undo", |doc, _| doc.undo());
+        cmds.register("redo", |doc, _| doc.redo());
+        // File management
+        cmds.register("save", |doc, _| { doc.save()?; Ok(Status::None) });
+        cmds.register("save_as", |doc, args| {
+            doc.save_as(args.first().ok_or(Error::InvalidArguments)?)?;
+            Ok(Status::None)
+        });
+        cmds.register("insert_file", |doc, args| {
+            let path = args.first().ok_or(Error::InvalidArguments)?;
+            doc.insert_file(Loc::at(0, doc.loc().y + 1), path)?;
+            Ok(Status::None)
+        });
+        cmds.register("rename", |doc, args| {
+            doc.rename(args.first().ok_or(Error::InvalidArguments)?)?;
+            Ok(Status::None)
+        });
+        cmds
+    }
+}
+
+impl Commands {
+    /// Create a new registry, with no commands in it
+    #[must_use]
+    pub fn new() -> Self {
+        Self { map: HashMap::default() }
+    }
+
+    /// Register a command under a name, replacing any command already registered under it
+    pub fn register<S: Into<String>>(&mut self, name: S, cmd: Command) {
+        self.map.insert(name.into(), cmd);
+    }
+
+    /// Determine if a command exists in the registry
+    #[must_use]
+    pub fn contains(&self, name: &str) -> bool {
+        self.map.contains_key(name)
+    }
+
+    /// Get the names of every command in the registry, in alphabetical order
+    #[must_use]
+    pub fn names(&self) -> Vec<&str> {
+        let mut names: Vec<&str> = self.map.keys().map(String::as_str).collect();
+        names.sort_unstable();
+        names
+    }
+
+    /// Run a command on a document.
+    /// # Errors
+    /// Returns an error if the command doesn't exist, or if the command itself failed.
+    pub fn run(&self, doc: &mut Document, name: &str, args: &[&str]) -> Result<Status> {
+        let cmd = self.map.get(name).ok_or_else(|| Error::UnknownCommand(name.to_string()))?;
+        cmd(doc, args)
+    }
+
+    /// Run a command a number of times as a single undo patch (see `Document::repeat`).
+    /// # Errors
+    /// Returns an error if the command doesn't exist, or if the command itself failed.
+    pub fn run_n(
+        &self,
+        doc: &mut Document,
+        count: usize,
+        name: &str,
+        args: &[&str],
+    ) -> Result<Status> {
+        let cmd = self.map.get(name).ok_or_else(|| Error::UnknownCommand(name.to_string()))?;
+        doc.repeat(count, |doc| cmd(doc, args))
+    }
+
+    /// Run a command from a line of text, such as one typed into a command palette.
+    /// The first word is the command name and the rest are the arguments.
+    /// The command name can be preceded by a count, to run it that many times (e.g. `5 move_down`).
+    /// # Errors
+    /// Returns an error if the command doesn't exist, or if the command itself failed.
+    pub fn run_line(&self, doc: &mut Document, line: &str) -> Result<Status> {
+        let mut words = line.split_whitespace().peekable();
+        let count = words.next_if(|w| w.parse::<usize>().is_ok()).map(str::parse::<usize>);
+        let name = words.next().ok_or(Error::InvalidArguments)?;
+        let args: Vec<&str> = words.collect();
+        match count {
+            Some(Ok(count)) => self.run_n(doc, count, name, &args),
+            _ => self.run(doc, name, &args),
+        }
+    }
+}
+
+/// Parse a numerical argument
+fn arg_num(args: &[&str], idx: usize) -> Result<usize> {
+    args.get(idx)
+        .and_then(|a| a.parse().ok())
+        .ok_or(Error::InvalidArguments)
+}
@@ -0,0 +1,58 @@
+/// completion.rs - keeps track of the words in a document, for completing words as they are typed
+use std::collections::HashMap;
+
+/// Counts how many times each word appears, so that it can be kept up to date line by line
+/// rather than rescanning the whole document
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct WordIndex {
+    /// The number of times each word appears
+    pub counts: HashMap<String, usize>,
+}
+
+impl WordIndex {
+    /// Create a new index from some text
+    #[must_use]
+    pub fn new(text: &str) -> Self {
+        let mut index = Self::default();
+        index.add_line(text);
+        index
+    }
+
+    /// Add the words in a line (or any text) to the index
+    pub fn add_line(&mut self, line: &str) {
+        for word in words(line) {
+            *self.counts.entry(word.to_string()).or_default() += 1;
+        }
+    }
+
+    /// Remove the words in a line (or any text) from the index
+    pub fn remove_line(&mut self, line: &str) {
+        for word in words(line) {
+            if let Some(count) = self.counts.get_mut(word) {
+                *count = count.saturating_sub(1);
+                if *count == 0 {
+                    self.counts.remove(word);
+                }
+            }
+        }
+    }
+
+    /// Find the words that start with a prefix, with the most common first.
+    /// The prefix itself isn't included, as it doesn't need completing.
+    #[must_use]
+    pub fn complete(&self, prefix: &str) -> Vec<String> {
+        let mut found: Vec<(&String, &usize)> = self
+            .counts
+            .iter()
+            .filter(|(word, _)| word.starts_with(prefix) && word.as_str() != prefix)
+            .collect();
+        found.sort_by(|a, b| b.1.cmp(a.1).then_with(|| a.0.cmp(b.0)));
+        found.into_iter().map(|(word, _)| word.clone()).collect()
+    }
+}
+
+/// Split text into words, made up of letters, numbers and underscores
+fn words(text: &str) -> impl Iterator<Item = &str> {
+    text.split(|c: char| !(c.is_alphanumeric() || c == '_'))
+        .filter(|w| !w.is_empty())
+}
@@ -0,0 +1,235 @@
+/// diff.rs - computes minimal differences between two texts, for formatters, reloads and gutters
+use std::ops::Range;
+
+/// A region where two sequences differ, where the lines in `old` were replaced by those in `new`.
+/// Either range may be empty, for pure insertions and deletions.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Hunk {
+    pub old: Range<usize>,
+    pub new: Range<usize>,
+}
+
+impl Hunk {
+    /// Returns true if this hunk only inserts lines
+    #[must_use]
+    pub fn is_insertion(&self) -> bool {
+        self.old.is_empty()
+    }
+
+    /// Returns true if this hunk only deletes lines
+    #[must_use]
+    pub fn is_deletion(&self) -> bool {
+        self.new.is_empty()
+    }
+}
+
+/// The state of a line compared to a baseline, for rendering version control gutters
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum LineState {
+    #[default]
+    Unchanged,
+    Added,
+    Modified,
+}
+
+/// Find the differences between two sequences using the Myers algorithm.
+/// This uses its linear space variation, which finds the middle of the shortest edit path and
+/// then works on each half, so memory doesn't grow with the number of changes.
+/// The hunks are in order and never overlap, everything outside of them is unchanged.
+#[must_use]
+pub fn diff<T: PartialEq>(old: &[T], new: &[T]) -> Vec<Hunk> {
+    let mut deleted = vec![false; old.len()];
+    let mut inserted = vec![false; new.len()];
+    // Parts of the sequences that are still to be compared
+    let mut parts = vec![(0..old.len(), 0..new.len())];
+    while let Some((mut olds, mut news)) = parts.pop() {
+        // Skip the lines that are the same at the start and end
+        while !olds.is_empty() && !news.is_empty() && old[olds.start] == new[news.start] {
+            olds.start += 1;
+            news.start += 1;
+        }
+        while !olds.is_empty() && !news.is_empty() && old[olds.end - 1] == new[news.end - 1] {
+            olds.end -= 1;
+            news.end -= 1;
+        }
+        let split = if olds.is_empty() || news.is_empty() {
+            None
+        } else {
+            middle(&old[olds.clone()], &new[news.clone()])
+        };
+        if let Some((x, y)) = split {
+            parts.push((olds.start + x..olds.end, news.start + y..news.end));
+            parts.push((olds.start..olds.start + x, news.start..news.start + y));
+        } else {
+            deleted[olds].fill(true);
+            inserted[news].fill(true);
+        }
+    }
+    // Group the changes into hunks
+    let (old_len, new_len) = (old.len(), new.len());
+    let mut hunks = vec![];
+    let (mut x, mut y) = (0, 0);
+    while x < old_len || y < new_len {
+        if x < old_len && y < new_len && !deleted[x] && !inserted[y] {
+            x += 1;
+            y += 1;
+            continue;
+        }
+        let (start_x, start_y) = (x, y);
+        while x < old_len && deleted[x] {
+            x += 1;
+        }
+        while y < new_len && inserted[y] {
+            y += 1;
+        }
+        hunks.push(Hunk { old: start_x..x, new: start_y..y });
+    }
+    hunks
+}
+
+/// Find a point on the shortest edit path between two sequences, about half way along it, by
+/// searching forwards from the start and backwards from the end until the searches meet.
+/// Returns `None` if the point is at either end, as it wouldn't split the sequences up.
+#[allow(clippy::cast_possible_wrap, clippy::cast_sign_loss)]
+fn middle<T: PartialEq>(old: &[T], new: &[T]) -> Option<(usize, usize)> {
+    let (old_len, new_len) = (old.len() as isize, new.len() as isize);
+    let max = (old_len + new_len + 1) / 2;
+    // Diagonal k is stored at index k + max, holding the furthest x reached along it (-1 if it
+    // hasn't been reached), counting from the end of the sequences for the backward search
+    let mut forward = vec![-1; 2 * max as usize + 2];
+    forward[max as usize + 1] = 0;
+    let mut backward = forward.clone();
+    let delta = old_len - new_len;
+    let odd = delta % 2 != 0;
+    // Diagonals to skip at either side, once they have run off the edge of the sequences
+    let (mut forward_start, mut forward_end, mut backward_start, mut backward_end) = (0, 0, 0, 0);
+    let same_from_end = |x: isize, y: isize| {
+        old[(old_len - x - 1) as usize] == new[(new_len - y - 1) as usize]
+    };
+    let found = |x: isize, y: isize| {
+        (x + y > 0 && x + y < old_len + new_len).then_some((x as usize, y as usize))
+    };
+    for d in 0..max {
+        for k in (-d + forward_start..=d - forward_end).step_by(2) {
+            let at = (k + max) as usize;
+            let mut x = if k == -d || (k != d && forward[at - 1] < forward[at + 1]) {
+                forward[at + 1]
+            } else {
+                forward[at - 1] + 1
+            };
+            let mut y = x - k;
+            while x < old_len && y < new_len && old[x as usize] == new[y as usize] {
+                x += 1;
+                y += 1;
+            }
+            forward[at] = x;
+            if x > old_len {
+                forward_end += 2;
+            } else if y > new_len {
+                forward_start += 2;
+            } else if odd {
+                let other = delta - k + max;
+                let reached = (0..2 * max).contains(&other) && backward[other as usize] != -1;
+                if reached && x >= old_len - backward[other as usize] {
+                    return found(x, y);
+                }
+            }
+        }
+        for k in (-d + backward_start..=d - backward_end).step_by(2) {
+            let at = (k + max) as usize;
+            let mut x = if k == -d || (k != d && backward[at - 1] < backward[at + 1]) {
+                backward[at + 1]
+            } else {
+                backward[at - 1] + 1
+            };
+            let mut y = x - k;
+            while x < old_len && y < new_len && same_from_end(x, y) {
+                x += 1;
+                y += 1;
+            }
+            backward[at] = x;
+            if x > old_len {
+                backward_end += 2;
+            } else if y > new_len {
+                backward_start += 2;
+            } else if !odd {
+                let other = delta - k + max;
+                if (0..2 * max).contains(&other) && forward[other as usize] != -1 {
+                    let x1 = forward[other as usize];
+                    if x1 >= old_len - x {
+                        return found(x1, x1 - (delta - k));
+                    }
+                }
+            }
+        }
+    }
+    None
+}
+
+/// Find the differences between the lines of two texts
+#[must_use]
+pub fn diff_lines(old: &str, new: &str) -> Vec<Hunk> {
+    let old: Vec<&str> = old.lines().collect();
+    let new: Vec<&str> = new.lines().collect();
+    diff(&old, &new)
+}
+
+/// Work out where a line in the old sequence ends up in the new one.
+/// Lines within a changed hunk are mapped onto its replacement, as closely as possible.
+#[must_use]
+pub fn map_line(hunks: &[Hunk], y: usize) -> usize {
+    let mut at = y;
+    for hunk in hunks {
+        if y < hunk.old.start {
+            break;
+        } else if y < hunk.old.end {
+            return hunk.new.start + (y - hunk.old.start).min(hunk.new.len().saturating_sub(1));
+        }
+        at = y - hunk.old.end + hunk.new.end;
+    }
+    at
+}
+
+/// Work out the state of each line in the new sequence from the hunks between it and the old one.
+/// Lines that replace old ones are modified, and any extra lines in a hunk are added.
+#[must_use]
+pub fn line_states(hunks: &[Hunk], len: usize) -> Vec<LineState> {
+    let mut states = vec![LineState::Unchanged; len];
+    for hunk in hunks {
+        for (i, y) in hunk.new.clone().enumerate() {
+            states[y] = if i < hunk.old.len() { LineState::Modified } else { LineState::Added };
+        }
+    }
+    states
+}
+
+/// Pair up the lines of two sequences for displaying them side by side.
+/// Unchanged lines are paired with each other, and changed lines are paired up for as long as
+/// both sides have lines left in the hunk, with `None` filling in the gaps on the shorter side.
+#[must_use]
+pub fn align_lines(
+    hunks: &[Hunk],
+    old_len: usize,
+    new_len: usize,
+) -> Vec<(Option<usize>, Option<usize>)> {
+    let mut rows = vec![];
+    let (mut x, mut y) = (0, 0);
+    // An empty hunk at the end picks up the unchanged lines after the last change
+    let end = Hunk { old: old_len..old_len, new: new_len..new_len };
+    for hunk in hunks.iter().chain(std::iter::once(&end)) {
+        // Unchanged lines before this hunk
+        while x < hunk.old.start {
+            rows.push((Some(x), Some(y)));
+            x += 1;
+            y += 1;
+        }
+        // Changed lines within this hunk
+        for i in 0..hunk.old.len().max(hunk.new.len()) {
+            let left = (i < hunk.old.len()).then_some(hunk.old.start + i);
+            let right = (i < hunk.new.len()).then_some(hunk.new.start + i);
+            rows.push((left, right));
+        }
+        (x, y) = (hunk.old.end, hunk.new.end);
+    }
+    rows
+}
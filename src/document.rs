@@ -1,22 +1,131 @@
 /// document.rs - has Document, for opening, editing and saving documents
-use crate::event::{Error, Event, Result, Status, EventMgmt};
+use crate::anchors::{AnchorId, Anchors, LineShift};
+use crate::completion::WordIndex;
+use crate::diff::{Hunk, LineState, diff, line_states, map_line};
+use crate::event::{Direction, Error, Event, GroupingPolicy, Result, Status, EventMgmt};
+use crate::listing::{DirEntry, read_dir};
 use crate::map::{CharMap, form_map};
+use crate::regex;
 use crate::searching::{Searcher, Match};
-use crate::utils::{Loc, Size, get_range, trim, width, tab_boundaries_backward, tab_boundaries_forward};
+use crate::syntax::Syntax;
+use crate::tags::{outline, Symbol};
+use crate::utils::{
+    Align, Loc, Size, Span, StyledSpan, filetype, get_range, grapheme_len, graphemes, is_boundary, nfc, pad_to,
+    parse_location, reflow, tab_stop, trim, width, tab_boundaries_backward, tab_boundaries_forward,
+};
+use regex::Regex;
 use ropey::Rope;
-use std::fs::File;
-use std::io::{BufReader, BufWriter};
-use std::ops::RangeBounds;
+use std::collections::{BTreeSet, HashMap, VecDeque};
+use std::fs::{File, OpenOptions};
+use std::io::{BufReader, BufWriter, ErrorKind, Read, Write};
+use std::ops::{Range, RangeBounds};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// Counts the untitled documents that have been created, so each gets its own name
+static UNTITLED: AtomicUsize = AtomicUsize::new(0);
+
+/// The number of search patterns that are remembered in the search history
+const SEARCH_HISTORY_LIMIT: usize = 100;
+
+/// Information about a document and the cursor within it, for rendering status lines
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct StatusInfo {
+    /// The name of the file without its directory, if the document has one
+    pub file_name: Option<String>,
+    /// The extension of the file, if it has one
+    pub extension: Option<String>,
+    /// The type of file, determined from the extension
+    pub file_type: Option<String>,
+    /// Whether the document has been modified since it was last saved
+    pub modified: bool,
+    /// Whether the document can be edited
+    pub read_only: bool,
+    /// The line the cursor is on, starting from 1
+    pub line: usize,
+    /// The number of lines in the document
+    pub total_lines: usize,
+    /// The character the cursor is on within the line, starting from 1
+    pub column: usize,
+}
+
+impl StatusInfo {
+    /// Fill in a status line template. These placeholders are replaced:
+    /// - `{file}` with the file name, or `[No Name]`
+    /// - `{type}` with the file type, or `Unknown`
+    /// - `{modified}` with `[+]` if the document has been modified, or nothing
+    /// - `{line}`, `{total}` and `{column}` with the cursor position and number of lines
+    #[must_use]
+    pub fn format(&self, template: &str) -> String {
+        template
+            .replace("{file}", self.file_name.as_deref().unwrap_or("[No Name]"))
+            .replace("{type}", self.file_type.as_deref().unwrap_or("Unknown"))
+            .replace("{modified}", if self.modified { "[+]" } else { "" })
+            .replace("{line}", &self.line.to_string())
+            .replace("{total}", &self.total_lines.to_string())
+            .replace("{column}", &self.column.to_string())
+    }
+}
+
+/// What an event would do to a document, worked out without changing it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChangePreview {
+    /// The lines that would be changed, as they are numbered now
+    pub before: Range<usize>,
+    /// The lines they would become, as they would be numbered afterwards
+    pub after: Range<usize>,
+    /// The contents of the lines afterwards
+    pub lines: Vec<String>,
+    /// Where the cursor would end up, in character indices
+    pub cursor: Loc,
+    /// The status the event would give
+    pub status: Status,
+}
+
+/// How many matches a replacement went through, for reporting to the user
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct ReplaceCount {
+    /// The number of matches that were replaced
+    pub replaced: usize,
+    /// The number of matches that were turned down
+    pub skipped: usize,
+}
+
+/// A mark shown in the sign column of the gutter, such as a breakpoint, an error or a bookmark
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Sign {
+    /// What the sign is for (e.g. `breakpoint`), a line has at most one sign with each name
+    pub name: String,
+    /// The character shown in the gutter, which should be a single column wide
+    pub symbol: char,
+    /// When a line has several signs, the one with the highest priority is shown
+    pub priority: usize,
+}
+
+impl Sign {
+    /// Create a new sign
+    #[must_use]
+    pub fn new(name: &str, symbol: char, priority: usize) -> Self {
+        Self { name: name.to_string(), symbol, priority }
+    }
+}
 
 /// A document struct manages a file.
 /// It has tools to read, write and traverse a document.
 /// By default, it uses file buffering so it can open almost immediately.
 /// To start executing events, remember to use the `Document::exe` function and check out
 /// the documentation for `Event` to learn how to form editing events.
-#[derive(Clone, PartialEq, Eq, Debug)]
+/// Comparing documents ignores the caches that are worked out from the text (such as the loaded
+/// lines and character maps), so documents that differ only in what has been loaded are equal.
+#[derive(Clone, Debug)]
+#[allow(clippy::struct_excessive_bools)]
 pub struct Document {
     /// The file name of the document opened
     pub file_name: Option<String>,
+    /// The absolute path of the file with symbolic links resolved, for telling whether two
+    /// file names refer to the same file
+    pub canonical_path: Option<PathBuf>,
     /// The rope of the document to facilitate reading and writing to disk
     pub file: Rope,
     /// Contains the number of lines buffered into the document
@@ -43,51 +152,389 @@ pub struct Document {
     pub tab_width: usize,
     /// Whether or not the document can be edited
     pub read_only: bool,
+    /// Whether the file the document was opened from can be written to, as far as could be
+    /// told when it was opened. Documents that can't be written to can still be edited, and
+    /// saving them returns `Error::PermissionDenied` (see `Document::save_or_else`).
+    pub writable: bool,
     /// Storage of the old cursor x position (to snap back to)
     pub old_cursor: usize,
     /// Flag for if the editor is currently in a redo action
     pub in_redo: bool,
+    /// Whether the document should end with a newline when written.
+    /// This is detected when the document is opened, so files without a final newline stay that way.
+    pub insert_final_newline: bool,
+    /// Whether the cursor can sit one past the last character of a line (like insert mode), or
+    /// whether it is clamped onto the last character (like vim's normal mode)
+    pub cursor_past_end: bool,
+    /// Whether inserted text types over the characters after it, rather than shifting them along
+    pub overwrite: bool,
+    /// Whether moving left and right wraps the cursor onto the previous and next lines
+    pub wrap_cursor: bool,
+    /// The minimum number of lines to scroll by when the cursor moves out of the viewport
+    pub scroll_step: usize,
+    /// The display column that lines shouldn't go past, for highlighting long lines
+    pub ruler: Option<usize>,
+    /// The number of characters a line can have before it is clamped, so that pathological
+    /// lines (such as minified code) only have their start rendered and don't stall the editor.
+    /// Lines are never clamped unless this is set (e.g. to 100,000).
+    pub max_line_length: Option<usize>,
+    /// The display width that lines in prose files are wrapped at as text is typed past it
+    /// (see `Document::set_text_width`)
+    pub text_width: Option<usize>,
+    /// Whether text is normalized (to NFC) as it is inserted, so that text that looks the same
+    /// is the same when searching and comparing
+    pub normalize: bool,
+    /// Whether normalizing changed the text that was loaded, so front ends can warn before
+    /// saving that the file will change
+    pub normalized: bool,
+    /// Display columns to draw vertical guide lines at (such as 80 and 120)
+    pub guides: Vec<usize>,
+    /// The lines that the document is compared against, such as the last version control commit
+    pub baseline: Option<Vec<String>>,
+    /// The state of each line compared to the baseline
+    pub line_states: Vec<LineState>,
+    /// Anchors that follow their lines as the document is edited (see `Anchors`)
+    pub anchors: Anchors,
+    /// The signs that have been placed, by the anchor that keeps them on their line
+    /// (see `Document::place_sign`)
+    pub signs: HashMap<AnchorId, Sign>,
+    /// Events waiting to be applied a few at a time (see `Document::queue_events`)
+    pub pending: VecDeque<Event>,
+    /// The number of events that have been queued since the queue was last empty, for
+    /// reporting progress
+    pub pending_total: usize,
+    /// A title to show instead of the file name, such as for scratch buffers
+    pub title: Option<String>,
+    /// Extra information for front ends to keep with the document (such as buffer-local
+    /// options or the last search pattern)
+    pub metadata: HashMap<String, String>,
+    /// The entries of the directory, if this document is a directory listing
+    pub listing: Option<Vec<DirEntry>>,
+    /// The words in the document, for completion, which is built on first use
+    pub words: Option<WordIndex>,
+    /// The text that is selected, if any, which undo puts back to how it was before each patch
+    pub selection: Option<Span>,
+    /// The patterns that have been searched for, oldest first, without duplicates
+    pub search_history: Vec<String>,
+    /// The direction of the last search, which repeated searches are relative to
+    pub search_direction: Direction,
+    /// The lines that have been edited since they were last rendered
+    render_lines: BTreeSet<usize>,
+    /// Whether the whole view needs rendering again, such as after lines are added or removed
+    render_all: bool,
+    /// The offset and size of the view when it was last rendered
+    rendered_view: Option<(Loc, Size)>,
 }
 
+impl PartialEq for Document {
+    fn eq(&self, other: &Self) -> bool {
+        // Listing every field means new ones can't be forgotten about here
+        let Self {
+            file_name,
+            canonical_path,
+            file,
+            size,
+            cursor,
+            offset,
+            char_ptr,
+            event_mgmt,
+            modified,
+            tab_width,
+            read_only,
+            writable,
+            old_cursor,
+            in_redo,
+            insert_final_newline,
+            cursor_past_end,
+            overwrite,
+            wrap_cursor,
+            scroll_step,
+            ruler,
+            max_line_length,
+            text_width,
+            normalize,
+            normalized,
+            guides,
+            baseline,
+            anchors,
+            signs,
+            pending,
+            pending_total,
+            title,
+            metadata,
+            listing,
+            selection,
+            search_history,
+            search_direction,
+            // Caches, which are worked out from the text
+            loaded_to: _,
+            lines: _,
+            dbl_map: _,
+            tab_map: _,
+            line_states: _,
+            words: _,
+            render_lines: _,
+            render_all: _,
+            rendered_view: _,
+        } = self;
+        file_name == &other.file_name
+            && canonical_path == &other.canonical_path
+            && file == &other.file
+            && size == &other.size
+            && cursor == &other.cursor
+            && offset == &other.offset
+            && char_ptr == &other.char_ptr
+            && event_mgmt == &other.event_mgmt
+            && modified == &other.modified
+            && tab_width == &other.tab_width
+            && read_only == &other.read_only
+            && writable == &other.writable
+            && old_cursor == &other.old_cursor
+            && in_redo == &other.in_redo
+            && insert_final_newline == &other.insert_final_newline
+            && cursor_past_end == &other.cursor_past_end
+            && overwrite == &other.overwrite
+            && wrap_cursor == &other.wrap_cursor
+            && scroll_step == &other.scroll_step
+            && ruler == &other.ruler
+            && max_line_length == &other.max_line_length
+            && text_width == &other.text_width
+            && normalize == &other.normalize
+            && normalized == &other.normalized
+            && guides == &other.guides
+            && baseline == &other.baseline
+            && anchors == &other.anchors
+            && signs == &other.signs
+            && pending == &other.pending
+            && pending_total == &other.pending_total
+            && title == &other.title
+            && metadata == &other.metadata
+            && listing == &other.listing
+            && selection == &other.selection
+            && search_history == &other.search_history
+            && search_direction == &other.search_direction
+    }
+}
+
+impl Eq for Document {}
+
 impl Document {
     /// Creates a new, empty document with no file name.
     #[cfg(not(tarpaulin_include))]
     pub fn new(size: Size) -> Self {
-        Self {
-            file: Rope::from_str("\n"),
-            lines: vec!["".to_string()],
-            dbl_map: CharMap::default(),
-            tab_map: CharMap::default(),
-            loaded_to: 1,
-            file_name: None,
-            cursor: Loc::default(),
-            offset: Loc::default(),
-            size,
-            char_ptr: 0,
-            event_mgmt: EventMgmt::default(),
-            modified: false,
-            tab_width: 4,
-            read_only: false,
-            old_cursor: 0,
-            in_redo: false,
-        }
+        // New documents are set up like any other, so their fields can't drift apart
+        let mut doc = Self::from_rope(size, Rope::from_str("\n"));
+        doc.load_to(1);
+        doc
+    }
+
+    /// Creates a new, empty scratch document with no file name.
+    /// Each one is given a sequential title ("untitled-1", "untitled-2", etc) to tell them apart.
+    #[must_use]
+    pub fn untitled(size: Size) -> Self {
+        let mut doc = Self::new(size);
+        let number = UNTITLED.fetch_add(1, Ordering::Relaxed) + 1;
+        doc.title = Some(format!("untitled-{number}"));
+        doc
+    }
+
+    /// Returns true if the document hasn't been given a file to save to yet
+    #[must_use]
+    pub fn is_untitled(&self) -> bool {
+        self.file_name.is_none()
     }
 
     /// Open a document from a file name.
+    /// Files that can't be written to are opened as read only, and directories are opened as a
+    /// listing of their entries (see `Document::open_dir`).
     /// # Errors
     /// Returns an error when file doesn't exist, or has incorrect permissions.
     /// Also returns an error if the rope fails to initialise due to character set issues or
     /// disk errors.
     #[cfg(not(tarpaulin_include))]
     pub fn open<S: Into<String>>(size: Size, file_name: S) -> Result<Self> {
-        let file_name = file_name.into();
-        Ok(Self {
-            file: Rope::from_reader(BufReader::new(File::open(&file_name)?))?,
+        Self::open_with_progress(size, file_name, |_, _| ())
+    }
+
+    /// Open a document from a file name, calling a callback with the number of bytes read so
+    /// far and the total size of the file as it is read in.
+    /// This lets editors show a loading bar (and handle their own events) while large files open.
+    /// # Errors
+    /// Returns an error if the file fails to open (see `Document::open`).
+    #[cfg(not(tarpaulin_include))]
+    pub fn open_with_progress<S, F>(size: Size, file_name: S, on_progress: F) -> Result<Self>
+    where
+        S: Into<String>,
+        F: FnMut(usize, usize),
+    {
+        Self::open_reading(size, file_name.into(), on_progress, &AtomicBool::new(false))
+    }
+
+    /// Open a document from a file name, giving up part way through reading it if `cancel`
+    /// is set (e.g. from another thread when the user presses escape).
+    /// Returns `None` if the open was cancelled.
+    /// # Errors
+    /// Returns an error if the file fails to open (see `Document::open`).
+    #[cfg(not(tarpaulin_include))]
+    pub fn open_cancellable<S: Into<String>>(
+        size: Size,
+        file_name: S,
+        cancel: &AtomicBool,
+    ) -> Result<Option<Self>> {
+        match Self::open_reading(size, file_name.into(), |_, _| (), cancel) {
+            Err(Error::Io(_)) if cancel.load(Ordering::Relaxed) => Ok(None),
+            result => result.map(Some),
+        }
+    }
+
+    /// Read a document in from a file, reporting progress and checking for cancellation
+    #[cfg(not(tarpaulin_include))]
+    fn open_reading<F>(
+        size: Size,
+        file_name: String,
+        on_progress: F,
+        cancel: &AtomicBool,
+    ) -> Result<Self>
+    where
+        F: FnMut(usize, usize),
+    {
+        if Path::new(&file_name).is_dir() {
+            return Self::open_dir(size, file_name);
+        }
+        let file = File::open(&file_name)?;
+        let metadata = file.metadata()?;
+        let total = usize::try_from(metadata.len()).unwrap_or(usize::MAX);
+        let reader = Progress { inner: file, read: 0, total, callback: on_progress, cancel };
+        let mut doc = Self::from_reader(size, BufReader::new(reader))?;
+        doc.writable = !metadata.permissions().readonly();
+        doc.set_file_name(file_name);
+        Ok(doc)
+    }
+
+    /// Open a document from a file name with a location on the end of it, such as
+    /// `src/main.rs:42:7` or `+42 src/main.rs`, placing the cursor there and centring it.
+    /// The location is clamped to the document, and is optional.
+    /// # Errors
+    /// Returns an error if the file fails to open (see `Document::open`).
+    #[cfg(not(tarpaulin_include))]
+    pub fn open_at(size: Size, spec: &str) -> Result<Self> {
+        let (file_name, loc) = parse_location(spec);
+        let mut doc = Self::open(size, file_name)?;
+        if let Some(loc) = loc {
+            doc.load_to(loc.y + size.h);
+            let y = loc.y.min(doc.len_lines().saturating_sub(1));
+            doc.goto(&Loc::at(loc.x, y));
+            doc.old_cursor = doc.char_ptr;
+            doc.center_cursor();
+        }
+        Ok(doc)
+    }
+
+    /// Open a directory as a read only listing, with an entry on each line.
+    /// Use `Document::entry_at` to get the details of an entry, and `Document::enter` to open it.
+    /// # Errors
+    /// Returns an error if the directory couldn't be read.
+    #[cfg(not(tarpaulin_include))]
+    pub fn open_dir<S: Into<String>>(size: Size, path: S) -> Result<Self> {
+        let mut doc = Self::new(size);
+        doc.load_listing(path.into())?;
+        Ok(doc)
+    }
+
+    /// Replace the contents of the document with a listing of a directory
+    fn load_listing(&mut self, path: String) -> Result<()> {
+        let entries = read_dir(&path)?;
+        let mut text: String = entries.iter().map(|e| e.display() + "\n").collect();
+        if text.is_empty() {
+            text.push('\n');
+        }
+        self.file = Rope::from_str(&text);
+        self.lines.clear();
+        self.dbl_map = CharMap::default();
+        self.tab_map = CharMap::default();
+        self.loaded_to = 0;
+        self.cursor = Loc::default();
+        self.offset = Loc::default();
+        self.char_ptr = 0;
+        self.old_cursor = 0;
+        // Patches keep being numbered from where they were, for the workspace's history
+        let committed = self.event_mgmt.committed;
+        self.event_mgmt = EventMgmt::default();
+        self.event_mgmt.committed = committed;
+        self.modified = false;
+        self.read_only = true;
+        self.listing = Some(entries);
+        self.set_file_name(path);
+        Ok(())
+    }
+
+    /// Returns true if this document is a directory listing
+    #[must_use]
+    pub fn is_listing(&self) -> bool {
+        self.listing.is_some()
+    }
+
+    /// Get the directory entry on a certain line, if this document is a directory listing
+    #[must_use]
+    pub fn entry_at(&self, y: usize) -> Option<&DirEntry> {
+        self.listing.as_ref()?.get(y)
+    }
+
+    /// Enter the directory entry on a certain line.
+    /// Directories replace this listing with their own, returning `None`,
+    /// and files are opened as a new document, which is returned.
+    /// # Errors
+    /// Returns an error if there is no entry on the line, or if it fails to open.
+    pub fn enter(&mut self, y: usize) -> Result<Option<Self>> {
+        let entry = self.entry_at(y).ok_or(Error::OutOfRange)?.clone();
+        let path = entry.path.to_string_lossy().to_string();
+        if entry.is_dir {
+            self.load_listing(path)?;
+            self.load_to(self.size.h);
+            Ok(None)
+        } else {
+            Ok(Some(Self::open(self.size, path)?))
+        }
+    }
+
+    /// Open a document from any reader, without touching the file system.
+    /// This is the in-memory open path, useful for platforms without a file system
+    /// such as WebAssembly. The resulting document has no file name.
+    /// # Errors
+    /// Returns an error if the rope fails to initialise due to character set issues or
+    /// read errors.
+    #[cfg(not(tarpaulin_include))]
+    pub fn from_reader<R: Read>(size: Size, reader: R) -> Result<Self> {
+        Ok(Self::from_rope(size, Rope::from_reader(reader)?))
+    }
+
+    /// Open a document from some text, without touching the file system.
+    /// Useful for tests, scratch buffers and text received over the network.
+    /// The resulting document has no file name and is unmodified.
+    #[must_use]
+    pub fn from_str(size: Size, text: &str) -> Self {
+        Self::from_rope(size, Rope::from_str(text))
+    }
+
+    /// Set up a document around the contents of a file
+    fn from_rope(size: Size, mut file: Rope) -> Self {
+        // Documents always end with a newline internally, so note if the original didn't.
+        // This also means that an empty document has a single empty line to edit, and it is
+        // saved with a final newline, like a new document.
+        let len = file.len_chars();
+        let ends_in_newline = len > 0 && file.char(len - 1) == '\n';
+        if !ends_in_newline {
+            file.insert_char(len, '\n');
+        }
+        let insert_final_newline = len == 0 || ends_in_newline;
+        Self {
+            file,
             lines: vec![],
             dbl_map: CharMap::default(),
             tab_map: CharMap::default(),
             loaded_to: 0,
-            file_name: Some(file_name),
+            file_name: None,
+            canonical_path: None,
             cursor: Loc::default(),
             offset: Loc::default(),
             size,
@@ -96,95 +543,1002 @@ impl Document {
             modified: false,
             tab_width: 4,
             read_only: false,
+            writable: true,
             old_cursor: 0,
             in_redo: false,
-        })
+            insert_final_newline,
+            cursor_past_end: true,
+            overwrite: false,
+            wrap_cursor: false,
+            scroll_step: 1,
+            ruler: None,
+            max_line_length: None,
+            text_width: None,
+            normalize: false,
+            normalized: false,
+            guides: vec![],
+            baseline: None,
+            line_states: vec![],
+            anchors: Anchors::default(),
+            signs: HashMap::default(),
+            pending: VecDeque::new(),
+            pending_total: 0,
+            title: None,
+            metadata: HashMap::default(),
+            listing: None,
+            words: None,
+            selection: None,
+            search_history: vec![],
+            search_direction: Direction::Forward,
+            render_lines: BTreeSet::new(),
+            render_all: true,
+            rendered_view: None,
+        }
+    }
+
+    /// Sets whether the cursor can sit one past the last character of a line.
+    /// The cursor is snapped back onto the line if it is currently past the end.
+    pub fn set_cursor_past_end(&mut self, cursor_past_end: bool) {
+        self.cursor_past_end = cursor_past_end;
+        self.goto_x(self.char_ptr.min(self.line_end(self.loc().y)));
+        self.old_cursor = self.char_ptr;
+    }
+
+    /// Sets a title to show instead of the file name
+    pub fn set_title(&mut self, title: Option<String>) {
+        self.title = title;
+    }
+
+    /// Get the title of the document, falling back to the file name
+    #[must_use]
+    pub fn title(&self) -> Option<String> {
+        self.title.clone().or_else(|| self.status_info().file_name)
+    }
+
+    /// Store a piece of metadata with the document, returning the previous value
+    pub fn set_meta<K, V>(&mut self, key: K, value: V) -> Option<String>
+    where
+        K: Into<String>,
+        V: Into<String>,
+    {
+        self.metadata.insert(key.into(), value.into())
+    }
+
+    /// Get a piece of metadata stored with the document
+    #[must_use]
+    pub fn meta(&self, key: &str) -> Option<&str> {
+        self.metadata.get(key).map(String::as_str)
+    }
+
+    /// Remove a piece of metadata from the document, returning its value
+    pub fn remove_meta(&mut self, key: &str) -> Option<String> {
+        self.metadata.remove(key)
+    }
+
+    /// Sets when patches are committed automatically as the document is edited
+    pub fn set_grouping(&mut self, policy: GroupingPolicy) {
+        self.event_mgmt.policy = policy;
+    }
+
+    /// Sets the longest pause between events for them to count as one burst, such as a paste,
+    /// or `None` to turn burst detection off (which is the default).
+    /// Once a burst is long enough, `Document::exe` returns `Status::ProbablyPaste` and nothing
+    /// is committed until the burst ends, so that the whole paste is undone in one step.
+    pub fn set_paste_detection(&mut self, gap: Option<Duration>) {
+        self.event_mgmt.paste_gap = gap;
+        self.event_mgmt.burst = 0;
+    }
+
+    /// Returns true while a paste (or very fast input) is coming in, so that front ends can
+    /// hold off rendering until it is over, rather than rendering after every character.
+    /// The lines that changed are still requested for rendering once it finishes.
+    #[must_use]
+    pub fn is_pasting(&self) -> bool {
+        self.event_mgmt.is_paste() && self.event_mgmt.in_burst(Instant::now())
+    }
+
+    /// Sets whether inserted text types over the characters after it (like the insert key)
+    pub fn set_overwrite(&mut self, overwrite: bool) {
+        self.overwrite = overwrite;
+    }
+
+    /// Sets whether moving left and right wraps the cursor onto the previous and next lines
+    pub fn set_wrap_cursor(&mut self, wrap_cursor: bool) {
+        self.wrap_cursor = wrap_cursor;
+    }
+
+    /// Sets the minimum number of lines to scroll by when the cursor moves out of the viewport
+    pub fn set_scroll_step(&mut self, scroll_step: usize) {
+        self.scroll_step = scroll_step;
+    }
+
+    /// Sets the display column that lines shouldn't go past
+    pub fn set_ruler(&mut self, ruler: Option<usize>) {
+        self.ruler = ruler;
+    }
+
+    /// Sets the number of characters a line can have before it is clamped (see
+    /// `Document::is_clamped`), or `None` to never clamp lines
+    pub fn set_max_line_length(&mut self, max_line_length: Option<usize>) {
+        self.max_line_length = max_line_length;
+        self.render_all = true;
+    }
+
+    /// Sets the display width that lines are wrapped at as text is typed past it, or `None` to
+    /// never wrap while typing. This only applies to prose (see `Document::is_prose`), where
+    /// the line is split at the last space that fits, as if enter had been pressed there.
+    pub fn set_text_width(&mut self, text_width: Option<usize>) {
+        self.text_width = text_width;
+    }
+
+    /// Sets whether text is normalized (to NFC) as it is inserted.
+    /// When turned on, the text that is already in the document is normalized too, through
+    /// events committed as a single patch, so it can be undone in one step, and
+    /// `Document::normalized` records whether this changed anything.
+    pub fn set_normalize(&mut self, normalize: bool) {
+        self.normalize = normalize;
+        if !normalize || self.read_only {
+            return;
+        }
+        let text = self.file.to_string();
+        if nfc(&text) == text {
+            return;
+        }
+        self.load_to(self.len_lines() + 1);
+        // Characters can't combine across lines, so each line can be normalized on its own
+        let changed: Vec<(usize, String, String)> = self.lines[..self.len_lines()]
+            .iter()
+            .enumerate()
+            .map(|(y, line)| (y, line.clone(), nfc(line)))
+            .filter(|(_, line, normal)| line != normal)
+            .collect();
+        let cursor = self.char_loc();
+        let result = self.batch(|doc| {
+            for (y, line, normal) in changed {
+                doc.apply(Event::Delete(Loc::at(0, y), line))?;
+                doc.apply(Event::Insert(Loc::at(0, y), normal))?;
+            }
+            Ok(())
+        });
+        // Characters could have been combined, so keep the cursor on its line
+        let length = self.line(cursor.y).map_or(0, |l| l.chars().count());
+        self.goto(&Loc::at(cursor.x.min(length), cursor.y));
+        self.old_cursor = self.char_ptr;
+        self.normalized = result.is_ok();
     }
 
-    /// Sets the tab display width measured in spaces, default being 4
+    /// Sets the display columns to draw vertical guide lines at
+    pub fn set_guides(&mut self, mut guides: Vec<usize>) {
+        guides.sort_unstable();
+        guides.dedup();
+        self.guides = guides;
+    }
+
+    /// Sets whether the document should end with a newline when written
+    pub fn set_insert_final_newline(&mut self, insert_final_newline: bool) {
+        self.insert_final_newline = insert_final_newline;
+    }
+
+    /// Sets the tab display width measured in spaces, default being 4.
+    /// The loaded lines are mapped again, the cursor stays on the same character (scrolling
+    /// horizontally if it needs to), and the whole view is rendered again.
     pub fn set_tab_width(&mut self, tab_width: usize) {
         self.tab_width = tab_width;
+        self.render_all = true;
+        // Tab characters may now take up a different amount of space
+        for y in 0..self.lines.len() {
+            self.remap(y);
+        }
+        // Work out where the cursor's character is displayed now
+        let x = self.char_ptr;
+        self.char_ptr = 0;
+        self.cursor.x = 0;
+        self.offset.x = 0;
+        self.goto_x(x);
     }
 
     /// Save back to the file the document was opened from.
     /// # Errors
     /// Returns an error if the file fails to write, due to permissions
     /// or character set issues.
+    /// If the file can't be written to due to permissions, `Error::PermissionDenied` is returned.
     pub fn save(&mut self) -> Result<()> {
-        if !self.read_only {
+        if self.read_only {
+            Err(Error::ReadOnlyFile)
+        } else {
+            let Some(file_name) = &self.file_name else { return Err(Error::NoFileName) };
+            self.write_to(BufWriter::new(create(file_name)?))?;
             self.modified = false;
-            if let Some(file_name) = &self.file_name {
-                self.file.write_to(BufWriter::new(File::create(file_name)?))?;
+            self.writable = true;
+            self.refresh_line_states();
+            Ok(())
+        }
+    }
+
+    /// Save back to the file the document was opened from, with an escape hatch for when
+    /// permission is denied. The document is written to a new temporary file instead (with a
+    /// unique name, so nothing already there is written through), and the fallback is given the
+    /// path of it and the file it should go to, so that it can be copied over with elevated
+    /// privileges (e.g. `sudo cp`) or the user can be told how to do so.
+    /// # Errors
+    /// Returns an error if the file fails to write for any other reason, if the temporary file
+    /// fails to write, or if the fallback fails.
+    pub fn save_or_else<F>(&mut self, fallback: F) -> Result<()>
+    where
+        F: FnOnce(&Path, &str) -> Result<()>,
+    {
+        match self.save() {
+            Err(Error::PermissionDenied) => {
+                let file_name = self.file_name.clone().ok_or(Error::NoFileName)?;
+                let name = Path::new(&file_name)
+                    .file_name()
+                    .map_or_else(|| "untitled".to_string(), |f| f.to_string_lossy().to_string());
+                let (temp, file) = create_temp(&name)?;
+                self.write_to(BufWriter::new(file))?;
+                fallback(&temp, &file_name)?;
+                self.modified = false;
+                self.refresh_line_states();
                 Ok(())
-            } else {
-                Err(Error::NoFileName)
             }
-        } else {
-            Err(Error::ReadOnlyFile)
+            result => result,
         }
     }
 
     /// Save to a specified file.
+    /// The document is renamed to the new file and marked as saved, so that future saves go to it.
     /// # Errors
     /// Returns an error if the file fails to write, due to permissions
     /// or character set issues.
-    pub fn save_as(&self, file_name: &str) -> Result<()> {
-        if !self.read_only {
-            self.file.write_to(BufWriter::new(File::create(file_name)?))?;
+    pub fn save_as(&mut self, file_name: &str) -> Result<()> {
+        if self.read_only {
+            Err(Error::ReadOnlyFile)
+        } else {
+            self.write_to(BufWriter::new(create(file_name)?))?;
+            if self.is_untitled() {
+                self.title = None;
+            }
+            self.set_file_name(file_name);
+            self.modified = false;
+            self.writable = true;
+            self.refresh_line_states();
             Ok(())
+        }
+    }
+
+    /// Save a range of lines to a file, like vim's `:'<,'>w other.txt`.
+    /// Each line keeps its own line ending, and the document isn't renamed or marked as saved.
+    /// # Errors
+    /// Returns an error if the range is out of bounds, or the file fails to write.
+    pub fn save_range(&self, lines: Range<usize>, file_name: &str) -> Result<()> {
+        if lines.start > lines.end || lines.end > self.len_lines() {
+            return Err(Error::OutOfRange);
+        }
+        let start = self.file.line_to_char(lines.start);
+        let end = self.file.line_to_char(lines.end);
+        let mut writer = BufWriter::new(create(file_name)?);
+        for chunk in self.file.slice(start..end).chunks() {
+            writer.write_all(chunk.as_bytes())?;
+        }
+        writer.flush()?;
+        Ok(())
+    }
+
+    /// Add the contents of the document onto the end of a file, creating it if it doesn't exist,
+    /// like vim's `:w >> other.txt`. The document isn't renamed or marked as saved.
+    /// # Errors
+    /// Returns an error if the file fails to open or write.
+    pub fn save_append(&self, file_name: &str) -> Result<()> {
+        let file = OpenOptions::new().append(true).create(true).open(file_name)?;
+        self.write_to(BufWriter::new(file))
+    }
+
+    /// Rename (or move) the file behind this document, and use the new name from now on.
+    /// The file type is worked out from the new name, so changing the extension changes it.
+    /// Documents that haven't been saved yet are just given the new name.
+    /// # Errors
+    /// Returns an error if the file fails to be renamed, due to permissions or the
+    /// destination being on another file system.
+    pub fn rename(&mut self, file_name: &str) -> Result<()> {
+        if let Some(old) = &self.file_name {
+            if Path::new(old).exists() {
+                std::fs::rename(old, file_name)?;
+            }
         } else {
-            Err(Error::ReadOnlyFile)
+            self.title = None;
         }
+        self.set_file_name(file_name);
+        Ok(())
+    }
+
+    /// Set the file name of the document, working out its canonical path if it exists
+    fn set_file_name<S: Into<String>>(&mut self, file_name: S) {
+        let file_name = file_name.into();
+        self.canonical_path = std::fs::canonicalize(&file_name).ok();
+        self.file_name = Some(file_name);
+    }
+
+    /// Determine if a path refers to the file behind this document, even if it is reached
+    /// through a different relative path or a symbolic link
+    #[must_use]
+    pub fn is_same_file<P: AsRef<Path>>(&self, path: P) -> bool {
+        match (&self.canonical_path, std::fs::canonicalize(path)) {
+            (Some(canonical), Ok(other)) => *canonical == other,
+            _ => false,
+        }
+    }
+
+    /// Write the contents of the document to any writer, without touching the file system.
+    /// The final newline is left out if `insert_final_newline` is off.
+    /// # Errors
+    /// Returns an error if the writer fails to write.
+    pub fn write_to<W: Write>(&self, mut writer: W) -> Result<()> {
+        let len = self.file.len_chars();
+        if self.insert_final_newline || len == 0 || self.file.char(len - 1) != '\n' {
+            self.file.write_to(writer)?;
+        } else {
+            for chunk in self.file.slice(..len - 1).chunks() {
+                writer.write_all(chunk.as_bytes())?;
+            }
+            writer.flush()?;
+        }
+        Ok(())
+    }
+
+    /// Execute an event, registering it in the undo / redo.
+    /// You should always edit a document through this method to ensure undo and redo work.
+    /// In overwrite mode, insertions replace the characters after them on the line, which is
+    /// registered as a deletion followed by the insertion.
+    /// The status returned says whether the event would usually end an undo patch, so front
+    /// ends can use `Status::suggests_commit` to decide when to commit, or leave it to the
+    /// grouping policy (see `Document::set_grouping`).
+    /// Inserted text is normalized first if normalization is on (see `Document::set_normalize`).
+    /// Deleting a single space at the end of a tab's worth of indentation deletes the whole tab.
+    /// # Errors
+    /// Will return an error if the event was unable to be completed, or would split a line in
+    /// the middle of a character (see `Document::is_boundary`).
+    /// Returns `Error::EventsPending` while queued events are still being applied (see
+    /// `Document::queue_events`), as the edit would end up in their undo patch.
+    pub fn exe(&mut self, ev: Event) -> Result<Status> {
+        if !self.pending.is_empty() {
+            return Err(Error::EventsPending);
+        }
+        let ev = match ev {
+            Event::Insert(loc, st) if self.normalize => Event::Insert(loc, nfc(&st)),
+            Event::InsertLine(y, st) if self.normalize => Event::InsertLine(y, nfc(&st)),
+            ev => self.widen_tab(ev),
+        };
+        // Lines can't be split in the middle of a character, but this is only checked here, as
+        // undoing a splice has to split the line wherever it was joined
+        if let Event::SplitDown(loc) = &ev {
+            let line = self.line(loc.y).unwrap_or_default();
+            if loc.x <= line.chars().count() && !is_boundary(&line, loc.x) {
+                return Err(Error::NotBoundary);
+            }
+        }
+        let status = match &ev {
+            Event::Insert(_, st) if st.ends_with(char::is_whitespace) => Status::WordBoundary,
+            Event::Insert(..) | Event::Delete(..) => Status::None,
+            _ => Status::StructuralChange,
+        };
+        self.event_mgmt.group_before(Instant::now());
+        let status = if self.event_mgmt.burst > 0 && self.event_mgmt.is_paste() {
+            Status::ProbablyPaste
+        } else {
+            status
+        };
+        let start = self.event_mgmt.patch.len();
+        if let (true, Event::Insert(loc, st)) = (self.overwrite, &ev) {
+            // Remove the characters that are being typed over
+            let line = self.line(loc.y).unwrap_or_default();
+            let replaced: String = line.chars().skip(loc.x).take(st.chars().count()).collect();
+            if !replaced.is_empty() {
+                self.apply(Event::Delete(*loc, replaced))?;
+            }
+        }
+        let typed = match &ev {
+            Event::Insert(loc, st) => Some(Loc::at(loc.x + st.chars().count(), loc.y)),
+            _ => None,
+        };
+        if let Err(err) = self.apply(ev) {
+            // Put back any characters that were being typed over
+            self.roll_back(start);
+            return Err(err);
+        }
+        if let (Some(end), false) = (typed, status == Status::ProbablyPaste) {
+            self.wrap_while_typing(end)?;
+        }
+        self.event_mgmt.group_after(&status);
+        Ok(status)
+    }
+
+    /// Wrap a line that text has just been typed into (up to `end`) if it now goes past the
+    /// text width, moving the words after the last space that fits onto a new line below with
+    /// the same indentation, and keeping the cursor on the text it was on
+    fn wrap_while_typing(&mut self, end: Loc) -> Result<()> {
+        let Some(max) = self.text_width.filter(|_| self.is_prose()) else {
+            return Ok(());
+        };
+        let line = self.line(end.y).unwrap_or_default();
+        let chars: Vec<char> = line.chars().collect();
+        let after_space = chars[..end.x].last().is_some_and(|c| c.is_whitespace());
+        if width(&line, self.tab_width) <= max || after_space {
+            return Ok(());
+        }
+        // Find the last run of spaces that the text before it fits in front of
+        let indent: String = chars.iter().take_while(|c| c.is_whitespace()).collect();
+        let indent_len = indent.chars().count();
+        let mut found = None;
+        let mut before = indent.clone();
+        for x in indent_len..chars.len() {
+            if width(&before, self.tab_width) > max {
+                break;
+            }
+            if chars[x].is_whitespace() && !chars[x - 1].is_whitespace() {
+                found = Some(x);
+            }
+            before.push(chars[x]);
+        }
+        // Words too long to fit are left as they are, as are spaces after the typed text
+        let Some(start) = found.filter(|start| *start > indent_len && *start < end.x) else {
+            return Ok(());
+        };
+        let spaces: String = chars[start..].iter().take_while(|c| c.is_whitespace()).collect();
+        let moved = end.x - start - spaces.chars().count();
+        self.apply(Event::Delete(Loc::at(start, end.y), spaces))?;
+        self.apply(Event::SplitDown(Loc::at(start, end.y)))?;
+        if !indent.is_empty() {
+            self.apply(Event::Insert(Loc::at(0, end.y + 1), indent))?;
+        }
+        self.goto(&Loc::at(indent_len + moved, end.y + 1));
+        Ok(())
+    }
+
+    /// Determine if the document is prose rather than code (Markdown, plain text and version
+    /// control commit messages), for features like wrapping while typing
+    #[must_use]
+    pub fn is_prose(&self) -> bool {
+        let messages = ["COMMIT_EDITMSG", "MERGE_MSG", "TAG_EDITMSG", "EDIT_DESCRIPTION"];
+        let name = self.file_name.as_deref().map(|n| Path::new(n).file_name());
+        if let Some(Some(name)) = name {
+            if messages.iter().any(|m| name == *m) {
+                return true;
+            }
+        }
+        matches!(
+            self.status_info().file_type.as_deref(),
+            Some("Markdown" | "Plain Text" | "reStructuredText" | "TeX")
+        )
+    }
+
+    /// Check that an event can be executed, without changing anything.
+    /// As well as checking that locations are within the document, the text that delete events
+    /// carry must match what is in the document, so that they can be undone correctly.
+    /// This works on lines that haven't been loaded yet too.
+    /// # Errors
+    /// Returns `Error::ReadOnlyFile` if the document can't be edited, or `Error::OutOfRange` if
+    /// the event doesn't fit the document.
+    pub fn validate(&self, ev: &Event) -> Result<()> {
+        if self.read_only {
+            return Err(Error::ReadOnlyFile);
+        }
+        let line = |y: usize| -> Result<String> {
+            if y >= self.len_lines() {
+                return Err(Error::OutOfRange);
+            }
+            let line = self.file.line(y).to_string();
+            Ok(line.trim_end_matches(['\n', '\r']).to_string())
+        };
+        let valid = match ev {
+            Event::Insert(loc, _) => loc.x <= line(loc.y)?.chars().count(),
+            Event::SplitDown(loc) => {
+                let line = line(loc.y)?;
+                if loc.x <= line.chars().count() && !is_boundary(&line, loc.x) {
+                    return Err(Error::NotBoundary);
+                }
+                loc.x <= line.chars().count()
+            }
+            Event::Delete(loc, st) => {
+                let line = line(loc.y)?;
+                line.chars().skip(loc.x).take(st.chars().count()).eq(st.chars())
+            }
+            Event::InsertLine(y, _) => *y <= self.len_lines(),
+            Event::DeleteLine(y, st) => &line(*y)? == st,
+            Event::SpliceUp(loc) => loc.y + 1 < self.len_lines(),
+        };
+        if valid {
+            Ok(())
+        } else {
+            Err(Error::OutOfRange)
+        }
+    }
+
+    /// Work out what executing an event would do, without changing the document.
+    /// This is useful for previewing edits (such as a replace all) before they are made.
+    /// # Errors
+    /// Returns an error if the event can't be executed (see `Document::validate`).
+    pub fn simulate(&self, ev: &Event) -> Result<ChangePreview> {
+        self.validate(ev)?;
+        let (before, after) = Self::changed_lines(ev);
+        // Work on a copy without anything that is costly to keep up to date
+        let mut doc = self.clone();
+        doc.words = None;
+        doc.baseline = None;
+        doc.load_to(before.end.max(after.end) + 1);
+        let status = doc.exe(ev.clone())?;
+        let lines = after
+            .clone()
+            .map(|y| doc.file.line(y).to_string().trim_end_matches(['\n', '\r']).to_string())
+            .collect();
+        Ok(ChangePreview { before, after, lines, cursor: doc.char_loc(), status })
+    }
+
+    /// Work out which lines an event changes, before and after it is executed
+    fn changed_lines(ev: &Event) -> (Range<usize>, Range<usize>) {
+        match ev {
+            Event::Insert(loc, _) | Event::Delete(loc, _) => (loc.y..loc.y + 1, loc.y..loc.y + 1),
+            Event::InsertLine(y, _) => (*y..*y, *y..y + 1),
+            Event::DeleteLine(y, _) => (*y..y + 1, *y..*y),
+            Event::SplitDown(loc) => (loc.y..loc.y + 1, loc.y..loc.y + 2),
+            Event::SpliceUp(loc) => (loc.y..loc.y + 2, loc.y..loc.y + 1),
+        }
+    }
+
+    /// Execute an event, registering it in the undo / redo, without applying overwrite mode.
+    /// This is for operations that insert text which shouldn't replace what is already there.
+    fn apply(&mut self, ev: Event) -> Result<()> {
+        if !self.read_only {
+            if self.event_mgmt.is_patch_empty() {
+                self.event_mgmt.patch_selection = self.selection;
+            }
+            // Only events that succeed are registered, so that they can be undone
+            self.forth(ev.clone())?;
+            self.event_mgmt.register(ev);
+        }
+        Ok(())
+    }
+
+    /// Queue events to be applied a few at a time with `Document::apply_pending`, such as the
+    /// thousands of edits of a large replacement, so that front ends can keep rendering (and
+    /// show progress) while they are applied.
+    /// Everything queued until the queue empties is made into a single undo patch, so
+    /// `Document::exe`, `Document::undo` and `Document::redo` are refused while events are
+    /// pending (finish them with `Document::apply_pending` or drop them with
+    /// `Document::cancel_pending` first).
+    pub fn queue_events<I: IntoIterator<Item = Event>>(&mut self, events: I) {
+        if self.pending.is_empty() {
+            self.event_mgmt.commit();
+            self.pending_total = 0;
+        }
+        let before = self.pending.len();
+        self.pending.extend(events);
+        self.pending_total += self.pending.len() - before;
+    }
+
+    /// Apply queued events until they run out or the time is up, returning true once every
+    /// queued event has been applied. Editors can call this once per frame (e.g. with a budget
+    /// of a few milliseconds). At least one event is applied each time, so that the queue
+    /// empties however small the budget.
+    /// # Errors
+    /// Returns an error if an event fails, in which case every queued event that was applied
+    /// is rolled back and the rest are dropped.
+    pub fn apply_pending(&mut self, budget: Duration) -> Result<bool> {
+        let start = Instant::now();
+        while let Some(ev) = self.pending.pop_front() {
+            if let Err(err) = self.apply(ev) {
+                self.cancel_pending();
+                return Err(err);
+            }
+            if start.elapsed() >= budget {
+                break;
+            }
+        }
+        if self.pending.is_empty() && self.pending_total > 0 {
+            self.event_mgmt.commit();
+            self.pending_total = 0;
+        }
+        Ok(self.pending.is_empty())
+    }
+
+    /// Get the progress through the queued events, as the number that have been applied and
+    /// the number that have been queued (both 0 when nothing is queued)
+    #[must_use]
+    pub fn pending_progress(&self) -> (usize, usize) {
+        (self.pending_total - self.pending.len(), self.pending_total)
+    }
+
+    /// Drop the queued events that haven't been applied yet, and roll back the ones that have,
+    /// leaving the document as it was before they were queued
+    pub fn cancel_pending(&mut self) {
+        if self.pending_total == 0 {
+            return;
+        }
+        self.pending.clear();
+        self.pending_total = 0;
+        let patch = std::mem::take(&mut self.event_mgmt.patch);
+        let _ = self.replay_events(&patch, Direction::Backward);
+    }
+
+    /// Undo the last patch in the document, leaving the cursor where the patch started and
+    /// the selection as it was before the patch.
+    /// Returns `Status::NothingToUndo` if there are no patches to undo.
+    /// # Errors
+    /// Will return an error if any of the events failed to be reversed, in which case the
+    /// document and the undo stack are left as they were, or if events are pending.
+    pub fn undo(&mut self) -> Result<Status> {
+        if !self.pending.is_empty() {
+            return Err(Error::EventsPending);
+        }
+        let Some(mut patch) = self.event_mgmt.undo() else { return Ok(Status::NothingToUndo) };
+        // The patch is given in the order to undo it, so put it back in its original order
+        patch.reverse();
+        if let Err(err) = self.replay_events(&patch, Direction::Backward) {
+            self.event_mgmt.redo();
+            return Err(err);
+        }
+        self.modified = !self.event_mgmt.is_undo_empty();
+        self.refresh_line_states();
+        self.old_cursor = self.char_ptr;
+        self.selection = self.event_mgmt.redo_stack().last().and_then(|p| p.selection);
+        Ok(Status::None)
+    }
+
+    /// Redo the last patch in the document, leaving the cursor where the patch ended with
+    /// nothing selected.
+    /// Returns `Status::NothingToRedo` if there are no patches to redo.
+    /// # Errors
+    /// Will return an error if any of the events failed to be re-executed, in which case the
+    /// document and the redo stack are left as they were, or if events are pending.
+    pub fn redo(&mut self) -> Result<Status> {
+        if !self.pending.is_empty() {
+            return Err(Error::EventsPending);
+        }
+        let Some(patch) = self.event_mgmt.redo() else { return Ok(Status::NothingToRedo) };
+        self.in_redo = true;
+        let result = self.replay_events(&patch, Direction::Forward);
+        self.in_redo = false;
+        if let Err(err) = result {
+            self.event_mgmt.undo();
+            return Err(err);
+        }
+        self.modified = true;
+        self.refresh_line_states();
+        self.old_cursor = self.char_ptr;
+        self.selection = None;
+        Ok(Status::None)
+    }
+
+    /// Run an operation a number of times as a single undo patch, for count prefixes in modal
+    /// editors (such as deleting 5 lines or moving 10 words).
+    /// Stops early if the operation hits the edge of the line or file, returning that status.
+    /// # Errors
+    /// Returns an error if the operation fails, in which case any edits it already made are
+    /// rolled back.
+    pub fn repeat<F>(&mut self, count: usize, mut op: F) -> Result<Status>
+    where
+        F: FnMut(&mut Self) -> Result<Status>,
+    {
+        self.batch(|doc| {
+            for _ in 0..count {
+                match op(doc)? {
+                    Status::None
+                    | Status::WordBoundary
+                    | Status::StructuralChange
+                    | Status::ProbablyPaste => (),
+                    status => return Ok(status),
+                }
+            }
+            Ok(Status::None)
+        })
+    }
+
+    /// Make some edits as a batch, which is committed as a single patch that can be undone in
+    /// one step, without the grouping policy splitting it up (see `EventMgmt::begin_batch`).
+    /// If the edits fail, any that were already made are rolled back.
+    fn batch<T, F>(&mut self, edits: F) -> Result<T>
+    where
+        F: FnOnce(&mut Self) -> Result<T>,
+    {
+        self.event_mgmt.begin_batch();
+        let start = self.event_mgmt.patch.len();
+        let result = edits(self);
+        if result.is_err() {
+            self.roll_back(start);
+        }
+        self.event_mgmt.end_batch();
+        result
+    }
+
+    /// Reverse the events in the current patch from an index onwards, and take them out of it
+    fn roll_back(&mut self, start: usize) {
+        let made = self.event_mgmt.patch.split_off(start);
+        let _ = self.replay_events(&made, Direction::Backward);
+    }
+
+    /// Execute an event a number of times as a single undo patch.
+    /// The event is built from the document each time, so it can depend on the cursor.
+    /// # Errors
+    /// Returns an error if an event fails, in which case the events already executed are
+    /// rolled back.
+    pub fn exe_n<F>(&mut self, count: usize, mut build: F) -> Result<Status>
+    where
+        F: FnMut(&Self) -> Event,
+    {
+        self.repeat(count, |doc| {
+            let ev = build(doc);
+            doc.exe(ev)
+        })
+    }
+
+    /// Replay a sequence of events, either executing them in order or reversing them from the
+    /// last one back. This is used for undo and redo, and can be used to apply edits from
+    /// elsewhere (such as another client editing the same file).
+    /// The events aren't registered with the event manager.
+    /// # Errors
+    /// Returns an error if an event fails, in which case the events before it are rolled back
+    /// so that the document is left as it was.
+    pub fn replay_events(&mut self, events: &[Event], direction: Direction) -> Result<()> {
+        let events: Vec<Event> = match direction {
+            Direction::Forward => events.to_vec(),
+            Direction::Backward => events.iter().rev().cloned().map(Event::reverse).collect(),
+        };
+        for (i, ev) in events.iter().enumerate() {
+            if let Err(err) = self.forth(ev.clone()) {
+                // Roll back the events that succeeded
+                for done in events[..i].iter().rev() {
+                    let _ = self.forth(done.clone().reverse());
+                }
+                return Err(err);
+            }
+        }
+        Ok(())
+    }
+
+    /// Handle an editing event, use the method `exe` for executing events.
+    /// # Errors
+    /// Returns an error if there is a problem with the specified operation.
+    pub fn forth(&mut self, ev: Event) -> Result<()> {
+        // Work out which lines are changed, to keep the word index up to date
+        let (before, after) = Self::changed_lines(&ev);
+        let removed = self.rope_lines(before.clone());
+        if let Some(words) = &mut self.words {
+            for line in &removed {
+                words.remove_line(line);
+            }
+        }
+        let shift = LineShift::of(&ev);
+        let result = match ev {
+            Event::Insert(loc, ch) => self.insert(&loc, &ch),
+            Event::Delete(loc, st) => self.delete(loc.x..=loc.x + st.chars().count(), loc.y),
+            Event::InsertLine(loc, st) => self.insert_line(loc, st),
+            Event::DeleteLine(loc, _) => self.delete_line(loc),
+            Event::SplitDown(loc) => self.split_down(&loc),
+            Event::SpliceUp(loc) => self.splice_up(loc.y),
+        };
+        // If the event failed, the lines are left as they were
+        let added = if result.is_ok() { self.rope_lines(after.clone()) } else { removed };
+        if let Some(words) = &mut self.words {
+            for line in &added {
+                words.add_line(line);
+            }
+        }
+        result?;
+        if let Some(shift) = shift {
+            self.shift_anchors(shift);
+        }
+        self.track_line_states(shift, after.clone());
+        if before.len() == after.len() {
+            self.render_lines.extend(after);
+        } else {
+            self.render_all = true;
+        }
+        Ok(())
+    }
+
+    /// Replace the prefix that has been typed before a location with a completion of it,
+    /// as a single undo patch, leaving the cursor after the completion.
+    /// # Errors
+    /// Returns an error if the location is out of range, or the prefix goes past the start
+    /// of the line.
+    pub fn insert_completion(&mut self, loc: Loc, prefix_len: usize, text: &str) -> Result<()> {
+        let start = loc.x.checked_sub(prefix_len).ok_or(Error::OutOfRange)?;
+        self.out_of_range(loc.x, loc.y)?;
+        let line = self.line(loc.y).ok_or(Error::OutOfRange)?;
+        let prefix: String = line.chars().skip(start).take(prefix_len).collect();
+        self.batch(|doc| {
+            if !prefix.is_empty() {
+                doc.apply(Event::Delete(Loc::at(start, loc.y), prefix))?;
+            }
+            doc.apply(Event::Insert(Loc::at(start, loc.y), text.to_string()))
+        })
+    }
+
+    /// Get lines from the rope, if the word index needs them
+    fn rope_lines(&self, range: Range<usize>) -> Vec<String> {
+        if self.words.is_none() {
+            return vec![];
+        }
+        range.filter_map(|y| self.file.get_line(y).map(|l| l.to_string())).collect()
+    }
+
+    /// Find the words in the document that start with a prefix, with the most common first.
+    /// The first time this is called, the whole document is read to find its words, and the
+    /// index is then kept up to date as the document is edited.
+    pub fn complete(&mut self, prefix: &str) -> Vec<String> {
+        let file = &self.file;
+        self.words
+            .get_or_insert_with(|| WordIndex::new(&file.to_string()))
+            .complete(prefix)
+    }
+
+    /// Set the text to compare this document against, to track which lines have changed.
+    /// This would usually be the last committed version of the file, e.g. from `git show`.
+    pub fn set_baseline(&mut self, text: &str) {
+        self.baseline = Some(text.lines().map(str::to_string).collect());
+        self.refresh_line_states();
+    }
+
+    /// Stop comparing this document against a baseline
+    pub fn clear_baseline(&mut self) {
+        self.baseline = None;
+        self.line_states.clear();
+    }
+
+    /// Get the state of a line compared to the baseline
+    #[must_use]
+    pub fn line_state(&self, y: usize) -> LineState {
+        self.line_states.get(y).copied().unwrap_or_default()
+    }
+
+    /// Place a sign on a line, replacing any sign with the same name already there.
+    /// Signs are anchored to their line (see `Anchors`), so they move with it as lines are
+    /// inserted and removed above it, and are removed along with it.
+    pub fn place_sign(&mut self, y: usize, sign: Sign) -> AnchorId {
+        self.remove_sign(y, &sign.name);
+        let id = self.anchors.add(y);
+        self.signs.insert(id, sign);
+        self.render_lines.insert(y);
+        id
+    }
+
+    /// Remove the signs with a name from a line, returning true if there were any.
+    /// A line can have more than one sign with a name after lines with signs are joined.
+    pub fn remove_sign(&mut self, y: usize, name: &str) -> bool {
+        let mut removed = false;
+        for id in self.anchors.at(y) {
+            if self.signs.get(&id).is_some_and(|s| s.name == name) {
+                self.signs.remove(&id);
+                self.anchors.remove(id);
+                removed = true;
+            }
+        }
+        if removed {
+            self.render_lines.insert(y);
+        }
+        removed
+    }
+
+    /// Remove every sign with a name (such as all the diagnostics, before new ones are placed)
+    pub fn clear_signs(&mut self, name: &str) {
+        for y in self.signs_named(name) {
+            self.remove_sign(y, name);
+        }
+    }
+
+    /// Get the lines that have a sign with a name on them, in order (e.g. to jump between
+    /// bookmarks)
+    #[must_use]
+    pub fn signs_named(&self, name: &str) -> Vec<usize> {
+        let mut lines: Vec<usize> = self
+            .signs
+            .iter()
+            .filter(|(_, s)| s.name == name)
+            .filter_map(|(id, _)| self.anchors.line(*id))
+            .collect();
+        lines.sort_unstable();
+        lines.dedup();
+        lines
+    }
+
+    /// Get the signs on a line, highest priority first (and newest first among equals)
+    #[must_use]
+    pub fn signs_at(&self, y: usize) -> Vec<&Sign> {
+        let mut ids: Vec<AnchorId> = self.anchors.at(y);
+        ids.retain(|id| self.signs.contains_key(id));
+        ids.sort_by_key(|id| std::cmp::Reverse((self.signs[id].priority, *id)));
+        ids.iter().map(|id| &self.signs[id]).collect()
+    }
+
+    /// Get the sign shown for each line on the screen that has one, as the line index and the
+    /// highest priority sign on it
+    #[must_use]
+    pub fn visible_signs(&self) -> Vec<(usize, &Sign)> {
+        let mut lines: Vec<usize> = self
+            .anchors
+            .within(self.offset.y..self.offset.y + self.size.h)
+            .into_iter()
+            .filter(|(_, id)| self.signs.contains_key(id))
+            .map(|(y, _)| y)
+            .collect();
+        lines.dedup();
+        lines.into_iter().filter_map(|y| Some((y, *self.signs_at(y).first()?))).collect()
+    }
+
+    /// Get the character to show in the sign column of the gutter for a line.
+    /// Placed signs take priority, otherwise lines that differ from the baseline are marked
+    /// with `+` if they were added and `~` if they were modified.
+    #[must_use]
+    pub fn sign(&self, y: usize) -> Option<char> {
+        self.signs_at(y).first().map(|s| s.symbol).or(match self.line_state(y) {
+            LineState::Added => Some('+'),
+            LineState::Modified => Some('~'),
+            LineState::Unchanged => None,
+        })
     }
 
-    /// Execute an event, registering it in the undo / redo.
-    /// You should always edit a document through this method to ensure undo and redo work.
-    /// # Errors
-    /// Will return an error if the event was unable to be completed.
-    pub fn exe(&mut self, ev: Event) -> Result<()> {
-        if !self.read_only {
-            self.event_mgmt.register(ev.clone());
-            self.forth(ev)?;
+    /// Move the anchors to follow their lines after an event has changed the number of lines,
+    /// dropping the signs that were on removed lines
+    fn shift_anchors(&mut self, shift: LineShift) {
+        if self.anchors.is_empty() {
+            return;
         }
-        Ok(())
+        self.anchors.shift(shift);
+        // Signs go with their lines, leaving only the front end's anchors for it to collect
+        let signs = &mut self.signs;
+        self.anchors.removed.retain(|id| signs.remove(id).is_none());
     }
 
-    /// Undo the last patch in the document.
-    /// # Errors
-    /// Will return an error if any of the events failed to be reversed.
-    pub fn undo(&mut self) -> Result<()> {
-        for ev in self.event_mgmt.undo().unwrap_or_default() {
-            self.forth(ev.reverse())?;
+    /// Keep the line states up to date after an event changes some lines, without comparing
+    /// the whole document against the baseline. Edited lines are marked as modified and new
+    /// lines as added, so lines that are changed back stay marked until the line states are
+    /// refreshed (see `Document::refresh_line_states`).
+    fn track_line_states(&mut self, shift: Option<LineShift>, after: Range<usize>) {
+        if self.baseline.is_none() {
+            return;
+        }
+        let states = &mut self.line_states;
+        let edited = match shift {
+            Some(LineShift::Inserted(y)) => {
+                states.insert(y.min(states.len()), LineState::Added);
+                // Lines from the inserted one down only moved
+                after.start..y
+            }
+            Some(LineShift::Removed(y) | LineShift::Joined(y)) => {
+                if y < states.len() {
+                    states.remove(y);
+                }
+                after
+            }
+            None => after,
+        };
+        for y in edited {
+            if let Some(state @ LineState::Unchanged) = states.get_mut(y) {
+                *state = LineState::Modified;
+            }
+        }
+        // Text with line breaks in it can change the number of lines without a shift
+        if self.line_states.len() != self.len_lines() {
+            self.refresh_line_states();
         }
-        self.modified = !self.event_mgmt.is_undo_empty();
-        Ok(())
     }
 
-    /// Redo the last patch in the document.
-    /// # Errors
-    /// Will return an error if any of the events failed to be re-executed.
-    pub fn redo(&mut self) -> Result<()> {
-        self.in_redo = true;
-        for ev in self.event_mgmt.redo().unwrap_or_default() {
-            self.forth(ev)?;
+    /// Compare the whole document against the baseline again (if there is one), so that lines
+    /// that were edited and then changed back are no longer marked.
+    /// This is done when the baseline is set, on undo and redo, and when the document is saved
+    /// or reloaded. It takes time proportional to the number of differences.
+    pub fn refresh_line_states(&mut self) {
+        if let Some(baseline) = &self.baseline {
+            let current = self.all_lines();
+            self.line_states = line_states(&diff(baseline, &current), current.len());
         }
-        self.modified = true;
-        self.in_redo = false;
-        Ok(())
     }
 
-    /// Handle an editing event, use the method `exe` for executing events.
-    /// # Errors
-    /// Returns an error if there is a problem with the specified operation.
-    pub fn forth(&mut self, ev: Event) -> Result<()> {
-        match ev {
-            Event::Insert(loc, ch) => self.insert(&loc, &ch),
-            Event::Delete(loc, st) => self.delete_with_tab(&loc, &st),
-            Event::InsertLine(loc, st) => self.insert_line(loc, st),
-            Event::DeleteLine(loc, _) => self.delete_line(loc),
-            Event::SplitDown(loc) => self.split_down(&loc),
-            Event::SpliceUp(loc) => self.splice_up(loc.y),
-        }
+    /// Get every line in the document, including ones that haven't been loaded yet
+    fn all_lines(&self) -> Vec<String> {
+        self.file
+            .lines()
+            .take(self.len_lines())
+            .map(|l| l.to_string().trim_end_matches(['\n', '\r']).to_string())
+            .collect()
     }
 
     /// Inserts a string into this document.
@@ -201,46 +1555,38 @@ impl Document {
         // Update cache
         let line: String = self.file.line(loc.y).chars().collect();
         self.lines[loc.y] = line.trim_end_matches(&['\n', '\r']).to_string();
-        // Update unicode map
-        let dbl_start = self.dbl_map.shift_insertion(loc, st, self.tab_width);
-        let tab_start = self.tab_map.shift_insertion(loc, st, self.tab_width);
-        // Register new double widths and tabs
-        let (mut dbls, mut tabs) = form_map(st, self.tab_width);
-        // Shift up to match insertion position in the document
-        let tab_shift = self.tab_width.saturating_sub(1) * tab_start;
-        for e in &mut dbls {
-            *e = (e.0 + loc.x + dbl_start + tab_shift, e.1 + loc.x);
-        }
-        for e in &mut tabs {
-            *e = (e.0 + loc.x + tab_shift + dbl_start, e.1 + loc.x);
-        }
-        self.dbl_map.splice(loc, dbl_start, dbls);
-        self.tab_map.splice(loc, tab_start, tabs);
+        // Update unicode and tab map
+        self.remap(loc.y);
         // Go to end x position
         self.goto_x(loc.x + st.chars().count());
         self.old_cursor = self.char_ptr;
         Ok(())
     }
 
-    /// Deletes a character at a location whilst checking for tab spaces
+    /// Deletes a character at a location whilst checking for tab spaces, registering it in
+    /// the undo / redo (see `Document::exe`)
+    /// # Errors
+    /// Returns an error if location is out of range.
     pub fn delete_with_tab(&mut self, loc: &Loc, st: &str) -> Result<()> {
+        self.exe(Event::Delete(*loc, st.to_string())).map(|_| ())
+    }
+
+    /// Widen the deletion of a single space at the end of a tab's worth of indentation into
+    /// the deletion of the whole tab, leaving other events as they are
+    fn widen_tab(&self, ev: Event) -> Event {
+        let Event::Delete(loc, st) = &ev else { return ev };
         // Check for tab spaces
         let boundaries = tab_boundaries_backward(
-            &self.line(loc.y).unwrap_or_else(|| "".to_string()), 
+            &self.line(loc.y).unwrap_or_else(|| "".to_string()),
             self.tab_width
         );
-        if boundaries.contains(&loc.x.saturating_add(1)) && !self.in_redo {
-            // Register other delete actions to delete the whole tab
-            let mut loc_copy = loc.clone();
-            self.delete(loc.x..=loc.x + st.chars().count(), loc.y)?;
-            for _ in 1..self.tab_width {
-                loc_copy.x -= 1;
-                self.exe(Event::Delete(loc_copy, " ".to_string()))?;
-            }
-            Ok(())
+        // Only a single space is widened to a whole tab, longer deletes remove exactly the text
+        // they were given
+        if st == " " && boundaries.contains(&loc.x.saturating_add(1)) {
+            let start = Loc::at(loc.x + 1 - self.tab_width, loc.y);
+            Event::Delete(start, " ".repeat(self.tab_width))
         } else {
-            // Normal character delete
-            self.delete(loc.x..=loc.x + st.chars().count(), loc.y)
+            ev
         }
     }
 
@@ -260,15 +1606,13 @@ impl Document {
         self.goto(&Loc::at(start, y));
         start += line_start;
         end += line_start;
-        let removed = self.file.slice(start..end).to_string();
-        // Update unicode and tab map
-        self.dbl_map.shift_deletion(&Loc::at(line_start, y), (start, end), &removed, self.tab_width);
-        self.tab_map.shift_deletion(&Loc::at(line_start, y), (start, end), &removed, self.tab_width);
         // Update rope
         self.file.remove(start..end);
         // Update cache
         let line: String = self.file.line(y).chars().collect();
         self.lines[y] = line.trim_end_matches(&['\n', '\r']).to_string();
+        // Update unicode and tab map
+        self.remap(y);
         self.old_cursor = self.char_ptr;
         Ok(())
     }
@@ -408,10 +1752,15 @@ impl Document {
         Status::None
     }
 
-    /// Move the cursor left
+    /// Move the cursor left, wrapping onto the end of the previous line if `wrap_cursor` is on
     pub fn move_left(&mut self) -> Status {
         // Return if already at start of line
         if self.loc().x == 0 {
+            if self.wrap_cursor && self.loc().y != 0 {
+                self.move_up();
+                self.move_end();
+                return Status::None;
+            }
             return Status::StartOfLine;
         }
         // Determine the width of the character to traverse
@@ -440,12 +1789,15 @@ impl Document {
         Status::None
     }
 
-    /// Move the cursor right
+    /// Move the cursor right, wrapping onto the start of the next line if `wrap_cursor` is on
     pub fn move_right(&mut self) -> Status {
         // Return if already on end of line
         let line = self.line(self.loc().y).unwrap_or_else(|| "".to_string());
-        let width = width(&line, self.tab_width);
-        if width == self.loc().x {
+        if self.char_ptr >= self.line_end(self.loc().y) {
+            if self.wrap_cursor && self.move_down() == Status::None {
+                self.move_home();
+                return Status::None;
+            }
             return Status::EndOfLine;
         }
         // Determine the width of the character to traverse
@@ -538,6 +1890,62 @@ impl Document {
         }
     }
 
+    /// Scroll so that the cursor is in the middle of the viewport, where possible
+    pub fn center_cursor(&mut self) {
+        let y = self.loc().y;
+        self.offset.y = y.saturating_sub(self.size.h / 2);
+        self.cursor.y = y - self.offset.y;
+        self.load_to(self.offset.y + self.size.h);
+    }
+
+    /// Move up by half a page
+    pub fn move_half_page_up(&mut self) -> Status {
+        let half = isize::try_from(self.size.h / 2).unwrap_or(isize::MAX);
+        self.move_vertical(-half.max(1))
+    }
+
+    /// Move down by half a page
+    pub fn move_half_page_down(&mut self) -> Status {
+        let half = isize::try_from(self.size.h / 2).unwrap_or(isize::MAX);
+        self.move_vertical(half.max(1))
+    }
+
+    /// Move the cursor up or down by a number of lines, where negative numbers move up.
+    /// If the cursor leaves the viewport, it is scrolled by at least `scroll_step` lines.
+    pub fn move_vertical(&mut self, n: isize) -> Status {
+        let y = self.loc().y;
+        // Return if already at the start or end of the document
+        if n < 0 && y == 0 {
+            return Status::StartOfFile;
+        } else if n > 0 && y >= self.len_lines() {
+            return Status::EndOfFile;
+        }
+        let target = y.saturating_add_signed(n).min(self.len_lines());
+        // Ensure that the lines are loaded from the buffer
+        self.load_to(target + 1);
+        // Scroll the viewport if the target is outside of it
+        let height = self.size.h.max(1);
+        let step = self.scroll_step.clamp(1, height);
+        if target < self.offset.y {
+            let by = (self.offset.y - target).max(step);
+            self.offset.y = self.offset.y.saturating_sub(by);
+        } else if target >= self.offset.y + height {
+            let by = (target + 1 - self.offset.y - height).max(step);
+            self.offset.y = (self.offset.y + by).min(target);
+        }
+        self.cursor.y = target - self.offset.y;
+        // Snap to end of line
+        self.fix_dangling_cursor();
+        // Move back if in the middle of a longer character
+        self.fix_split();
+        // Update the character pointer
+        self.update_char_ptr();
+        self.goto_x(self.old_cursor);
+        // Buffer new lines in viewport
+        self.load_to(self.offset.y + self.size.h);
+        Status::None
+    }
+
     /// Moves to the previous word in the document
     pub fn move_prev_word(&mut self) -> Status {
         let Loc { x, y } = self.char_loc();
@@ -573,79 +1981,738 @@ impl Document {
             self.goto(&mtch.loc);
         }
         self.old_cursor = self.char_ptr;
-        Status::None
+        Status::None
+    }
+
+    /// Function to search the document to find the next occurance of a regex
+    pub fn next_match(&mut self, regex: &str, inc: usize) -> Option<Match> {
+        // Prepare
+        let mut srch = Searcher::new(regex);
+        // Check current line for matches
+        let current = self.line(self.loc().y)?;
+        if self.char_ptr + inc <= current.chars().count() {
+            let current: String = current.chars().skip(self.char_ptr + inc).collect();
+            if let Some(mut mtch) = srch.lfind(&current) {
+                mtch.loc.y = self.loc().y;
+                mtch.loc.x += self.char_ptr + inc;
+                return Some(mtch)
+            }
+        }
+        // Check subsequent lines for matches
+        let mut line_no = self.loc().y + 1;
+        self.load_to(line_no + 1);
+        while line_no < self.len_lines() {
+            let line = self.line(line_no)?;
+            if let Some(mut mtch) = srch.lfind(&line) {
+                mtch.loc.y = line_no;
+                return Some(mtch);
+            }
+            line_no += 1;
+            self.load_to(line_no + 1);
+        }
+        None
+    }
+
+    /// Function to search the document to find the previous occurance of a regex
+    pub fn prev_match(&mut self, regex: &str) -> Option<Match> {
+        // Prepare
+        let mut srch = Searcher::new(regex);
+        // Check current line for matches
+        let current: String = self.line(self.loc().y)?
+            .chars()
+            .take(self.char_ptr)
+            .collect();
+        if let Some(mut mtch) = srch.rfind(&current) {
+            mtch.loc.y = self.loc().y;
+            return Some(mtch);
+        }
+        // Check antecedent lines for matches
+        self.load_to(self.loc().y + 1);
+        if self.loc().y == 0 {
+            return None;
+        }
+        let mut line_no = self.loc().y - 1;
+        while let Some(line) = self.line(line_no) {
+            if let Some(mut mtch) = srch.rfind(&line) {
+                mtch.loc.y = line_no;
+                return Some(mtch);
+            }
+            if line_no == 0 { break; }
+            line_no = line_no.saturating_sub(1);
+        }
+        None
+    }
+
+    /// Find the first match of a regex at or after a location, where the match can run over
+    /// several lines (e.g. `foo\nbar`, or `(?s)begin.*?end`), unlike `Document::next_match`.
+    /// Lines are joined with `\n` and loaded as the search needs them, starting with as many as
+    /// the pattern has newlines in it and adding more while there is no match, or while the
+    /// match runs up to the end of the lines so far and could carry on past it.
+    /// # Errors
+    /// Returns an error if the regex isn't valid.
+    pub fn find_across_lines(&mut self, regex: &str, from: Loc) -> Result<Option<Match>> {
+        let re = Regex::new(regex)?;
+        let rows = regex.matches("\\n").count() + regex.matches('\n').count() + 1;
+        Ok(self.find_across(&re, rows, from))
+    }
+
+    /// Find the first match of a compiled regex at or after a location, starting with `rows`
+    /// lines joined together (see `Document::find_across_lines`)
+    fn find_across(&mut self, re: &Regex, mut rows: usize, from: Loc) -> Option<Match> {
+        if from.y >= self.len_lines() {
+            return None;
+        }
+        loop {
+            let end = (from.y + rows).min(self.len_lines());
+            self.load_to(end);
+            let mut text = String::new();
+            for y in from.y..end {
+                text.push_str(&self.line(y)?);
+                if y + 1 < self.len_lines() {
+                    text.push('\n');
+                }
+            }
+            let start = text.char_indices().nth(from.x).map_or(text.len(), |(i, _)| i);
+            let found = re.find_at(&text, start);
+            let finished = end == self.len_lines();
+            match found {
+                Some(m) if m.end() < text.len() || finished => {
+                    let before = &text[..m.start()];
+                    let y = from.y + before.matches('\n').count();
+                    let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+                    let x = before[line_start..].chars().count();
+                    return Some(Match { loc: Loc::at(x, y), text: m.as_str().to_string() });
+                }
+                None if finished => return None,
+                _ => rows *= 2,
+            }
+        }
+    }
+
+    /// Search for a regex from the cursor in a direction, carrying on from the other end of the
+    /// document if there are no more matches that way (like vim's `wrapscan`).
+    /// The status is `Status::WrappedAround` if the match was only found by wrapping around
+    /// (so front ends can say "search hit BOTTOM, continuing at TOP"), or `Status::NoMatches`
+    /// if there are no matches in the whole document.
+    pub fn find_wrapping(&mut self, regex: &str, direction: Direction) -> (Option<Match>, Status) {
+        let found = match direction {
+            Direction::Forward => self.next_match(regex, 1),
+            Direction::Backward => self.prev_match(regex),
+        };
+        if found.is_some() {
+            return (found, Status::None);
+        }
+        // Start again from the other end of the document
+        self.load_to(self.len_lines());
+        let mut srch = Searcher::new(regex);
+        let lines: Box<dyn Iterator<Item = usize>> = match direction {
+            Direction::Forward => Box::new(0..self.len_lines()),
+            Direction::Backward => Box::new((0..self.len_lines()).rev()),
+        };
+        for y in lines {
+            let line = self.line(y).unwrap_or_default();
+            let found = match direction {
+                Direction::Forward => srch.lfind(&line),
+                Direction::Backward => srch.rfind(&line),
+            };
+            if let Some(mut mtch) = found {
+                mtch.loc.y = y;
+                return (Some(mtch), Status::WrappedAround);
+            }
+        }
+        (None, Status::NoMatches)
+    }
+
+    /// Search for a regex from the cursor in a direction, wrapping around the document (see
+    /// `Document::find_wrapping`), and remember it for repeating and the search history
+    pub fn search(&mut self, regex: &str, direction: Direction) -> (Option<Match>, Status) {
+        self.search_history.retain(|pattern| pattern != regex);
+        self.search_history.push(regex.to_string());
+        if self.search_history.len() > SEARCH_HISTORY_LIMIT {
+            self.search_history.remove(0);
+        }
+        self.search_direction = direction;
+        self.find_wrapping(regex, direction)
+    }
+
+    /// Search for the last pattern again, like vim's `n` and `N`.
+    /// The direction is relative to the last search, so `Direction::Backward` searches the
+    /// opposite way to it. Returns `Status::NoMatches` if nothing has been searched for yet.
+    pub fn repeat_search(&mut self, direction: Direction) -> (Option<Match>, Status) {
+        let Some(regex) = self.search_history.last().cloned() else {
+            return (None, Status::NoMatches);
+        };
+        let direction = if direction == self.search_direction {
+            Direction::Forward
+        } else {
+            Direction::Backward
+        };
+        self.find_wrapping(&regex, direction)
+    }
+
+    /// Get a pattern from the search history, for scrolling through it in a search prompt.
+    /// 0 is the last pattern that was searched for, 1 is the one before it, and so on.
+    #[must_use]
+    pub fn search_history_at(&self, back: usize) -> Option<&str> {
+        let idx = self.search_history.len().checked_sub(back + 1)?;
+        self.search_history.get(idx).map(String::as_str)
+    }
+
+    /// Replace a specific part of the document with another string, as a single patch.
+    /// # Errors
+    /// Will error if the replacement failed to be executed, in which case it is rolled back.
+    pub fn replace(&mut self, loc: Loc, target: &str, into: &str) -> Result<()> {
+        self.batch(|doc| {
+            doc.apply(Event::Delete(loc, target.to_string()))?;
+            doc.apply(Event::Insert(loc, into.to_string()))
+        })
+    }
+
+    /// Replace all instances of a regex with another string.
+    /// The replacement text can refer to capture groups (e.g. `$1` or `${name}`).
+    /// # Errors
+    /// Will error if the regex isn't valid, or if a replacement failed to be executed, in which
+    /// case the replacements made so far are rolled back.
+    pub fn replace_all(&mut self, target: &str, into: &str) -> Result<ReplaceCount> {
+        self.replace_all_with(target, into, |_, _| true)
+    }
+
+    /// Replace instances of a regex with another string, asking `confirm` about each match (in
+    /// order, given the match and what it would be replaced with) to decide whether to replace it.
+    /// The replacement text can refer to capture groups (e.g. `$1` or `${name}`).
+    /// The accepted replacements are made as a single undo patch.
+    /// # Errors
+    /// Will error if the regex isn't valid, or if a replacement failed to be executed, in which
+    /// case the replacements made so far are rolled back.
+    pub fn replace_all_with<F>(
+        &mut self,
+        target: &str,
+        into: &str,
+        confirm: F,
+    ) -> Result<ReplaceCount>
+    where
+        F: FnMut(&Match, &str) -> bool,
+    {
+        let (accepted, count) = self.replacements(target, into, confirm)?;
+        // Work backwards so that earlier matches aren't moved by later replacements
+        self.batch(|doc| {
+            for (mtch, new) in accepted.into_iter().rev() {
+                if !mtch.text.is_empty() {
+                    doc.apply(Event::Delete(mtch.loc, mtch.text))?;
+                }
+                if !new.is_empty() {
+                    doc.apply(Event::Insert(mtch.loc, new))?;
+                }
+            }
+            Ok(count)
+        })
+    }
+
+    /// Queue the replacement of every instance of a regex with another string, to be made a
+    /// few at a time with `Document::apply_pending`, so that replacing across huge documents
+    /// doesn't stop the editor from rendering.
+    /// Returns the number of matches that will be replaced.
+    /// # Errors
+    /// Returns an error if the regex isn't valid.
+    pub fn queue_replace_all(&mut self, target: &str, into: &str) -> Result<usize> {
+        let (accepted, count) = self.replacements(target, into, |_, _| true)?;
+        // Work backwards so that earlier matches aren't moved by later replacements
+        let mut events = vec![];
+        for (mtch, new) in accepted.into_iter().rev() {
+            if !mtch.text.is_empty() {
+                events.push(Event::Delete(mtch.loc, mtch.text));
+            }
+            if !new.is_empty() {
+                events.push(Event::Insert(mtch.loc, new));
+            }
+        }
+        self.queue_events(events);
+        Ok(count.replaced)
+    }
+
+    /// Find the instances of a regex to replace, in order, with the text that would replace
+    /// them, asking `confirm` about each one (see `Document::replace_all_with`)
+    fn replacements<F>(
+        &mut self,
+        target: &str,
+        into: &str,
+        mut confirm: F,
+    ) -> Result<(Vec<(Match, String)>, ReplaceCount)>
+    where
+        F: FnMut(&Match, &str) -> bool,
+    {
+        let re = Regex::new(target)?;
+        self.load_to(self.len_lines());
+        let mut count = ReplaceCount::default();
+        let mut accepted = vec![];
+        for y in 0..self.len_lines() {
+            let line = self.line(y).unwrap_or_default();
+            for cap in re.captures_iter(&line) {
+                let Some(m) = cap.get(0) else { continue };
+                let mut new = String::new();
+                cap.expand(into, &mut new);
+                let mtch = Match {
+                    loc: Loc::at(Searcher::raw_to_char(m.start(), &line), y),
+                    text: m.as_str().to_string(),
+                };
+                if confirm(&mtch, &new) {
+                    count.replaced += 1;
+                    accepted.push((mtch, new));
+                } else {
+                    count.skipped += 1;
+                }
+            }
+        }
+        Ok((accepted, count))
+    }
+
+    /// Replace all instances of a regex with another string, stopping part way through if
+    /// `cancel` is set (e.g. from another thread when the user presses escape).
+    /// Returns false if it was cancelled, in which case the replacements made so far are kept.
+    /// # Errors
+    /// Returns an error if the regex isn't valid, or if a replacement failed to be executed.
+    pub fn replace_all_cancellable(
+        &mut self,
+        target: &str,
+        into: &str,
+        cancel: &AtomicBool,
+    ) -> Result<bool> {
+        Regex::new(target)?;
+        self.goto(&Loc::at(0, 0));
+        // Step over empty matches, so they aren't found again
+        let mut inc = 0;
+        while let Some(mtch) = self.next_match(target, inc) {
+            if cancel.load(Ordering::Relaxed) {
+                return Ok(false);
+            }
+            self.replace(mtch.loc, &mtch.text, into)?;
+            // Continue after the replacement, so that it isn't searched within
+            let mut end = mtch.loc;
+            if let Some((before, last)) = into.rsplit_once('\n') {
+                end.y += before.matches('\n').count() + 1;
+                end.x = last.chars().count();
+            } else {
+                end.x += into.chars().count();
+            }
+            self.goto(&end);
+            inc = usize::from(mtch.text.is_empty());
+        }
+        Ok(true)
+    }
+
+    /// Find every match of a regex in the document, in order.
+    /// Setting `cancel` stops the search part way through, in which case `None` is returned.
+    /// # Errors
+    /// Returns an error if the regex isn't valid.
+    pub fn search_all(&self, regex: &str, cancel: &AtomicBool) -> Result<Option<Vec<Match>>> {
+        let re = Regex::new(regex)?;
+        let mut result = vec![];
+        for (y, line) in self.file.lines().take(self.len_lines()).enumerate() {
+            if cancel.load(Ordering::Relaxed) {
+                return Ok(None);
+            }
+            let line = line.to_string();
+            let line = line.trim_end_matches(['\n', '\r']);
+            result.extend(re.find_iter(line).map(|m| Match {
+                loc: Loc::at(Searcher::raw_to_char(m.start(), line), y),
+                text: m.as_str().to_string(),
+            }));
+        }
+        Ok(Some(result))
+    }
+
+    /// Add to the number under (or after) a location on its line, like vim's Ctrl+A and Ctrl+X.
+    /// Supports decimal numbers (including negative ones) and hexadecimal numbers (0x prefixed),
+    /// keeping any zero padding and hexadecimal letter case intact.
+    /// The cursor is left on the last digit of the new number.
+    /// Returns false if there was no number to change.
+    /// # Errors
+    /// Will error if the location is out of range, the number doesn't fit in 64 bits (before or
+    /// after the change) or the edit failed to be executed.
+    pub fn increment_number_at(&mut self, loc: &Loc, delta: i64) -> Result<bool> {
+        self.out_of_range(loc.x, loc.y)?;
+        let line = self.line(loc.y).ok_or(Error::OutOfRange)?;
+        let re = regex!(r"0[xX][0-9a-fA-F]+|-?[0-9]+");
+        // Find the first number that ends after the location
+        let Some((start, old)) = re.find_iter(&line)
+            .map(|m| (line[..m.start()].chars().count(), m.as_str()))
+            .find(|(start, old)| start + old.chars().count() > loc.x) else {
+            return Ok(false);
+        };
+        let new = if let Some(hex) = old.get(2..).filter(|_| old[..2].eq_ignore_ascii_case("0x")) {
+            // Hexadecimal numbers are unsigned, so they can't go below zero
+            let value = u64::from_str_radix(hex, 16)
+                .ok()
+                .and_then(|v| v.checked_add_signed(delta))
+                .ok_or(Error::OutOfRange)?;
+            let width = hex.len();
+            let digits = if hex.chars().any(|c| c.is_ascii_uppercase()) {
+                format!("{value:0width$X}")
+            } else {
+                format!("{value:0width$x}")
+            };
+            format!("{}{digits}", &old[..2])
+        } else {
+            // Decimal numbers keep their zero padding
+            let value = old.parse::<i64>()
+                .ok()
+                .and_then(|v| v.checked_add(delta))
+                .ok_or(Error::OutOfRange)?;
+            let digits = old.trim_start_matches('-');
+            let width = if digits.len() > 1 && digits.starts_with('0') { digits.len() } else { 0 };
+            let sign = if value < 0 { "-" } else { "" };
+            format!("{sign}{:0width$}", value.unsigned_abs())
+        };
+        // Apply the change as a single patch
+        self.replace(Loc::at(start, loc.y), old, &new)?;
+        self.goto_x(start + new.chars().count().saturating_sub(1));
+        self.old_cursor = self.char_ptr;
+        Ok(true)
+    }
+
+    /// Align the first occurance of a delimiter (such as `=` or `,`) on each line within a range,
+    /// by padding the text before it with spaces. Lines without the delimiter are left alone.
+    /// The alignment is committed as a single patch, so it can be undone in one step.
+    /// # Errors
+    /// Will error if the range is out of bounds or the padding failed to be inserted.
+    pub fn align_on(&mut self, lines: Range<usize>, delimiter: &str) -> Result<()> {
+        if delimiter.is_empty() {
+            return Ok(());
+        }
+        self.load_to(lines.end);
+        if lines.end > self.len_lines() {
+            return Err(Error::OutOfRange);
+        }
+        // Find the delimiter on each line, recording its character index and display column
+        let mut targets = vec![];
+        for y in lines {
+            let line = self.line(y).ok_or(Error::OutOfRange)?;
+            if let Some(raw) = line.find(delimiter) {
+                let before = &line[..raw];
+                targets.push((y, before.chars().count(), width(before, self.tab_width)));
+            }
+        }
+        // Pad each line to match the furthest delimiter
+        let column = targets.iter().map(|t| t.2).max().unwrap_or(0);
+        self.batch(|doc| {
+            for (y, x, at) in targets {
+                if at < column {
+                    doc.apply(Event::Insert(Loc::at(x, y), " ".repeat(column - at)))?;
+                }
+            }
+            Ok(())
+        })
+    }
+
+    /// Re-wrap the paragraphs within a range of lines so that they fit within a display width,
+    /// keeping comment prefixes like `// ` or `# ` intact (see `utils::reflow`).
+    /// The reflow is committed as a single patch, so it can be undone in one step.
+    /// # Errors
+    /// Will error if the range is out of bounds or the lines failed to be replaced.
+    pub fn reflow(&mut self, lines: Range<usize>, max: usize) -> Result<()> {
+        self.load_to(lines.end);
+        if lines.end > self.len_lines() || lines.start > lines.end {
+            return Err(Error::OutOfRange);
+        }
+        let before = self.lines[lines.clone()].to_vec();
+        let after = reflow(&before, max, self.tab_width);
+        if before == after {
+            return Ok(());
+        }
+        // Replace the old lines with the new ones
+        self.batch(|doc| {
+            for (y, line) in before.into_iter().enumerate().rev() {
+                doc.exe(Event::DeleteLine(lines.start + y, line))?;
+            }
+            for (y, line) in after.into_iter().enumerate() {
+                doc.exe(Event::InsertLine(lines.start + y, line))?;
+            }
+            Ok(())
+        })
+    }
+
+    /// Paste text into the document at a location (such as from the clipboard, or a bracketed
+    /// paste from the terminal) as a single undo patch, leaving the cursor after it.
+    /// The text can span several lines, ending in `\n`, `\r\n` or `\r`. They are split the way
+    /// pressing enter splits them, so no carriage returns from the clipboard end up in the
+    /// document.
+    /// If `reindent` is true, the lines after the first are given the indentation of the line
+    /// being pasted into, keeping any indentation they have relative to each other.
+    /// # Errors
+    /// Will error if the location is out of range or the text failed to be inserted, in which
+    /// case nothing is pasted.
+    pub fn paste(&mut self, loc: Loc, text: &str, reindent: bool) -> Result<()> {
+        // Pasting on the empty line at the bottom of the document creates it
+        let create = loc == Loc::at(0, self.len_lines());
+        if !create {
+            self.out_of_range(loc.x, loc.y)?;
+        }
+        let text = self.clipboard_text(text);
+        let mut lines: Vec<String> = text.split('\n').map(str::to_string).collect();
+        if reindent && lines.len() > 1 {
+            let indent: String = self
+                .line(loc.y)
+                .unwrap_or_default()
+                .chars()
+                .take_while(|ch| ch.is_whitespace())
+                .collect();
+            let leading = |line: &String| line.chars().take_while(|ch| ch.is_whitespace()).count();
+            let content = |line: &&mut String| !line.trim().is_empty();
+            let common = lines[1..].iter_mut().filter(content).map(|l| leading(l)).min();
+            for line in lines[1..].iter_mut().filter(content) {
+                let rest: String = line.chars().skip(common.unwrap_or(0)).collect();
+                *line = format!("{indent}{rest}");
+            }
+        }
+        let mut events = Event::insert_str_at(loc, &lines.join("\n"));
+        if create {
+            events.insert(0, Event::InsertLine(loc.y, String::new()));
+        }
+        self.batch(|doc| events.into_iter().try_for_each(|event| doc.apply(event)))?;
+        let last = lines.last().map_or(0, |line| line.chars().count());
+        let end = if lines.len() > 1 {
+            Loc::at(last, loc.y + lines.len() - 1)
+        } else {
+            Loc::at(loc.x + last, loc.y)
+        };
+        self.goto(&end);
+        self.old_cursor = self.char_ptr;
+        Ok(())
+    }
+
+    /// Paste whole lines into the document above line `y` as a single undo patch, such as
+    /// text that was yanked line-wise, leaving the cursor on the first character of the first
+    /// pasted line that isn't whitespace. A newline at the end of the text is ignored.
+    /// If `reindent` is true, the lines are moved together (like vim's `]p`) so that the first
+    /// of them is indented as far as the nearest line above with any text on it (or the line
+    /// below, at the top of the document). Indentation is made of tabs if the document is
+    /// indented with tabs (see `Document::indentation_summary`), and spaces otherwise.
+    /// # Errors
+    /// Will error if the line is out of range or the lines failed to be inserted, in which
+    /// case nothing is pasted.
+    pub fn paste_lines(&mut self, y: usize, text: &str, reindent: bool) -> Result<()> {
+        self.load_to(y + 1);
+        if y > self.len_lines() {
+            return Err(Error::OutOfRange);
+        }
+        let text = self.clipboard_text(text);
+        let text = text.strip_suffix('\n').unwrap_or(&text);
+        let mut lines: Vec<String> = text.split('\n').map(str::to_string).collect();
+        let first = lines.iter().find(|line| !line.trim().is_empty());
+        if let (true, Some(first)) = (reindent, first) {
+            let leading = |line: &str| line.len() - line.trim_start().len();
+            let from = width(&first[..leading(first)], self.tab_width);
+            let to = (0..y)
+                .rev()
+                .find_map(|y| self.indent_level(y))
+                .or_else(|| self.indent_level(y))
+                .unwrap_or(0);
+            let tabs = self.indentation_summary().starts_with("Tabs");
+            for line in lines.iter_mut().filter(|line| !line.trim().is_empty()) {
+                let level = width(&line[..leading(line)], self.tab_width) + to;
+                let level = level.saturating_sub(from);
+                let indent = if tabs && self.tab_width > 0 {
+                    let (whole, rest) = (level / self.tab_width, level % self.tab_width);
+                    format!("{}{}", "\t".repeat(whole), " ".repeat(rest))
+                } else {
+                    " ".repeat(level)
+                };
+                *line = format!("{indent}{}", line.trim_start());
+            }
+        }
+        self.batch(|doc| {
+            for (i, line) in lines.iter().enumerate() {
+                doc.apply(Event::InsertLine(y + i, line.clone()))?;
+            }
+            Ok(())
+        })?;
+        let x = lines[0].chars().take_while(|ch| ch.is_whitespace()).count();
+        self.goto(&Loc::at(x, y));
+        self.old_cursor = self.char_ptr;
+        Ok(())
+    }
+
+    /// Insert the contents of another file at a location, like vim's `:r`, as a single undo
+    /// patch (see `Document::paste`).
+    /// The file is read the same way as `Document::open` reads files, and its line endings are
+    /// turned into the document's own. To insert the file below a line, as `:r` does, give the
+    /// start of the next line (or the line after the last one, to insert it at the end).
+    /// # Errors
+    /// Returns an error if the file fails to read, or the location is out of range.
+    pub fn insert_file<P: AsRef<Path>>(&mut self, loc: Loc, path: P) -> Result<()> {
+        let contents = Rope::from_reader(BufReader::new(File::open(path)?))?.to_string();
+        let mut text = contents.as_str();
+        // After the last line, the document's own final newline ends the inserted text
+        if loc == Loc::at(0, self.len_lines()) {
+            let ends = ["\r\n", "\n", "\r"];
+            text = ends.iter().find_map(|end| text.strip_suffix(end)).unwrap_or(text);
+        }
+        self.paste(loc, text, false)
+    }
+
+    /// Prepare text from the clipboard for inserting, turning `\r\n` and `\r` line endings into
+    /// `\n` and normalizing it if normalization is on
+    fn clipboard_text(&self, text: &str) -> String {
+        let text = text.replace("\r\n", "\n").replace('\r', "\n");
+        if self.normalize {
+            nfc(&text)
+        } else {
+            text
+        }
+    }
+
+    /// Get the text between two locations, with lines joined by `\n`
+    /// # Errors
+    /// Returns an error if either location is out of range, or if the start is after the end.
+    pub fn text_in_range(&self, start: Loc, end: Loc) -> Result<String> {
+        self.out_of_range(start.x, start.y)?;
+        self.out_of_range(end.x, end.y)?;
+        if start > end {
+            return Err(Error::OutOfRange);
+        }
+        let mut result = String::new();
+        for y in start.y..=end.y {
+            let line = &self.lines[y];
+            let from = if y == start.y { start.x } else { 0 };
+            let to = if y == end.y { end.x } else { line.chars().count() };
+            result.extend(line.chars().take(to).skip(from));
+            if y != end.y {
+                result.push('\n');
+            }
+        }
+        Ok(result)
+    }
+
+    /// Remove the text between two locations, which can span multiple lines.
+    /// This is done through events as a single patch, so it can be undone in one step.
+    /// Exactly the text in the range is removed, spaces are never widened to whole tabs.
+    /// # Errors
+    /// Returns an error if either location is out of range, or if the start is after the end.
+    pub fn remove_range(&mut self, start: Loc, end: Loc) -> Result<()> {
+        if start > end {
+            return Err(Error::OutOfRange);
+        }
+        let events = Event::delete_span(Span::new(start, end), self)?;
+        self.batch(|doc| events.into_iter().try_for_each(|ev| doc.apply(ev)))?;
+        self.goto(&start);
+        self.old_cursor = self.char_ptr;
+        Ok(())
     }
 
-    /// Function to search the document to find the next occurance of a regex
-    pub fn next_match(&mut self, regex: &str, inc: usize) -> Option<Match> {
-        // Prepare
-        let mut srch = Searcher::new(regex);
-        // Check current line for matches
-        let current: String = self.line(self.loc().y)?
-            .chars()
-            .skip(self.char_ptr + inc)
-            .collect();
-        if let Some(mut mtch) = srch.lfind(&current) {
-            mtch.loc.y = self.loc().y;
-            mtch.loc.x += self.char_ptr + inc;
-            return Some(mtch)
+    /// Run the document through a formatter, which takes the text and returns it formatted.
+    /// Only the lines that the formatter changed are replaced, so the cursor stays where it is
+    /// and the formatting is committed as a single patch, which can be undone in one step.
+    /// # Errors
+    /// Will error if the lines failed to be replaced.
+    pub fn format_with<F: FnOnce(&str) -> String>(&mut self, formatter: F) -> Result<()> {
+        self.load_to(self.len_lines() + 1);
+        let text = formatter(&self.lines[..self.len_lines()].join("\n"));
+        let mut after: Vec<String> = text.lines().map(str::to_string).collect();
+        // Documents always have a line to edit, even when there's no text
+        if after.is_empty() {
+            after.push(String::new());
         }
-        // Check subsequent lines for matches
-        let mut line_no = self.loc().y + 1;
-        self.load_to(line_no + 1);
-        while let Some(line) = self.line(line_no) {
-            if let Some(mut mtch) = srch.lfind(&line) {
-                mtch.loc.y = line_no;
-                return Some(mtch);
-            }
-            line_no += 1;
-            self.load_to(line_no + 1);
+        self.replace_lines(&after)
+    }
+
+    /// Replace a range of lines with the output of a filter, which is given the lines (each
+    /// ending with `\n`) and returns the lines to replace them with, like vim's `:'<,'>!sort`.
+    /// Only the lines that the filter changed are replaced, as a single patch.
+    /// # Errors
+    /// Will error if the range is out of bounds or the lines failed to be replaced.
+    pub fn filter_lines<F>(&mut self, lines: Range<usize>, filter: F) -> Result<()>
+    where
+        F: FnOnce(&str) -> String,
+    {
+        self.load_to(self.len_lines() + 1);
+        if lines.start > lines.end || lines.end > self.len_lines() {
+            return Err(Error::OutOfRange);
         }
-        None
+        let input: String = self.lines[lines.clone()].iter().map(|l| l.clone() + "\n").collect();
+        let output = filter(&input);
+        let mut after = self.lines[..lines.start].to_vec();
+        after.extend(output.lines().map(str::to_string));
+        after.extend_from_slice(&self.lines[lines.end..self.len_lines()]);
+        self.replace_lines(&after)
     }
 
-    /// Function to search the document to find the previous occurance of a regex
-    pub fn prev_match(&mut self, regex: &str) -> Option<Match> {
-        // Prepare
-        let mut srch = Searcher::new(regex);
-        // Check current line for matches
-        let current: String = self.line(self.loc().y)?
-            .chars()
-            .take(self.char_ptr)
-            .collect();
-        if let Some(mut mtch) = srch.rfind(&current) {
-            mtch.loc.y = self.loc().y;
-            return Some(mtch);
+    /// Replace the loaded lines of the document with new ones, through events in a single
+    /// patch, keeping the cursor on the same line of text where possible
+    fn replace_lines(&mut self, after: &[String]) -> Result<()> {
+        let before = self.lines[..self.len_lines()].to_vec();
+        let hunks = diff(&before, after);
+        if hunks.is_empty() {
+            return Ok(());
         }
-        // Check antecedent lines for matches
-        self.load_to(self.loc().y + 1);
-        let mut line_no = self.loc().y.saturating_sub(1);
-        while let Some(line) = self.line(line_no) {
-            if let Some(mut mtch) = srch.rfind(&line) {
-                mtch.loc.y = line_no;
-                return Some(mtch);
+        let cursor = self.char_loc();
+        // Replace the changed lines, from the bottom up so that the line numbers stay valid
+        self.batch(|doc| {
+            for hunk in hunks.iter().rev() {
+                doc.replace_hunk(hunk, &before, after)?;
             }
-            if line_no == 0 { break; }
-            line_no = line_no.saturating_sub(1);
-        }
-        None
+            Ok(())
+        })?;
+        // Put the cursor back, following it if the line it was on moved
+        let y = map_line(&hunks, cursor.y).min(self.len_lines().saturating_sub(1));
+        let x = cursor.x.min(self.line(y).map_or(0, |l| l.chars().count()));
+        self.goto(&Loc::at(x, y));
+        self.old_cursor = self.char_ptr;
+        Ok(())
     }
 
-    /// Replace a specific part of the document with another string.
+    /// Reload the document from its file, applying the differences as events in a single patch.
+    /// Unlike opening the file again, this keeps the undo history, cursor and everything else,
+    /// so external changes (such as from a `git checkout`) can be undone like any other edit.
     /// # Errors
-    /// Will error if the replacement failed to be executed.
-    pub fn replace(&mut self, loc: Loc, target: &str, into: &str) -> Result<()> {
-        self.exe(Event::Delete(loc, target.to_string()))?;
-        self.exe(Event::Insert(loc, into.to_string()))?;
+    /// Returns an error if the document has no file name, the file couldn't be read, or the
+    /// lines failed to be replaced.
+    pub fn reload_preserving_state(&mut self) -> Result<()> {
+        let file_name = self.file_name.clone().ok_or(Error::NoFileName)?;
+        let text = std::fs::read_to_string(file_name)?;
+        // Empty files get a final newline, as they do when opened
+        self.insert_final_newline = text.is_empty() || text.ends_with('\n');
+        // Read only documents can't be edited, but should still follow their file
+        let read_only = std::mem::replace(&mut self.read_only, false);
+        let result = self.format_with(|_| text);
+        self.read_only = read_only;
+        result?;
+        self.modified = false;
+        self.refresh_line_states();
         Ok(())
     }
 
-    /// Replace all instances of a regex with another string
-    pub fn replace_all(&mut self, target: &str, into: &str) {
-        self.goto(&Loc::at(0, 0));
-        while let Some(mtch) = self.next_match(target, 1) {
-            drop(self.replace(mtch.loc, &mtch.text, into));
+    /// Compare this document with another, for a side by side view or merge.
+    /// The old side of each hunk refers to this document and the new side refers to the other.
+    /// Use `diff::align_lines` to pair up their lines for rendering.
+    #[must_use]
+    pub fn compare(&self, other: &Document) -> Vec<Hunk> {
+        diff(&self.all_lines(), &other.all_lines())
+    }
+
+    /// Take a hunk (from `compare`) from the other document, replacing the lines in this one.
+    /// This is committed as a single patch, so it can be undone in one step.
+    /// # Errors
+    /// Will error if the hunk is out of range or the lines failed to be replaced.
+    pub fn take_hunk(&mut self, other: &Document, hunk: &Hunk) -> Result<()> {
+        let before = self.all_lines();
+        let after = other.all_lines();
+        if hunk.old.end > before.len() || hunk.new.end > after.len() {
+            return Err(Error::OutOfRange);
         }
+        self.load_to(self.len_lines() + 1);
+        self.batch(|doc| doc.replace_hunk(hunk, &before, &after))
+    }
+
+    /// Replace the lines on the old side of a hunk with those on the new side, through events
+    fn replace_hunk(&mut self, hunk: &Hunk, before: &[String], after: &[String]) -> Result<()> {
+        for y in hunk.old.clone().rev() {
+            self.exe(Event::DeleteLine(y, before[y].clone()))?;
+        }
+        for y in hunk.new.clone() {
+            let at = hunk.old.start + y - hunk.new.start;
+            self.exe(Event::InsertLine(at, after[y].clone()))?;
+        }
+        Ok(())
     }
 
     /// Function to go to a specific position
@@ -656,16 +2723,13 @@ impl Document {
 
     /// Function to go to a specific x position
     pub fn goto_x(&mut self, x: usize) {
-        let line = self.line(self.loc().y).unwrap_or_else(|| "".to_string());
         // If we're already at this x coordinate, just exit
         if self.char_ptr == x {
             return;
         }
         // If the move position is out of bounds, move to the end of the line
-        if line.chars().count() < x {
-            let line = self.line(self.loc().y).unwrap_or_else(|| "".to_string());
-            let length = line.chars().count();
-            self.goto_x(length);
+        if self.line_end(self.loc().y) < x {
+            self.goto_x(self.line_end(self.loc().y));
             return;
         }
         // Update char position
@@ -715,6 +2779,21 @@ impl Document {
         self.update_char_ptr();
         // Load any lines necessary
         self.load_to(self.offset.y + self.size.h);
+        // Snap onto the last character if the cursor can't sit past it
+        if self.char_ptr > self.line_end(self.loc().y) {
+            self.goto_x(self.line_end(self.loc().y));
+        }
+    }
+
+    /// Get the furthest character index the cursor can reach on a line
+    #[must_use]
+    pub fn line_end(&self, y: usize) -> usize {
+        let length = self.line(y).map_or(0, |l| l.chars().count());
+        if self.cursor_past_end {
+            length
+        } else {
+            length.saturating_sub(1)
+        }
     }
 
     /// Determines if specified coordinates are out of range of the document.
@@ -745,19 +2824,38 @@ impl Document {
         let mut idx = loc.x;
         // Account for double width characters
         idx += self.dbl_map.count(loc, false).unwrap_or(0);
-        // Account for tab characters
-        idx += self.tab_map.count(loc, false).unwrap_or(0) * self.tab_width.saturating_sub(1);
+        // Account for tab characters, which stretch to the next tab stop
+        idx += self.tab_map.get(loc.y).map_or(0, |tabs| {
+            tabs.iter()
+                .filter(|t| t.1 < loc.x)
+                .map(|t| tab_stop(t.0, self.tab_width).saturating_sub(1))
+                .sum()
+        });
         idx
     }
 
     /// A utility function to update the character pointer when moving up or down
     fn update_char_ptr(&mut self) {
-        let mut idx = self.loc().x;
+        let Loc { x, y } = self.loc();
+        let mut idx = x;
         let dbl_count = self.dbl_map.count(&self.loc(), true).unwrap_or(0);
         idx -= dbl_count;
-        let tab_count = self.tab_map.count(&self.loc(), true).unwrap_or(0);
-        idx -= tab_count * self.tab_width.saturating_sub(1);
-        self.char_ptr = idx;
+        let tab_extra: usize = self.tab_map.get(y).map_or(0, |tabs| {
+            tabs.iter()
+                .filter(|t| t.0 < x)
+                .map(|t| tab_stop(t.0, self.tab_width).saturating_sub(1))
+                .sum()
+        });
+        self.char_ptr = idx.saturating_sub(tab_extra);
+    }
+
+    /// Recalculate the locations of double width and tab characters on a line
+    fn remap(&mut self, y: usize) {
+        let (dbl_map, tab_map) = form_map(&self.lines[y], self.tab_width);
+        self.dbl_map.delete(y);
+        self.tab_map.delete(y);
+        self.dbl_map.insert(y, dbl_map);
+        self.tab_map.insert(y, tab_map);
     }
 
     /// A utility function to make sure the cursor doesn't go out of range when moving
@@ -785,7 +2883,7 @@ impl Document {
         if let Some(map) = self.tab_map.get(y) {
             let last_tab = self.tab_map.count(&self.loc(), true).unwrap().saturating_sub(1);
             let start = map[last_tab].0;
-            let range = start..start + self.tab_width;
+            let range = start..start + tab_stop(start, self.tab_width);
             if range.contains(&x) {
                 magnitude += x - start;
             }
@@ -825,25 +2923,513 @@ impl Document {
         }
     }
 
+    /// Determine if the whole view needs rendering again, clearing the request.
+    /// This is the case the first time it is called, when the view scrolls or is resized, and
+    /// when lines are added or removed (or the tab width changes).
+    /// Any requests to render individual lines are cleared too, as they are covered by this.
+    pub fn take_full_render_request(&mut self) -> bool {
+        let view = (self.offset, self.size);
+        let full = self.render_all || self.rendered_view != Some(view);
+        self.render_all = false;
+        self.rendered_view = Some(view);
+        if full {
+            self.render_lines.clear();
+        }
+        full
+    }
+
+    /// Get the lines that have been edited since they were last rendered, in order, clearing
+    /// the request. Call `Document::take_full_render_request` first, as these lines are only
+    /// worth rendering on their own if the whole view doesn't need rendering.
+    pub fn take_line_render_requests(&mut self) -> Vec<usize> {
+        std::mem::take(&mut self.render_lines).into_iter().collect()
+    }
+
     /// Get the line at a specified index
     #[must_use]
     pub fn line(&self, line: usize) -> Option<String> {
         Some(self.lines.get(line)?.to_string())
     }
 
+    /// Iterate through the loaded lines of this document
+    pub fn iter_rows(&self) -> impl DoubleEndedIterator<Item = &str> {
+        self.lines.iter().take(self.len_lines()).map(String::as_str)
+    }
+
+    /// Determine if a location on a loaded line falls between two user-perceived characters,
+    /// rather than in the middle of one (see `utils::is_boundary`).
+    /// Lines can only be split at boundaries.
+    #[must_use]
+    pub fn is_boundary(&self, loc: &Loc) -> bool {
+        self.lines.get(loc.y).is_some_and(|line| is_boundary(line, loc.x))
+    }
+
+    /// Split a loaded line into user-perceived characters (see `utils::graphemes`)
+    #[must_use]
+    pub fn line_graphemes(&self, y: usize) -> Option<Vec<&str>> {
+        Some(graphemes(self.lines.get(y)?))
+    }
+
+    /// Render the end of the document into a viewport, for panes that keep their content
+    /// anchored to the bottom (such as a REPL or a message log).
+    /// Returns exactly `size.h` rows of exactly `size.w` columns, from the top of the viewport
+    /// down, starting at the horizontal offset. If the document is shorter than the viewport,
+    /// the rows at the top are left blank. Lines are read from the file, so they don't need
+    /// to be loaded.
+    #[must_use]
+    pub fn render_from_bottom(&self, size: Size) -> Vec<String> {
+        let start = self.len_lines().saturating_sub(size.h);
+        let rows = self.file.lines_at(start).take(self.len_lines() - start).map(|line| {
+            // Long lines are clamped, as they are in the rest of the document
+            let line: String = line
+                .chars()
+                .filter(|c| *c != '\n' && *c != '\r')
+                .take(self.max_line_length.unwrap_or(usize::MAX))
+                .collect();
+            let line = trim(&line, self.offset.x, size.w, self.tab_width);
+            pad_to(&line, size.w, Align::Left, self.tab_width)
+        });
+        let blank = size.h - (self.len_lines() - start);
+        std::iter::repeat_n(" ".repeat(size.w), blank).chain(rows).collect()
+    }
+
+    /// Get the loaded lines around a location, along with their indices, for rendering previews
+    /// of search results or diagnostics. The range is clamped to the start and end of the document.
+    #[must_use]
+    pub fn context(&self, loc: &Loc, before: usize, after: usize) -> Vec<(usize, &str)> {
+        let start = loc.y.saturating_sub(before);
+        let end = loc.y.saturating_add(after).saturating_add(1);
+        self.iter_rows().enumerate().skip(start).take(end - start).collect()
+    }
+
+    /// Find the block of loaded lines around a line that are indented at least as far as it,
+    /// for folding by indentation or selecting a scope (as in Python).
+    /// Blank lines within the block are included, but not ones at either end of it.
+    /// A blank line is a block on its own.
+    #[must_use]
+    pub fn indent_block_at(&self, y: usize) -> Range<usize> {
+        let Some(level) = self.indent_level(y) else { return y..y + 1 };
+        let within = |y: usize| self.indent_level(y).is_none_or(|l| l >= level);
+        let mut start = y;
+        while start > 0 && within(start - 1) {
+            start -= 1;
+        }
+        let mut end = y + 1;
+        while end < self.len_lines().min(self.lines.len()) && within(end) {
+            end += 1;
+        }
+        // Leave out the blank lines at the edges
+        while self.indent_level(start).is_none() {
+            start += 1;
+        }
+        while self.indent_level(end - 1).is_none() {
+            end -= 1;
+        }
+        start..end
+    }
+
+    /// Find the next blank line after a line, for moving by paragraph
+    #[must_use]
+    pub fn next_blank_line(&self, y: usize) -> Option<usize> {
+        (y + 1..self.len_lines().min(self.lines.len())).find(|y| self.indent_level(*y).is_none())
+    }
+
+    /// Find the previous blank line before a line, for moving by paragraph
+    #[must_use]
+    pub fn prev_blank_line(&self, y: usize) -> Option<usize> {
+        (0..y.min(self.lines.len())).rev().find(|y| self.indent_level(*y).is_none())
+    }
+
+    /// Find the nearest line in a direction with the same indentation as a line, skipping blank
+    /// lines and ones indented further, for moving between the statements of a block.
+    /// Returns `None` if a line indented less is reached first, as that is outside the block.
+    #[must_use]
+    pub fn matching_indent_line(&self, y: usize, direction: Direction) -> Option<usize> {
+        let level = self.indent_level(y)?;
+        let mut lines: Box<dyn Iterator<Item = usize>> = match direction {
+            Direction::Forward => Box::new(y + 1..self.len_lines().min(self.lines.len())),
+            Direction::Backward => Box::new((0..y).rev()),
+        };
+        lines
+            .find(|y| self.indent_level(*y).is_some_and(|l| l <= level))
+            .filter(|y| self.indent_level(*y) == Some(level))
+    }
+
+    /// Get the display width of the indentation of a loaded line, or `None` if it is blank
+    fn indent_level(&self, y: usize) -> Option<usize> {
+        let line = self.lines.get(y)?;
+        let content = line.trim_start();
+        if content.is_empty() {
+            return None;
+        }
+        Some(width(&line[..line.len() - content.len()], self.tab_width))
+    }
+
+    /// Iterate through the characters of the loaded lines, starting at a location and moving
+    /// forward through the document. Each line ends with a `\n` character.
+    pub fn iter_chars_from(&self, loc: Loc) -> impl Iterator<Item = (Loc, char)> + '_ {
+        self.lines.iter().take(self.len_lines()).enumerate().skip(loc.y).flat_map(move |(y, line)| {
+            let start = if y == loc.y { loc.x } else { 0 };
+            line.chars()
+                .chain(std::iter::once('\n'))
+                .enumerate()
+                .skip(start)
+                .map(move |(x, ch)| (Loc::at(x, y), ch))
+        })
+    }
+
+    /// Iterate through the characters of the loaded lines before a location, moving
+    /// backward through the document. Each line ends with a `\n` character.
+    pub fn iter_chars_before(&self, loc: Loc) -> impl Iterator<Item = (Loc, char)> + '_ {
+        let lines = self.lines.iter().take(self.len_lines()).enumerate().take(loc.y + 1);
+        lines.rev().flat_map(move |(y, line)| {
+            let chars: Vec<char> = line.chars().chain(std::iter::once('\n')).collect();
+            let end = if y == loc.y { loc.x.min(chars.len()) } else { chars.len() };
+            chars
+                .into_iter()
+                .enumerate()
+                .take(end)
+                .rev()
+                .map(move |(x, ch)| (Loc::at(x, y), ch))
+        })
+    }
+
     /// Get the line at a specified index and trim it
     #[must_use]
     pub fn line_trim(&self, line: usize, start: usize, length: usize) -> Option<String> {
+        if self.is_clamped(line) {
+            // Each character takes up at least one column, so this is all that could be seen
+            let max = self.max_line_length.unwrap_or(usize::MAX);
+            let cut = (start + length + 1).min(max);
+            let line: String = self.lines.get(line)?.chars().take(cut).collect();
+            return Some(trim(&line, start, length, self.tab_width));
+        }
         let line = self.line(line);
         Some(trim(&line?, start, length, self.tab_width))
     }
 
+    /// Get the part of a line that shows through a window of the screen, as exactly `width`
+    /// columns. Double width characters that are cut in half by either edge of the window are
+    /// replaced with a space, and short lines are padded with spaces, so the result can be
+    /// written straight to the terminal without spilling over into the next cell.
+    #[must_use]
+    pub fn render_window(&self, y: usize, start: usize, width: usize) -> Option<String> {
+        let line = self.line_trim(y, start, width)?;
+        Some(pad_to(&line, width, Align::Left, self.tab_width))
+    }
+
+    /// Determine if a loaded line is longer than the maximum line length.
+    /// Only the start of a clamped line is rendered (up to the maximum line length), so front
+    /// ends may want to warn the user about it.
+    #[must_use]
+    pub fn is_clamped(&self, y: usize) -> bool {
+        match (self.max_line_length, self.lines.get(y)) {
+            // Lines can't have more characters than bytes, so most can be ruled out quickly
+            (Some(max), Some(line)) => line.len() > max && line.chars().count() > max,
+            _ => false,
+        }
+    }
+
+    /// Get the loaded lines that are clamped (see `Document::is_clamped`)
+    #[must_use]
+    pub fn clamped_lines(&self) -> Vec<usize> {
+        (0..self.len_lines().min(self.lines.len())).filter(|y| self.is_clamped(*y)).collect()
+    }
+
+    /// Get the line at a specified index and trim it, drawing a guide character at any guide
+    /// columns that the line doesn't reach.
+    #[must_use]
+    pub fn line_trim_guides(
+        &self,
+        line: usize,
+        start: usize,
+        length: usize,
+        guide: char,
+    ) -> Option<String> {
+        let mut result = self.line_trim(line, start, length)?;
+        for column in self.guides_within(start, length) {
+            let at = width(&result, self.tab_width);
+            if at <= column {
+                result.push_str(&" ".repeat(column - at));
+                result.push(guide);
+            }
+        }
+        Some(result)
+    }
+
+    /// Get the screen columns of the guides that are within the viewport
+    #[must_use]
+    pub fn guides_in_view(&self) -> Vec<usize> {
+        self.guides_within(self.offset.x, self.size.w)
+    }
+
+    /// Get the columns of the guides within a display range, relative to the start of it
+    fn guides_within(&self, start: usize, length: usize) -> Vec<usize> {
+        self.guides
+            .iter()
+            .filter(|g| (start..start + length).contains(g))
+            .map(|g| g - start)
+            .collect()
+    }
+
+    /// Get the name of the line endings the document uses ("LF" or "CRLF"), based on the first
+    /// line. Documents without any line endings are given as "LF".
+    #[must_use]
+    pub fn line_ending_name(&self) -> &'static str {
+        let first = self.file.line(0);
+        let len = first.len_chars();
+        if len >= 2 && first.char(len - 2) == '\r' {
+            "CRLF"
+        } else {
+            "LF"
+        }
+    }
+
+    /// Get the name of the encoding of the document.
+    /// Documents are always read and written as UTF-8.
+    #[must_use]
+    pub fn encoding_name(&self) -> &'static str {
+        "UTF-8"
+    }
+
+    /// Describe how the document is indented, such as "Tabs: 4" or "Spaces: 2".
+    /// This is worked out from the first thousand lines: tabs are used if more lines start with
+    /// a tab than with spaces, and the number of spaces is the most common step in indentation.
+    /// Documents without indentation are described using the tab width.
+    #[must_use]
+    pub fn indentation_summary(&self) -> String {
+        let mut tabs = 0;
+        let mut spaces = 0;
+        let mut steps: HashMap<usize, usize> = HashMap::new();
+        let mut last = 0;
+        for line in self.file.lines().take(1000) {
+            let line = line.to_string();
+            if line.trim().is_empty() {
+                continue;
+            }
+            if line.starts_with('\t') {
+                tabs += 1;
+                continue;
+            }
+            let indent = line.len() - line.trim_start_matches(' ').len();
+            if indent > 0 {
+                spaces += 1;
+            }
+            if indent > last {
+                *steps.entry(indent - last).or_default() += 1;
+            }
+            last = indent;
+        }
+        if tabs > spaces {
+            return format!("Tabs: {}", self.tab_width);
+        }
+        let step = steps
+            .into_iter()
+            .max_by_key(|(step, count)| (*count, std::cmp::Reverse(*step)))
+            .map_or(self.tab_width, |(step, _)| step);
+        format!("Spaces: {step}")
+    }
+
     /// Returns the number of lines in the document
     #[must_use]
     pub fn len_lines(&self) -> usize {
         self.file.len_lines().saturating_sub(1)
     }
 
+    /// Returns the number of characters (unicode scalar values) on a loaded line.
+    /// This is what character indices, such as the x in `Document::char_loc`, count.
+    #[must_use]
+    pub fn line_char_len(&self, y: usize) -> Option<usize> {
+        Some(self.lines.get(y)?.chars().count())
+    }
+
+    /// Returns the number of user-perceived characters on a loaded line, where a character
+    /// and the combining marks after it count as one (see `utils::grapheme_len`)
+    #[must_use]
+    pub fn line_grapheme_len(&self, y: usize) -> Option<usize> {
+        Some(grapheme_len(self.lines.get(y)?))
+    }
+
+    /// Returns the number of columns a loaded line takes up on screen, taking into account
+    /// double width and tab characters.
+    /// This is what display indices, such as the x in `Document::loc`, count.
+    #[must_use]
+    pub fn line_display_width(&self, y: usize) -> Option<usize> {
+        Some(width(self.lines.get(y)?, self.tab_width))
+    }
+
+    /// Returns the number of characters in the document, not counting line endings
+    #[must_use]
+    pub fn char_len(&self) -> usize {
+        self.file_lines().map(|line| line.chars().count()).sum()
+    }
+
+    /// Returns the number of user-perceived characters in the document, not counting line
+    /// endings (see `Document::line_grapheme_len`)
+    #[must_use]
+    pub fn grapheme_len(&self) -> usize {
+        self.file_lines().map(|line| grapheme_len(&line)).sum()
+    }
+
+    /// Returns the number of columns the widest line in the document takes up on screen
+    #[must_use]
+    pub fn display_width(&self) -> usize {
+        self.file_lines().map(|line| width(&line, self.tab_width)).max().unwrap_or(0)
+    }
+
+    /// Get the offset of a location (in character indices) from the start of the document, in
+    /// characters. Line endings count towards the offset, so this matches the character offsets
+    /// used by other tools, such as language servers.
+    /// The rope keeps running totals of the characters and lines in each part of the document,
+    /// updated as it is edited, so this takes logarithmic time however long the document is,
+    /// and works on lines that haven't been loaded yet.
+    /// # Errors
+    /// Returns `Error::OutOfRange` if the location is past the end of its line or the document.
+    pub fn char_offset(&self, loc: &Loc) -> Result<usize> {
+        if loc.y >= self.len_lines() || loc.x > self.rope_line_len(loc.y) {
+            return Err(Error::OutOfRange);
+        }
+        Ok(self.file.try_line_to_char(loc.y)? + loc.x)
+    }
+
+    /// Get the offset of a location (in character indices) from the start of the document, in
+    /// bytes of UTF-8, in logarithmic time (see `Document::char_offset`)
+    /// # Errors
+    /// Returns `Error::OutOfRange` if the location is past the end of its line or the document.
+    pub fn byte_offset(&self, loc: &Loc) -> Result<usize> {
+        Ok(self.file.try_char_to_byte(self.char_offset(loc)?)?)
+    }
+
+    /// Get the location (in character indices) of an offset from the start of the document in
+    /// characters, in logarithmic time (see `Document::char_offset`).
+    /// Offsets within a line ending are placed at the end of the line.
+    /// # Errors
+    /// Returns `Error::OutOfRange` if the offset is past the end of the document.
+    pub fn loc_from_char_offset(&self, offset: usize) -> Result<Loc> {
+        let y = self.file.try_char_to_line(offset)?;
+        if y >= self.len_lines() {
+            return Err(Error::OutOfRange);
+        }
+        let x = offset - self.file.try_line_to_char(y)?;
+        Ok(Loc::at(x.min(self.rope_line_len(y)), y))
+    }
+
+    /// Get the location (in character indices) of an offset from the start of the document in
+    /// bytes, in logarithmic time (see `Document::char_offset`).
+    /// Offsets in the middle of a character are placed at the start of it.
+    /// # Errors
+    /// Returns `Error::OutOfRange` if the offset is past the end of the document.
+    pub fn loc_from_byte_offset(&self, offset: usize) -> Result<Loc> {
+        self.loc_from_char_offset(self.file.try_byte_to_char(offset)?)
+    }
+
+    /// Get the number of characters on a line of the rope, loaded or not, without its ending
+    fn rope_line_len(&self, y: usize) -> usize {
+        let line = self.file.line(y);
+        let mut len = line.len_chars();
+        for ending in ['\n', '\r'] {
+            if len > 0 && line.char(len - 1) == ending {
+                len -= 1;
+            }
+        }
+        len
+    }
+
+    /// Iterate through every line of the file, loaded or not, without line endings
+    fn file_lines(&self) -> impl Iterator<Item = String> + '_ {
+        self.file.lines().take(self.len_lines()).map(|line| {
+            line.to_string().trim_end_matches(['\n', '\r']).to_string()
+        })
+    }
+
+    /// Describe the document and the lines in view, with a `|` marking the cursor, for logging
+    /// and bug reports. Tabs and other characters are shown as they are in the file.
+    #[must_use]
+    pub fn debug_dump(&self) -> String {
+        let name = self.title().unwrap_or_else(|| "[no name]".to_string());
+        let header = format!(
+            "{name} ({} lines, {}, cursor at {}{})\n",
+            self.len_lines(),
+            self.size,
+            self.char_loc(),
+            if self.modified { ", modified" } else { "" },
+        );
+        let end = (self.offset.y + self.size.h).min(self.len_lines());
+        let lines = (self.offset.y..end).map(|y| {
+            let mut line: String = self.file.line(y).chars().filter(|c| *c != '\n').collect();
+            let marker = if y == self.loc().y {
+                let at = line.char_indices().nth(self.char_ptr).map_or(line.len(), |(i, _)| i);
+                line.insert(at, '|');
+                '>'
+            } else {
+                ' '
+            };
+            format!("{marker}{:>5} {line}\n", y + 1)
+        });
+        std::iter::once(header).chain(lines).collect()
+    }
+
+    /// Work out a checksum of the text of the document, ignoring everything else (such as the
+    /// cursor, caches and history), for cheaply telling whether two documents hold the same text.
+    /// The checksum is the same across runs and platforms, so it can be sent elsewhere to sync.
+    #[must_use]
+    pub fn checksum(&self) -> u64 {
+        // 64 bit FNV-1a, which is simple and stable
+        let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+        for byte in self.file.bytes() {
+            hash ^= u64::from(byte);
+            hash = hash.wrapping_mul(0x0100_0000_01b3);
+        }
+        hash
+    }
+
+    /// Determine if two documents hold the same text, ignoring everything else.
+    /// Use `==` to compare documents strictly, including the cursor and history.
+    #[must_use]
+    pub fn same_text(&self, other: &Self) -> bool {
+        self.file == other.file
+    }
+
+    /// Gather information about the document for a status line.
+    /// Files without an extension (like `Makefile`) have no extension or file type.
+    #[must_use]
+    pub fn status_info(&self) -> StatusInfo {
+        let path = self.file_name.as_deref().map(Path::new);
+        let extension = path
+            .and_then(Path::extension)
+            .map(|e| e.to_string_lossy().to_string());
+        StatusInfo {
+            file_name: path
+                .and_then(Path::file_name)
+                .map(|f| f.to_string_lossy().to_string()),
+            file_type: extension.as_deref().and_then(filetype),
+            extension,
+            modified: self.modified,
+            read_only: self.read_only,
+            line: self.loc().y + 1,
+            total_lines: self.len_lines(),
+            column: self.char_ptr + 1,
+        }
+    }
+
+    /// Get how strings and comments are written in the document, based on its file type
+    /// (see `Syntax::for_filetype`)
+    #[must_use]
+    pub fn syntax(&self) -> Syntax {
+        self.status_info().file_type.map_or_else(Syntax::default, |t| Syntax::for_filetype(&t))
+    }
+
+    /// Get the outline of the document, such as its functions or headings, based on its file
+    /// type (see `tags::outline`). This includes lines that haven't been loaded yet.
+    #[must_use]
+    pub fn outline(&self) -> Vec<Symbol> {
+        let Some(file_type) = self.status_info().file_type else {
+            return vec![];
+        };
+        let lines = self.all_lines();
+        outline(&file_type, lines.iter().map(String::as_str), self.tab_width)
+    }
+
     /// Evaluate the line number text for a specific line
     #[must_use]
     pub fn line_number(&self, request: usize) -> String {
@@ -883,13 +3469,54 @@ impl Document {
     pub fn width_of(&self, y: usize, x: usize) -> usize {
         if self.is_dbl_width(y, x) {
             2
-        } else if self.is_tab(y, x) {
-            self.tab_width
+        } else if let Some(tab) = self.tab_map.get(y).and_then(|m| m.iter().find(|t| t.1 == x)) {
+            // Tabs stretch to the next tab stop
+            tab_stop(tab.0, self.tab_width)
         } else {
             1
         }
     }
 
+    /// Determine the display width of a line up to a character index, taking into account
+    /// double width and tab characters
+    #[must_use]
+    pub fn display_width_up_to(&self, loc: &Loc) -> usize {
+        self.display_idx(loc)
+    }
+
+    /// Get the range of characters on a line that go past the ruler, for highlighting them
+    #[must_use]
+    pub fn overflow(&self, y: usize) -> Option<Range<usize>> {
+        let ruler = self.ruler?;
+        let length = self.line(y)?.chars().count();
+        let mut column = 0;
+        for x in 0..length {
+            column += self.width_of(y, x);
+            if column > ruler {
+                return Some(x..length);
+            }
+        }
+        None
+    }
+
+    /// Get the built in decorations on a loaded line (the text past the ruler and the
+    /// selection), in the order they should be layered (see `StyledSpan::flatten`)
+    #[must_use]
+    pub fn decorations(&self, y: usize) -> Vec<StyledSpan> {
+        let mut spans = vec![];
+        if let Some(range) = self.overflow(y) {
+            spans.push(StyledSpan::new(range, StyledSpan::RULER));
+        }
+        let Some(selection) = self.selection else { return spans };
+        let line_len = |y| self.line(y).map_or(0, |l| l.chars().count());
+        for span in selection.split_lines(line_len) {
+            if span.start.y == y && !span.is_empty() {
+                spans.push(StyledSpan::new(span.start.x..span.end.x, StyledSpan::SELECTION));
+            }
+        }
+        spans
+    }
+
     /// Get the current position within the document, including offset
     #[must_use]
     pub const fn loc(&self) -> Loc {
@@ -908,3 +3535,58 @@ impl Document {
         }
     }
 }
+
+/// Wraps a reader to report how much of it has been read, failing once it is cancelled
+struct Progress<'a, R, F> {
+    inner: R,
+    read: usize,
+    total: usize,
+    callback: F,
+    cancel: &'a AtomicBool,
+}
+
+impl<R: Read, F: FnMut(usize, usize)> Read for Progress<'_, R, F> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        if self.cancel.load(Ordering::Relaxed) {
+            return Err(std::io::Error::other("cancelled"));
+        }
+        let n = self.inner.read(buf)?;
+        self.read += n;
+        (self.callback)(self.read, self.total.max(self.read));
+        Ok(n)
+    }
+}
+
+/// Create a file for writing, picking out permission errors so they can be handled
+fn create(file_name: &str) -> Result<File> {
+    File::create(file_name).map_err(|e| {
+        if e.kind() == ErrorKind::PermissionDenied {
+            Error::PermissionDenied
+        } else {
+            Error::Io(e)
+        }
+    })
+}
+
+/// Create a new file in the temporary directory, ending in a file name.
+/// The file is created fresh with a name that isn't taken yet, so an existing file or link
+/// that someone else put there is never opened, and only the current user can read it.
+fn create_temp(name: &str) -> Result<(PathBuf, File)> {
+    static TEMP_FILES: AtomicUsize = AtomicUsize::new(0);
+    loop {
+        let nanos = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.subsec_nanos());
+        let count = TEMP_FILES.fetch_add(1, Ordering::Relaxed);
+        let unique = format!("kaolinite-{}-{count}-{nanos:x}-{name}", std::process::id());
+        let path = std::env::temp_dir().join(unique);
+        let mut options = OpenOptions::new();
+        options.write(true).create_new(true);
+        #[cfg(unix)]
+        std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+        match options.open(&path) {
+            Ok(file) => return Ok((path, file)),
+            // The name was taken in the meantime, so try another
+            Err(e) if e.kind() == ErrorKind::AlreadyExists => (),
+            Err(e) => return Err(Error::Io(e)),
+        }
+    }
+}
@@ -1,6 +1,9 @@
 /// event.rs - manages editing events and provides tools for error handling
-use crate::utils::Loc;
+use crate::document::Document;
+use crate::utils::{Loc, Span};
 use quick_error::quick_error;
+use std::fmt;
+use std::time::{Duration, Instant};
 
 /// Represents an editing event.
 /// All possible editing events can be made up of a combination these events.
@@ -15,6 +18,55 @@ pub enum Event {
 }
 
 impl Event {
+    /// Work out the events that insert some text at a location, which can span several lines.
+    /// Lines can end in either `\n` or `\r\n`.
+    #[must_use]
+    pub fn insert_str_at(loc: Loc, text: &str) -> Vec<Event> {
+        let mut events = vec![];
+        let mut at = loc;
+        for (i, line) in text.split('\n').enumerate() {
+            if i > 0 {
+                events.push(Event::SplitDown(at));
+                at = Loc::at(0, at.y + 1);
+            }
+            let line = line.strip_suffix('\r').unwrap_or(line);
+            if !line.is_empty() {
+                events.push(Event::Insert(at, line.to_string()));
+                at.x += line.chars().count();
+            }
+        }
+        events
+    }
+
+    /// Work out the events that remove a span of text from a document, which can span several
+    /// lines. The lines the span covers must be loaded.
+    /// # Errors
+    /// Returns an error if the span is out of range.
+    pub fn delete_span(span: Span, doc: &Document) -> Result<Vec<Event>> {
+        let Span { start, end } = span;
+        let text = doc.text_in_range(start, end)?;
+        if start.y == end.y {
+            return Ok(if text.is_empty() { vec![] } else { vec![Event::Delete(start, text)] });
+        }
+        let lines: Vec<&str> = text.split('\n').collect();
+        let mut events = vec![];
+        // Remove the end of the first line
+        if !lines[0].is_empty() {
+            events.push(Event::Delete(start, lines[0].to_string()));
+        }
+        // Remove the lines in between
+        for line in &lines[1..lines.len() - 1] {
+            events.push(Event::DeleteLine(start.y + 1, (*line).to_string()));
+        }
+        // Remove the start of the last line and join what's left onto the first line
+        let head = lines[lines.len() - 1];
+        if !head.is_empty() {
+            events.push(Event::Delete(Loc::at(0, start.y + 1), head.to_string()));
+        }
+        events.push(Event::SpliceUp(start));
+        Ok(events)
+    }
+
     /// Given an event, provide the opposite of that event (for purposes of undoing)
     #[must_use]
     pub fn reverse(self) -> Event {
@@ -42,16 +94,73 @@ impl Event {
     }
 }
 
+impl fmt::Display for Event {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Event::Insert(loc, st) => write!(f, "insert {st:?} at {loc}"),
+            Event::Delete(loc, st) => write!(f, "delete {st:?} at {loc}"),
+            Event::InsertLine(y, st) => write!(f, "insert line {st:?} at {}", y + 1),
+            Event::DeleteLine(y, st) => write!(f, "delete line {st:?} at {}", y + 1),
+            Event::SplitDown(loc) => write!(f, "split down at {loc}"),
+            Event::SpliceUp(loc) => write!(f, "splice up at {loc}"),
+        }
+    }
+}
+
+/// The direction to replay a sequence of events in
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    /// Execute the events in order
+    Forward,
+    /// Reverse the events, starting from the last one (for purposes of undoing)
+    Backward,
+}
+
 /// Represents various statuses of functions
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Status {
     StartOfFile,
     EndOfFile,
     StartOfLine,
     EndOfLine,
+    NothingToUndo,
+    NothingToRedo,
+    WordBoundary,
+    StructuralChange,
+    WrappedAround,
+    NoMatches,
+    ProbablyPaste,
     None,
 }
 
+impl Status {
+    /// Returns true if the event that gave this status would usually end an undo patch,
+    /// such as typing a space or splitting a line. Front ends can commit when this is true.
+    #[must_use]
+    pub fn suggests_commit(&self) -> bool {
+        matches!(self, Status::WordBoundary | Status::StructuralChange)
+    }
+}
+
+impl fmt::Display for Status {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Status::StartOfFile => "start of file",
+            Status::EndOfFile => "end of file",
+            Status::StartOfLine => "start of line",
+            Status::EndOfLine => "end of line",
+            Status::NothingToUndo => "nothing to undo",
+            Status::NothingToRedo => "nothing to redo",
+            Status::WordBoundary => "word boundary",
+            Status::StructuralChange => "structural change",
+            Status::WrappedAround => "search wrapped around",
+            Status::NoMatches => "no matches",
+            Status::ProbablyPaste => "probably a paste",
+            Status::None => "none",
+        })
+    }
+}
+
 /// Easy result type for unified error handling
 pub type Result<T> = std::result::Result<T, Error>;
 
@@ -69,23 +178,103 @@ quick_error! {
             display("Rope error: {}", err)
             source(err)
         }
+        InvalidRegex(err: regex::Error) {
+            from()
+            display("Invalid regex: {}", err)
+            source(err)
+        }
+        Script(err: String) {
+            display("Script error: {}", err)
+        }
+        UnknownCommand(name: String) {
+            display("Unknown command: {}", name)
+        }
+        InvalidArguments
         NoFileName
         OutOfRange
+        NotBoundary
+        PermissionDenied
         ReadOnlyFile
+        UnsavedChanges
+        EventsPending
     }
 }
 
-/// For managing events for purposes of undo and redo
+/// The number of events in a burst for it to count as a paste
+pub const PASTE_EVENTS: usize = 8;
+
+/// Decides when patches are committed automatically, so that undo and redo work in
+/// sensible steps without the front end having to commit
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GroupingPolicy {
+    /// Only commit when `EventMgmt::commit` is called
+    #[default]
+    Manual,
+    /// Commit after each word, when whitespace is typed or lines are changed
+    Word,
+    /// Commit when lines are changed, such as splitting or deleting a line
+    Line,
+    /// Commit when there is a pause in editing longer than a certain duration
+    Time(Duration),
+    /// Commit after a certain number of events
+    Count(usize),
+}
+
+/// A committed patch on the undo or redo stack
 #[derive(Default, Debug, Clone, PartialEq, Eq)]
+pub struct Patch {
+    /// The events in the patch, in the order they were executed
+    pub events: Vec<Event>,
+    /// The selection when the patch was started, so undo can restore it
+    pub selection: Option<Span>,
+    /// The number the patch was given when it was committed (see `EventMgmt::committed`)
+    pub id: usize,
+}
+
+/// For managing events for purposes of undo and redo
+#[derive(Default, Debug, Clone)]
 pub struct EventMgmt {
     /// The patch is the current sequence of editing actions
     pub patch: Vec<Event>,
     /// Undo contains all the patches that have been applied
-    pub undo: Vec<Vec<Event>>,
+    undo: Vec<Patch>,
     /// Redo contains all the patches that have been undone
-    pub redo: Vec<Vec<Event>>,
+    redo: Vec<Patch>,
+    /// When patches are committed automatically
+    pub policy: GroupingPolicy,
+    /// The time the last event was executed, for grouping by time
+    pub last_event: Option<Instant>,
+    /// The selection when the current patch was started
+    pub patch_selection: Option<Span>,
+    /// The longest pause between events for them to count as one burst, such as a paste,
+    /// or `None` to not look for bursts
+    pub paste_gap: Option<Duration>,
+    /// The number of events in the current burst, after the first one
+    pub burst: usize,
+    /// How many batches of edits are in progress (see `EventMgmt::begin_batch`)
+    pub batch: usize,
+    /// The number of patches that have been committed, which only ever goes up, so that
+    /// patches can be told apart after others have been undone or merged
+    pub committed: usize,
+}
+
+impl PartialEq for EventMgmt {
+    fn eq(&self, other: &Self) -> bool {
+        // When the last event happened doesn't change what would be undone or redone
+        self.patch == other.patch
+            && self.undo == other.undo
+            && self.redo == other.redo
+            && self.policy == other.policy
+            && self.patch_selection == other.patch_selection
+            && self.paste_gap == other.paste_gap
+            && self.burst == other.burst
+            && self.batch == other.batch
+            && self.committed == other.committed
+    }
 }
 
+impl Eq for EventMgmt {}
+
 impl EventMgmt {
     /// Register that an event has occurred with the event manager
     pub fn register(&mut self, ev: Event) {
@@ -98,9 +287,89 @@ impl EventMgmt {
     /// make it so that every undo action would remove the previous word the user typed.
     pub fn commit(&mut self) {
         if !self.patch.is_empty() {
-            let mut patch = vec![];
-            std::mem::swap(&mut self.patch, &mut patch);
-            self.undo.push(patch);
+            self.undo.push(Patch {
+                events: std::mem::take(&mut self.patch),
+                selection: self.patch_selection.take(),
+                id: self.committed,
+            });
+            self.committed += 1;
+        }
+    }
+
+    /// Start a batch of edits that should be undone in one step, committing the current patch.
+    /// The grouping policy is suspended until the batch is ended with `EventMgmt::end_batch`.
+    /// Batches can be nested, in which case the patch is only committed by the outermost one.
+    pub fn begin_batch(&mut self) {
+        if self.batch == 0 {
+            self.commit();
+        }
+        self.batch += 1;
+    }
+
+    /// End a batch of edits started with `EventMgmt::begin_batch`, committing them as one patch
+    pub fn end_batch(&mut self) {
+        self.batch = self.batch.saturating_sub(1);
+        if self.batch == 0 {
+            self.commit();
+        }
+    }
+
+    /// Commit the current patch if the grouping policy says a new one should start with an event
+    /// executed at a certain time (after a pause in editing)
+    /// Nothing is committed while a batch of edits is in progress.
+    pub fn group_before(&mut self, now: Instant) {
+        if self.batch > 0 {
+            return;
+        }
+        // Events that follow each other very quickly are one burst, which ends with a pause
+        if self.in_burst(now) {
+            self.burst += 1;
+        } else {
+            if self.is_paste() {
+                self.commit();
+            }
+            self.burst = 0;
+        }
+        if let GroupingPolicy::Time(gap) = self.policy {
+            if self.last_event.is_some_and(|last| now.saturating_duration_since(last) > gap) {
+                self.commit();
+            }
+        }
+        self.last_event = Some(now);
+    }
+
+    /// Commit the current patch if the grouping policy says it ends after an event with a
+    /// certain status (see `Document::exe`)
+    /// Nothing is committed part way through a burst, so that a paste is undone in one step,
+    /// or part way through a batch of edits.
+    pub fn group_after(&mut self, status: &Status) {
+        if self.burst > 0 || self.batch > 0 {
+            return;
+        }
+        let end = match self.policy {
+            GroupingPolicy::Word => status.suggests_commit(),
+            GroupingPolicy::Line => *status == Status::StructuralChange,
+            GroupingPolicy::Count(count) => self.patch.len() >= count,
+            GroupingPolicy::Manual | GroupingPolicy::Time(_) => false,
+        };
+        if end {
+            self.commit();
+        }
+    }
+
+    /// Returns true if the current burst has enough events in it to be a paste (or very fast
+    /// input) rather than typing
+    #[must_use]
+    pub fn is_paste(&self) -> bool {
+        self.burst + 1 >= PASTE_EVENTS
+    }
+
+    /// Returns true if an event at a certain time would carry on the current burst
+    #[must_use]
+    pub fn in_burst(&self, now: Instant) -> bool {
+        match (self.paste_gap, self.last_event) {
+            (Some(gap), Some(last)) => now.saturating_duration_since(last) <= gap,
+            _ => false,
         }
     }
 
@@ -108,8 +377,9 @@ impl EventMgmt {
     /// of undoing (you'll need to reverse the events themselves manually)
     pub fn undo(&mut self) -> Option<Vec<Event>> {
         self.commit();
-        let mut ev = self.undo.pop()?;
-        self.redo.push(ev.clone());
+        let patch = self.undo.pop()?;
+        let mut ev = patch.events.clone();
+        self.redo.push(patch);
         ev.reverse();
         Some(ev)
     }
@@ -118,11 +388,54 @@ impl EventMgmt {
     /// redoing
     pub fn redo(&mut self) -> Option<Vec<Event>> {
         self.commit();
-        let ev = self.redo.pop()?;
-        self.undo.push(ev.clone());
+        let patch = self.redo.pop()?;
+        let ev = patch.events.clone();
+        self.undo.push(patch);
         Some(ev)
     }
 
+    /// Combine the last `n` patches on the undo stack into one, after committing the current
+    /// patch, so that a composite operation (such as pasting and then formatting what was
+    /// pasted) can be grouped after it has been made and undone in one step.
+    /// Undoing the combined patch restores the selection from before the first of them.
+    /// Returns false if there were fewer than two patches to combine.
+    pub fn merge_last(&mut self, n: usize) -> bool {
+        self.commit();
+        let n = n.min(self.undo.len());
+        if n < 2 {
+            return false;
+        }
+        let start = self.undo.len() - n;
+        let merged: Vec<Patch> = self.undo.drain(start..).collect();
+        self.undo.push(Patch {
+            selection: merged[0].selection,
+            events: merged.into_iter().flat_map(|p| p.events).collect(),
+            // The combined patch counts as a new one
+            id: self.committed,
+        });
+        self.committed += 1;
+        true
+    }
+
+    /// Count the patches at the top of the undo stack that were committed after a number of
+    /// patches had been (see `EventMgmt::committed`), such as the patches made since then
+    #[must_use]
+    pub fn committed_since(&self, committed: usize) -> usize {
+        self.undo.iter().rev().take_while(|p| p.id >= committed).count()
+    }
+
+    /// Get the patches that can be undone, with the most recent last
+    #[must_use]
+    pub fn undo_stack(&self) -> &[Patch] {
+        &self.undo
+    }
+
+    /// Get the patches that can be redone, with the next one to redo last
+    #[must_use]
+    pub fn redo_stack(&self) -> &[Patch] {
+        &self.redo
+    }
+
     /// Returns true if the undo stack is empty, meaning no patches have been applied
     #[must_use]
     pub fn is_undo_empty(&self) -> bool {
@@ -146,7 +459,7 @@ impl EventMgmt {
     #[must_use]
     pub fn last(&self) -> Option<&Event> {
         if self.patch.is_empty() {
-            self.undo.last().and_then(|u| u.last())
+            self.undo.last().and_then(|u| u.events.last())
         } else {
             self.patch.last()
         }
@@ -0,0 +1,267 @@
+/// ffi.rs - a stable C ABI for embedding kaolinite in programs not written in Rust
+///
+/// Build a C compatible library with
+/// `cargo rustc --release --features ffi --crate-type cdylib`
+/// and include `include/kaolinite.h` to use it.
+///
+/// Documents are handed out as opaque pointers which must be released with
+/// `kaolinite_document_free`. Strings returned by this module are owned by the caller and must be
+/// released with `kaolinite_string_free`.
+///
+/// Panics never cross into the caller. A call that panics returns `KAOLINITE_ERR` (or a null
+/// pointer, or a default value) instead.
+use crate::document::Document;
+use crate::event::Event;
+use crate::utils::{Loc, Size};
+use std::ffi::{c_char, c_int, CStr, CString};
+use std::panic::{self, AssertUnwindSafe};
+use std::ptr;
+
+/// Return code for a successful call
+pub const KAOLINITE_OK: c_int = 0;
+/// Return code for a call that failed
+pub const KAOLINITE_ERR: c_int = -1;
+
+/// The kinds of editing events that can be executed through the C ABI
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KaoliniteEventKind {
+    Insert = 0,
+    Delete = 1,
+    InsertLine = 2,
+    DeleteLine = 3,
+    SplitDown = 4,
+    SpliceUp = 5,
+}
+
+impl TryFrom<c_int> for KaoliniteEventKind {
+    type Error = ();
+
+    fn try_from(kind: c_int) -> Result<Self, ()> {
+        Ok(match kind {
+            0 => Self::Insert,
+            1 => Self::Delete,
+            2 => Self::InsertLine,
+            3 => Self::DeleteLine,
+            4 => Self::SplitDown,
+            5 => Self::SpliceUp,
+            _ => return Err(()),
+        })
+    }
+}
+
+/// A location that can be passed across the C ABI
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct KaoliniteLoc {
+    pub x: usize,
+    pub y: usize,
+}
+
+impl From<Loc> for KaoliniteLoc {
+    fn from(loc: Loc) -> Self {
+        Self { x: loc.x, y: loc.y }
+    }
+}
+
+/// Create a new, empty document with no file name
+#[no_mangle]
+pub extern "C" fn kaolinite_document_new(w: usize, h: usize) -> *mut Document {
+    Box::into_raw(Box::new(Document::new(Size::is(w, h))))
+}
+
+/// Open a document from a file, returning a null pointer if it failed to open
+/// # Safety
+/// `path` must be a valid, null terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn kaolinite_document_open(
+    path: *const c_char,
+    w: usize,
+    h: usize,
+) -> *mut Document {
+    let Some(path) = to_str(path) else { return ptr::null_mut() };
+    guard(ptr::null_mut(), || match Document::open(Size::is(w, h), path) {
+        Ok(mut doc) => {
+            doc.load_to(h);
+            Box::into_raw(Box::new(doc))
+        }
+        Err(_) => ptr::null_mut(),
+    })
+}
+
+/// Release a document
+/// # Safety
+/// `doc` must have been created by this module and must not be used afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn kaolinite_document_free(doc: *mut Document) {
+    if !doc.is_null() {
+        drop(Box::from_raw(doc));
+    }
+}
+
+/// Execute an editing event on a document, where `kind` is one of `KaoliniteEventKind`.
+/// `x` is ignored for line events and `text` is ignored for split and splice events.
+/// The lines the event touches are loaded first.
+/// Returns `KAOLINITE_ERR` for an unknown kind of event.
+/// # Safety
+/// `doc` must be a valid document and `text` must be a valid, null terminated string
+/// (or null when not required by the event kind).
+#[no_mangle]
+pub unsafe extern "C" fn kaolinite_document_exe(
+    doc: *mut Document,
+    kind: c_int,
+    x: usize,
+    y: usize,
+    text: *const c_char,
+) -> c_int {
+    let Some(doc) = doc.as_mut() else { return KAOLINITE_ERR };
+    let Ok(kind) = KaoliniteEventKind::try_from(kind) else { return KAOLINITE_ERR };
+    let loc = Loc::at(x, y);
+    let text = to_str(text).unwrap_or_default().to_string();
+    let ev = match kind {
+        KaoliniteEventKind::Insert => Event::Insert(loc, text),
+        KaoliniteEventKind::Delete => Event::Delete(loc, text),
+        KaoliniteEventKind::InsertLine => Event::InsertLine(y, text),
+        KaoliniteEventKind::DeleteLine => Event::DeleteLine(y, text),
+        KaoliniteEventKind::SplitDown => Event::SplitDown(loc),
+        KaoliniteEventKind::SpliceUp => Event::SpliceUp(loc),
+    };
+    guard(KAOLINITE_ERR, || {
+        // Splicing joins the line below, so that has to be loaded too
+        doc.load_to(y.saturating_add(2));
+        status(doc.exe(ev).is_ok())
+    })
+}
+
+/// Commit the current patch on a document, making it a single undo step
+/// # Safety
+/// `doc` must be a valid document.
+#[no_mangle]
+pub unsafe extern "C" fn kaolinite_document_commit(doc: *mut Document) -> c_int {
+    let Some(doc) = doc.as_mut() else { return KAOLINITE_ERR };
+    guard(KAOLINITE_ERR, || {
+        doc.event_mgmt.commit();
+        KAOLINITE_OK
+    })
+}
+
+/// Undo the last patch on a document
+/// # Safety
+/// `doc` must be a valid document.
+#[no_mangle]
+pub unsafe extern "C" fn kaolinite_document_undo(doc: *mut Document) -> c_int {
+    let Some(doc) = doc.as_mut() else { return KAOLINITE_ERR };
+    guard(KAOLINITE_ERR, || status(doc.undo().is_ok()))
+}
+
+/// Redo the last patch on a document
+/// # Safety
+/// `doc` must be a valid document.
+#[no_mangle]
+pub unsafe extern "C" fn kaolinite_document_redo(doc: *mut Document) -> c_int {
+    let Some(doc) = doc.as_mut() else { return KAOLINITE_ERR };
+    guard(KAOLINITE_ERR, || status(doc.redo().is_ok()))
+}
+
+/// Save a document back to the file it was opened from
+/// # Safety
+/// `doc` must be a valid document.
+#[no_mangle]
+pub unsafe extern "C" fn kaolinite_document_save(doc: *mut Document) -> c_int {
+    let Some(doc) = doc.as_mut() else { return KAOLINITE_ERR };
+    guard(KAOLINITE_ERR, || status(doc.save().is_ok()))
+}
+
+/// Load lines of a document up to a specified index
+/// # Safety
+/// `doc` must be a valid document.
+#[no_mangle]
+pub unsafe extern "C" fn kaolinite_document_load_to(doc: *mut Document, to: usize) {
+    if let Some(doc) = doc.as_mut() {
+        guard((), || doc.load_to(to));
+    }
+}
+
+/// Get the text of a line, returning a null pointer if the line doesn't exist.
+/// The string must be released with `kaolinite_string_free`.
+/// # Safety
+/// `doc` must be a valid document.
+#[no_mangle]
+pub unsafe extern "C" fn kaolinite_document_line(doc: *const Document, y: usize) -> *mut c_char {
+    let Some(doc) = doc.as_ref() else { return ptr::null_mut() };
+    guard(ptr::null_mut(), || {
+        doc.line(y)
+            .and_then(|line| CString::new(line).ok())
+            .map_or(ptr::null_mut(), CString::into_raw)
+    })
+}
+
+/// Get the number of lines in a document
+/// # Safety
+/// `doc` must be a valid document.
+#[no_mangle]
+pub unsafe extern "C" fn kaolinite_document_len_lines(doc: *const Document) -> usize {
+    doc.as_ref().map_or(0, |doc| guard(0, || doc.len_lines()))
+}
+
+/// Get the position of the cursor on the screen
+/// # Safety
+/// `doc` must be a valid document.
+#[no_mangle]
+pub unsafe extern "C" fn kaolinite_document_cursor(doc: *const Document) -> KaoliniteLoc {
+    let Some(doc) = doc.as_ref() else { return KaoliniteLoc::default() };
+    guard(KaoliniteLoc::default(), || doc.cursor.into())
+}
+
+/// Get the position of the cursor within the document, with x being the character index
+/// # Safety
+/// `doc` must be a valid document.
+#[no_mangle]
+pub unsafe extern "C" fn kaolinite_document_char_loc(doc: *const Document) -> KaoliniteLoc {
+    let Some(doc) = doc.as_ref() else { return KaoliniteLoc::default() };
+    guard(KaoliniteLoc::default(), || doc.char_loc().into())
+}
+
+/// Move the cursor to a location, with x being the character index
+/// # Safety
+/// `doc` must be a valid document.
+#[no_mangle]
+pub unsafe extern "C" fn kaolinite_document_goto(doc: *mut Document, x: usize, y: usize) {
+    if let Some(doc) = doc.as_mut() {
+        guard((), || doc.goto(&Loc::at(x, y)));
+    }
+}
+
+/// Release a string that was returned by this module
+/// # Safety
+/// `st` must have been returned by this module and must not be used afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn kaolinite_string_free(st: *mut c_char) {
+    if !st.is_null() {
+        drop(CString::from_raw(st));
+    }
+}
+
+/// Convert a C string into a string slice, returning None if it is null or invalid
+unsafe fn to_str<'a>(st: *const c_char) -> Option<&'a str> {
+    if st.is_null() {
+        None
+    } else {
+        CStr::from_ptr(st).to_str().ok()
+    }
+}
+
+/// Run the body of an entry point, returning a fallback instead of letting a panic unwind into
+/// the caller (which would abort the whole program)
+fn guard<T>(fallback: T, body: impl FnOnce() -> T) -> T {
+    panic::catch_unwind(AssertUnwindSafe(body)).unwrap_or(fallback)
+}
+
+/// Convert a success flag into a return code
+fn status(ok: bool) -> c_int {
+    if ok {
+        KAOLINITE_OK
+    } else {
+        KAOLINITE_ERR
+    }
+}
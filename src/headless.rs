@@ -0,0 +1,179 @@
+/// headless.rs - drives and draws documents without a terminal, for end to end tests
+use crate::commands::Commands;
+use crate::document::Document;
+use crate::event::{Event, Result};
+use crate::keymap::{KeyResult, Keymap};
+use crate::layout::{Layout, LayoutOptions};
+use crate::utils::{pad_to, truncate, Align, Size};
+use std::fmt;
+
+/// A single piece of input, as a user would give it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Step {
+    /// Type some text, a character at a time (newlines split the line, like the enter key)
+    Type(String),
+    /// Press a key, which is looked up in the driver's keymap
+    Key(String),
+    /// Run a command line from the command registry (e.g. `goto 3 1`)
+    Command(String),
+}
+
+impl Step {
+    /// Read a script, with one step on each line.
+    /// Lines starting with `type ` type the rest of the line (where `\n` and `\t` stand for a
+    /// newline and a tab), lines starting with `key ` press a key, and any other line is run as a
+    /// command. Blank lines and lines starting with `#` are skipped.
+    #[must_use]
+    pub fn parse_script(script: &str) -> Vec<Step> {
+        script
+            .lines()
+            .map(str::trim_start)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .map(|line| {
+                if let Some(text) = line.strip_prefix("type ") {
+                    Step::Type(text.replace("\\n", "\n").replace("\\t", "\t"))
+                } else if let Some(key) = line.strip_prefix("key ") {
+                    Step::Key(key.trim().to_string())
+                } else {
+                    Step::Command(line.trim_end().to_string())
+                }
+            })
+            .collect()
+    }
+}
+
+impl fmt::Display for Step {
+    /// Displays as a line of a script (see `Step::parse_script`)
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Step::Type(text) => {
+                write!(f, "type {}", text.replace('\n', "\\n").replace('\t', "\\t"))
+            }
+            Step::Key(key) => write!(f, "key {key}"),
+            Step::Command(line) => write!(f, "{line}"),
+        }
+    }
+}
+
+/// Feeds input into a document the way an editor would, without needing a terminal.
+/// Every step is recorded, so that a session can be saved as a script and played back later.
+#[derive(Debug, Clone)]
+pub struct Driver {
+    /// The document being driven
+    pub doc: Document,
+    /// The commands that command steps and keys can run
+    pub commands: Commands,
+    /// The keybindings that key steps are looked up in, which map key names to command lines
+    pub keymap: Keymap<String>,
+    /// The steps that have been performed so far
+    pub recording: Vec<Step>,
+}
+
+impl Driver {
+    /// Start driving a document, with the default commands and no keybindings
+    #[must_use]
+    pub fn new(doc: Document) -> Self {
+        Self {
+            doc,
+            commands: Commands::default(),
+            keymap: Keymap::new("normal"),
+            recording: vec![],
+        }
+    }
+
+    /// Perform a step, recording it.
+    /// Keys that are unbound, or only part of a longer binding so far, do nothing.
+    /// # Errors
+    /// Returns an error if an edit or command failed.
+    pub fn step(&mut self, step: Step) -> Result<()> {
+        match &step {
+            Step::Type(text) => self.type_text(text)?,
+            Step::Key(key) => {
+                if let KeyResult::Command(line) = self.keymap.feed(key.clone()) {
+                    self.commands.run_line(&mut self.doc, &line)?;
+                }
+            }
+            Step::Command(line) => {
+                self.commands.run_line(&mut self.doc, line)?;
+            }
+        }
+        self.recording.push(step);
+        Ok(())
+    }
+
+    /// Perform several steps in order, stopping at the first one that fails
+    /// # Errors
+    /// Returns an error if an edit or command failed.
+    pub fn play<I: IntoIterator<Item = Step>>(&mut self, steps: I) -> Result<()> {
+        steps.into_iter().try_for_each(|step| self.step(step))
+    }
+
+    /// Perform the steps of a script (see `Step::parse_script`)
+    /// # Errors
+    /// Returns an error if an edit or command failed.
+    pub fn play_script(&mut self, script: &str) -> Result<()> {
+        self.play(Step::parse_script(script))
+    }
+
+    /// Write out the steps performed so far as a script, which can be played back later
+    #[must_use]
+    pub fn script(&self) -> String {
+        self.recording.iter().map(|step| step.to_string() + "\n").collect()
+    }
+
+    /// Get the full text of the document
+    #[must_use]
+    pub fn text(&self) -> String {
+        self.doc.file.to_string()
+    }
+
+    /// Draw the document as it would be shown on a terminal (see `snapshot`)
+    #[must_use]
+    pub fn snapshot(&self, terminal: Size, options: &LayoutOptions, status: &str) -> String {
+        snapshot(&self.doc, terminal, options, status)
+    }
+
+    /// Type text into the document at the cursor, a character at a time
+    fn type_text(&mut self, text: &str) -> Result<()> {
+        for ch in text.chars() {
+            // Typing on the empty line at the bottom of the document creates it
+            let y = self.doc.loc().y;
+            if y == self.doc.len_lines() {
+                self.doc.exe(Event::InsertLine(y, String::new()))?;
+            }
+            let loc = self.doc.char_loc();
+            if ch == '\n' {
+                self.doc.exe(Event::SplitDown(loc))?;
+            } else {
+                self.doc.exe(Event::Insert(loc, ch.to_string()))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Draw a document as it would be shown on a terminal, as plain text, for comparing against a
+/// golden file in tests.
+/// The viewport is laid out as in `Layout::new`, with the gutter drawn by `Layout::gutter_render`
+/// and the status line template (see `StatusInfo::format`) on the first line below
+/// the document. Every line of the result is exactly as wide as the terminal, and lines that
+/// aren't loaded are drawn empty.
+#[must_use]
+pub fn snapshot(doc: &Document, terminal: Size, options: &LayoutOptions, status: &str) -> String {
+    let layout = Layout::new(terminal, doc, options);
+    let blank = " ".repeat(terminal.w);
+    let mut rows = vec![blank.clone(); layout.origin.y];
+    for row in 0..layout.size.h {
+        let y = doc.offset.y + row;
+        let gutter = layout.gutter_render(doc, options, y);
+        let text = doc.render_window(y, doc.offset.x, layout.size.w);
+        rows.push(format!("{gutter}{}", text.unwrap_or_else(|| " ".repeat(layout.size.w))));
+    }
+    if options.bottom_lines > 0 && rows.len() < terminal.h {
+        let status = doc.status_info().format(status);
+        let status = truncate(&status, terminal.w, "…", doc.tab_width);
+        rows.push(pad_to(&status, terminal.w, Align::Left, doc.tab_width));
+    }
+    rows.resize(terminal.h, blank);
+    rows.into_iter().map(|row| row + "\n").collect()
+}
@@ -0,0 +1,177 @@
+/// jobs.rs - background work that can be done a little at a time, in between rendering frames
+use crate::completion::WordIndex;
+use crate::document::Document;
+use crate::event::Result;
+use crate::searching::{Match, Searcher};
+use crate::utils::Loc;
+use regex::Regex;
+use ropey::Rope;
+use std::any::Any;
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+/// The number of lines a built in job works through in each step
+const LINES_PER_STEP: usize = 256;
+
+/// A piece of work that can be done in small steps, so that it doesn't hold up the editor
+pub trait Job: Any {
+    /// Do a small amount of the work, returning true once the job is finished
+    fn step(&mut self) -> bool;
+}
+
+/// Runs jobs in turn, for a limited amount of time each time it is called.
+/// Editors can call `Scheduler::run_for` once per frame (e.g. with a budget of 2ms) to keep
+/// rendering smooth while large files are processed.
+#[derive(Default)]
+pub struct Scheduler {
+    /// The jobs that are still running, and their ids
+    pub queue: VecDeque<(usize, Box<dyn Job>)>,
+    /// The jobs that have finished, and their ids
+    pub finished: Vec<(usize, Box<dyn Job>)>,
+    /// The id to give to the next job
+    next_id: usize,
+}
+
+impl std::fmt::Debug for Scheduler {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Scheduler")
+            .field("queue", &self.queue.iter().map(|(id, _)| id).collect::<Vec<_>>())
+            .field("finished", &self.finished.iter().map(|(id, _)| id).collect::<Vec<_>>())
+            .finish_non_exhaustive()
+    }
+}
+
+impl Scheduler {
+    /// Create a new scheduler, with no jobs
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a job to the end of the queue, returning its id
+    pub fn add<J: Job>(&mut self, job: J) -> usize {
+        let id = self.next_id;
+        self.next_id += 1;
+        self.queue.push_back((id, Box::new(job)));
+        id
+    }
+
+    /// Stop a job that hasn't finished yet, returning true if it was found
+    pub fn cancel(&mut self, id: usize) -> bool {
+        let before = self.queue.len();
+        self.queue.retain(|(i, _)| *i != id);
+        self.queue.len() != before
+    }
+
+    /// Determine if there are any jobs left to run
+    #[must_use]
+    pub fn is_idle(&self) -> bool {
+        self.queue.is_empty()
+    }
+
+    /// Take turns stepping through the jobs until they are all finished or the time is up.
+    /// At least one step is always taken, so that jobs make progress however small the budget.
+    /// Returns true if every job is finished.
+    pub fn run_for(&mut self, budget: Duration) -> bool {
+        let start = Instant::now();
+        while let Some((id, mut job)) = self.queue.pop_front() {
+            if job.step() {
+                self.finished.push((id, job));
+            } else {
+                self.queue.push_back((id, job));
+            }
+            if start.elapsed() >= budget {
+                break;
+            }
+        }
+        self.is_idle()
+    }
+
+    /// Take a finished job out of the scheduler, so that its results can be used.
+    /// Returns `None` if the job hasn't finished, or isn't of the type asked for.
+    pub fn take<J: Job>(&mut self, id: usize) -> Option<J> {
+        let idx = self.finished.iter().position(|(i, job)| {
+            *i == id && (job.as_ref() as &dyn Any).is::<J>()
+        })?;
+        let (_, job) = self.finished.remove(idx);
+        (job as Box<dyn Any>).downcast::<J>().ok().map(|j| *j)
+    }
+}
+
+/// Builds a word index of a document (for word completion) a chunk of lines at a time
+#[derive(Debug, Clone)]
+pub struct WordIndexJob {
+    /// The contents of the document when the job was started
+    pub text: Rope,
+    /// The next line to index
+    pub line: usize,
+    /// The words found so far
+    pub index: WordIndex,
+}
+
+impl WordIndexJob {
+    /// Start indexing a document.
+    /// Later edits to the document aren't picked up by the job.
+    #[must_use]
+    pub fn new(doc: &Document) -> Self {
+        Self { text: doc.file.clone(), line: 0, index: WordIndex::default() }
+    }
+}
+
+impl Job for WordIndexJob {
+    fn step(&mut self) -> bool {
+        let len = doc_lines(&self.text);
+        let end = (self.line + LINES_PER_STEP).min(len);
+        for line in self.text.lines_at(self.line).take(end - self.line) {
+            self.index.add_line(&line.to_string());
+        }
+        self.line = end;
+        self.line >= len
+    }
+}
+
+/// Finds every match of a regular expression in a document a chunk of lines at a time
+#[derive(Debug, Clone)]
+pub struct SearchJob {
+    /// The contents of the document when the job was started
+    pub text: Rope,
+    /// The regular expression being searched for
+    pub re: Regex,
+    /// The next line to search
+    pub line: usize,
+    /// The matches found so far, in order
+    pub matches: Vec<Match>,
+}
+
+impl SearchJob {
+    /// Start searching a document.
+    /// Later edits to the document aren't picked up by the job.
+    /// # Errors
+    /// Returns an error if the regex isn't valid.
+    pub fn new(doc: &Document, regex: &str) -> Result<Self> {
+        Ok(Self { text: doc.file.clone(), re: Regex::new(regex)?, line: 0, matches: vec![] })
+    }
+}
+
+impl Job for SearchJob {
+    fn step(&mut self) -> bool {
+        let len = doc_lines(&self.text);
+        let end = (self.line + LINES_PER_STEP).min(len);
+        for (y, line) in self.text.lines_at(self.line).take(end - self.line).enumerate() {
+            let line = line.to_string();
+            let line = line.trim_end_matches(['\n', '\r']);
+            self.matches.extend(self.re.find_iter(line).map(|m| Match {
+                loc: Loc::at(Searcher::raw_to_char(m.start(), line), self.line + y),
+                text: m.as_str().to_string(),
+            }));
+        }
+        self.line = end;
+        self.line >= len
+    }
+}
+
+/// The number of lines in a document's contents.
+/// The rope has an empty line after its final newline, which isn't part of the document.
+fn doc_lines(text: &Rope) -> usize {
+    text.len_lines().saturating_sub(1)
+}
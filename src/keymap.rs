@@ -0,0 +1,133 @@
+/// keymap.rs - a UI agnostic keybinding map, with support for modes and multi-key chords
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::time::{Duration, Instant};
+
+/// The outcome of feeding a key into a keymap
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum KeyResult {
+    /// The key sequence completed a binding, holding the name of the command to run
+    Command(String),
+    /// The key sequence is the start of a binding, so more keys are expected
+    Pending,
+    /// The key sequence isn't bound to anything
+    Unbound,
+}
+
+/// Maps key sequences to command names, with a separate set of bindings for each mode.
+/// The key type is generic so that it can be used with any front end (crossterm, termion, etc).
+/// Command names are intended to be looked up in a `Commands` registry.
+#[derive(Debug, Clone)]
+pub struct Keymap<K> {
+    /// The bindings for each mode
+    pub bindings: HashMap<String, HashMap<Vec<K>, String>>,
+    /// The mode that is currently active
+    pub mode: String,
+    /// The keys that have been fed so far, which form the start of a binding
+    pub pending: Vec<K>,
+    /// The time that the last key was fed
+    pub last_key: Option<Instant>,
+    /// How long to wait for the next key in a sequence before giving up on it
+    pub timeout: Duration,
+}
+
+impl<K: Clone + Eq + Hash> Keymap<K> {
+    /// Create a new, empty keymap starting in a specified mode
+    pub fn new<S: Into<String>>(mode: S) -> Self {
+        Self {
+            bindings: HashMap::default(),
+            mode: mode.into(),
+            pending: vec![],
+            last_key: None,
+            timeout: Duration::from_secs(1),
+        }
+    }
+
+    /// Bind a key sequence to a command in a certain mode
+    pub fn bind<S: Into<String>, C: Into<String>>(&mut self, mode: S, keys: &[K], command: C) {
+        self.bindings
+            .entry(mode.into())
+            .or_default()
+            .insert(keys.to_vec(), command.into());
+    }
+
+    /// Remove a binding from a certain mode
+    pub fn unbind(&mut self, mode: &str, keys: &[K]) {
+        if let Some(map) = self.bindings.get_mut(mode) {
+            map.remove(keys);
+        }
+    }
+
+    /// Switch to another mode, abandoning any pending key sequence
+    pub fn set_mode<S: Into<String>>(&mut self, mode: S) {
+        self.mode = mode.into();
+        self.reset();
+    }
+
+    /// Abandon any pending key sequence
+    pub fn reset(&mut self) {
+        self.pending.clear();
+        self.last_key = None;
+    }
+
+    /// Feed a key into the keymap
+    pub fn feed(&mut self, key: K) -> KeyResult {
+        self.feed_at(key, Instant::now())
+    }
+
+    /// Feed a key into the keymap, as if it were pressed at a certain time.
+    /// If the previous key was pressed longer ago than the timeout, the pending sequence is
+    /// abandoned before this key is processed.
+    pub fn feed_at(&mut self, key: K, now: Instant) -> KeyResult {
+        if self.timed_out(now) {
+            self.reset();
+        }
+        self.pending.push(key);
+        self.last_key = Some(now);
+        let exact = self.lookup(&self.pending).cloned();
+        if self.is_prefix(&self.pending) {
+            // Wait for more keys, even if this sequence is bound on its own
+            KeyResult::Pending
+        } else {
+            self.reset();
+            exact.map_or(KeyResult::Unbound, KeyResult::Command)
+        }
+    }
+
+    /// Check whether a pending sequence has timed out, should be called periodically while a
+    /// sequence is pending. If a shorter binding is waiting on a longer one, it is resolved here.
+    pub fn check_timeout(&mut self, now: Instant) -> Option<KeyResult> {
+        if !self.timed_out(now) {
+            return None;
+        }
+        let exact = self.lookup(&self.pending).cloned();
+        self.reset();
+        Some(exact.map_or(KeyResult::Unbound, KeyResult::Command))
+    }
+
+    /// Returns true if a sequence is pending
+    #[must_use]
+    pub fn is_pending(&self) -> bool {
+        !self.pending.is_empty()
+    }
+
+    /// Look up the command bound to a key sequence in the current mode
+    #[must_use]
+    pub fn lookup(&self, keys: &[K]) -> Option<&String> {
+        self.bindings.get(&self.mode)?.get(keys)
+    }
+
+    /// Determine if a key sequence is the start of a longer binding in the current mode
+    #[must_use]
+    pub fn is_prefix(&self, keys: &[K]) -> bool {
+        self.bindings.get(&self.mode).is_some_and(|map| {
+            map.keys().any(|k| k.len() > keys.len() && k.starts_with(keys))
+        })
+    }
+
+    /// Returns true if the pending sequence has been waiting for longer than the timeout
+    fn timed_out(&self, now: Instant) -> bool {
+        self.last_key
+            .is_some_and(|last| now.saturating_duration_since(last) > self.timeout)
+    }
+}
@@ -0,0 +1,84 @@
+/// layout.rs - works out where the document viewport sits on the screen, around the gutter and bars
+use crate::document::Document;
+use crate::utils::{pad_to, trim, width, Align, Loc, Size};
+
+/// Preferences for the parts of the screen surrounding the document
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct LayoutOptions {
+    /// Whether line numbers are shown in the gutter
+    pub line_numbers: bool,
+    /// Whether a column is kept at the start of the gutter for signs, such as version control
+    /// markers or diagnostics (see `Document::sign`)
+    pub sign_column: bool,
+    /// Text drawn in the gutter after the line numbers (e.g. ` │`)
+    pub separator: &'static str,
+    /// Extra columns at the end of the gutter, after the separator
+    pub gutter_padding: usize,
+    /// Lines reserved above the document (e.g. a tab bar)
+    pub top_lines: usize,
+    /// Lines reserved below the document (e.g. a status line)
+    pub bottom_lines: usize,
+}
+
+/// The position and size of the document viewport on the screen
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Layout {
+    /// The width of the gutter to the left of the document
+    pub gutter: usize,
+    /// The position of the top left corner of the viewport
+    pub origin: Loc,
+    /// The size of the viewport, which should be used as the size of the document
+    pub size: Size,
+}
+
+impl Layout {
+    /// Work out the layout of a document within a terminal of a certain size
+    #[must_use]
+    pub fn new(terminal: Size, doc: &Document, options: &LayoutOptions) -> Self {
+        let numbers = if options.line_numbers { doc.len_lines().to_string().len() } else { 0 };
+        let signs = usize::from(options.sign_column);
+        let separator = width(options.separator, doc.tab_width);
+        let gutter = (signs + numbers + separator + options.gutter_padding).min(terminal.w);
+        let origin = Loc::at(gutter, options.top_lines.min(terminal.h));
+        let size = Size::is(
+            terminal.w - gutter,
+            terminal.h.saturating_sub(options.top_lines + options.bottom_lines),
+        );
+        Self { gutter, origin, size }
+    }
+
+    /// Set the size of a document to match the viewport
+    pub fn apply(&self, doc: &mut Document) {
+        doc.size = self.size;
+    }
+
+    /// Get the position of the cursor of a document on the screen
+    #[must_use]
+    pub fn cursor(&self, doc: &Document) -> Loc {
+        Loc::at(self.origin.x + doc.cursor.x, self.origin.y + doc.cursor.y)
+    }
+
+    /// Get the whole of the gutter for a line of the document, exactly as wide as the gutter:
+    /// the sign (if there is a sign column), the line number (if they are shown) and then the
+    /// separator. Lines past the end of the document are numbered with `~`.
+    #[must_use]
+    pub fn gutter_render(&self, doc: &Document, options: &LayoutOptions, y: usize) -> String {
+        let mut gutter = String::new();
+        if options.sign_column {
+            gutter.push(doc.sign(y).unwrap_or(' '));
+        }
+        if options.line_numbers {
+            gutter.push_str(&doc.line_number(y));
+        }
+        gutter.push_str(options.separator);
+        let gutter = trim(&gutter, 0, self.gutter, doc.tab_width);
+        pad_to(&gutter, self.gutter, Align::Left, doc.tab_width)
+    }
+
+    /// Determine if a position on the screen is within the viewport
+    #[must_use]
+    pub fn contains(&self, loc: &Loc) -> bool {
+        (self.origin.x..self.origin.x + self.size.w).contains(&loc.x)
+            && (self.origin.y..self.origin.y + self.size.h).contains(&loc.y)
+    }
+}
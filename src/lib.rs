@@ -12,8 +12,19 @@
 //! - Undo & Redo
 //! - Moving around the document, by word, page, character or other means
 //! - Searching & Replacing
+//! - Word completion
+//! - Jumping to definitions, using ctags files or a simple symbol index
 //! - Handles tabs, different line endings and double width characters perfectly
 //! - File buffering for larger files
+//! - Background jobs (such as indexing and searching) that can be done a little at a time
+//! - Opening documents from memory, for platforms without a file system (such as WebAssembly)
+//! - Browsing directories
+//! - Filling new files from templates
+//! - Managing several open documents, with search across all of them
+//! - Scripted input, for testing editors end to end without a terminal
+//! - Finding strings and comments on a line, for matching brackets and toggling comments
+//! - Anchoring signs and other markers to lines, so they follow their lines through edits
+//! - Placing the cursor and scrolling the terminal with crossterm (with the `terminal` feature)
 //!
 //! It removes a lot of complexity from your text editor and allows the creation of an advanced
 //! text editor in very few lines of idiomatic code.
@@ -26,11 +37,32 @@
 
 #![warn(clippy::all, clippy::pedantic)]
 #![allow(clippy::module_name_repetitions)]
+pub mod anchors;
+pub mod commands;
+pub mod completion;
+pub mod diff;
 pub mod document;
 pub mod event;
+pub mod headless;
+pub mod jobs;
+pub mod keymap;
+pub mod layout;
+pub mod listing;
 pub mod utils;
 pub mod map;
 pub mod searching;
+pub mod syntax;
+pub mod tags;
+pub mod templates;
+pub mod workspace;
+#[cfg(feature = "wasm")]
+pub mod wasm;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+#[cfg(feature = "scripting")]
+pub mod scripting;
+#[cfg(feature = "terminal")]
+pub mod terminal;
 
 pub use document::Document;
-pub use utils::{Loc, Size};
+pub use utils::{Loc, Size, Span, StyledSpan};
@@ -0,0 +1,80 @@
+/// listing.rs - reads directories into entries, for browsing the file system with a document
+use crate::event::Result;
+use std::fs::Metadata;
+use std::path::{Component, Path, PathBuf};
+use std::time::SystemTime;
+
+/// An entry within a directory listing
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DirEntry {
+    /// The name of the entry (`..` for the parent directory)
+    pub name: String,
+    /// The path to the entry
+    pub path: PathBuf,
+    /// Whether the entry is a directory
+    pub is_dir: bool,
+    /// The size of the entry in bytes
+    pub len: u64,
+    /// When the entry was last modified, if the platform supports it
+    pub modified: Option<SystemTime>,
+    /// Whether the entry can't be written to
+    pub read_only: bool,
+}
+
+impl DirEntry {
+    /// Form an entry from a path and its metadata
+    fn new(name: String, path: PathBuf, metadata: &Metadata) -> Self {
+        Self {
+            name,
+            path,
+            is_dir: metadata.is_dir(),
+            len: metadata.len(),
+            modified: metadata.modified().ok(),
+            read_only: metadata.permissions().readonly(),
+        }
+    }
+
+    /// The text to display for this entry, with directories ending in `/`
+    #[must_use]
+    pub fn display(&self) -> String {
+        if self.is_dir {
+            format!("{}/", self.name)
+        } else {
+            self.name.clone()
+        }
+    }
+}
+
+/// Read the entries of a directory.
+/// Directories come before files, both in alphabetical order, with the parent directory (`..`)
+/// at the top if there is one.
+/// # Errors
+/// Returns an error if the directory couldn't be read.
+pub fn read_dir<P: AsRef<Path>>(path: P) -> Result<Vec<DirEntry>> {
+    let path = path.as_ref();
+    let mut entries = vec![];
+    for entry in std::fs::read_dir(path)? {
+        let entry = entry?;
+        // Follow symbolic links, unless they are broken
+        let metadata = match std::fs::metadata(entry.path()) {
+            Ok(metadata) => metadata,
+            Err(_) => entry.metadata()?,
+        };
+        let name = entry.file_name().to_string_lossy().to_string();
+        entries.push(DirEntry::new(name, entry.path(), &metadata));
+    }
+    entries.sort_by(|a, b| b.is_dir.cmp(&a.is_dir).then_with(|| a.name.cmp(&b.name)));
+    if let Some(parent) = std::fs::canonicalize(path)?.parent() {
+        // Step back out of the directory rather than building up `..` components
+        let up = match path.components().next_back() {
+            Some(Component::Normal(_)) => path
+                .parent()
+                .filter(|p| !p.as_os_str().is_empty())
+                .map_or_else(|| PathBuf::from("."), Path::to_path_buf),
+            _ => path.join(".."),
+        };
+        let metadata = std::fs::metadata(parent)?;
+        entries.insert(0, DirEntry::new("..".to_string(), up, &metadata));
+    }
+    Ok(entries)
+}
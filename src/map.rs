@@ -1,6 +1,6 @@
 /// map.rs - provides an easy interface to manage characters with large widths
 use std::collections::HashMap;
-use crate::utils::{Loc, width};
+use crate::utils::{Loc, tab_stop, width};
 use unicode_width::UnicodeWidthChar;
 
 /// This is a type for making a note of the location of different characters
@@ -160,7 +160,7 @@ pub fn form_map(st: &str, tab_width: usize) -> (DblUsize, DblUsize) {
     for (char_idx, ch) in st.chars().enumerate() {
         if ch == '\t' {
             tab.push((idx, char_idx));
-            idx += tab_width;
+            idx += tab_stop(idx, tab_width);
         } else if ch.width().unwrap_or(1) == 1 {
             idx += 1;
         } else {
@@ -0,0 +1,180 @@
+/// scripting.rs - a bridge between documents and the rhai scripting language, for user plugins
+///
+/// Scripts can define hook functions which are called with the document bound to `this`:
+/// ```rhai
+/// fn on_event(ev) {
+///     if ev.kind == "insert" && ev.text == "(" {
+///         this.insert(ev.x + 1, ev.y, ")");
+///     }
+/// }
+/// ```
+use crate::document::Document;
+use crate::event::{Error, Event, Result};
+use crate::utils::Loc;
+use rhai::{CallFnOptions, Dynamic, Engine, EvalAltResult, Map, Scope, AST, INT};
+
+/// The name of the hook that is called after an event is executed
+pub const ON_EVENT: &str = "on_event";
+
+/// Result type used by functions registered with the scripting engine
+type ScriptResult<T> = std::result::Result<T, Box<EvalAltResult>>;
+
+/// Hosts a compiled script, and allows hooks within it to be called on a document
+pub struct ScriptHost {
+    /// The engine, with the document API registered
+    pub engine: Engine,
+    /// The compiled script
+    pub ast: AST,
+    /// Variables that persist between hook calls
+    pub scope: Scope<'static>,
+}
+
+impl ScriptHost {
+    /// Compile a script, ready for its hooks to be called
+    /// # Errors
+    /// Returns an error if the script fails to compile.
+    pub fn new(script: &str) -> Result<Self> {
+        let engine = engine();
+        let mut scope = Scope::new();
+        let ast = engine.compile(script).map_err(|e| Error::Script(e.to_string()))?;
+        // Run any top level statements, to set up the scope
+        engine
+            .run_ast_with_scope(&mut scope, &ast)
+            .map_err(|e| Error::Script(e.to_string()))?;
+        Ok(Self { engine, ast, scope })
+    }
+
+    /// Determine whether the script defines a certain hook
+    #[must_use]
+    pub fn has_hook(&self, hook: &str) -> bool {
+        self.ast.iter_functions().any(|f| f.name == hook)
+    }
+
+    /// Call a hook in the script with the document bound to `this`.
+    /// Hooks that aren't defined in the script are ignored.
+    /// # Errors
+    /// Returns an error if the hook failed to run, or replaced `this`, in which case the document
+    /// has been discarded by the script and is left empty.
+    pub fn call(&mut self, doc: &mut Document, hook: &str, args: Vec<Dynamic>) -> Result<Dynamic> {
+        if !self.has_hook(hook) {
+            return Ok(Dynamic::UNIT);
+        }
+        // Temporarily move the document into the script
+        let placeholder = Document::new(doc.size);
+        let mut this = Dynamic::from(std::mem::replace(doc, placeholder));
+        let options = CallFnOptions::new().eval_ast(false).bind_this_ptr(&mut this);
+        let result = self
+            .engine
+            .call_fn_with_options::<Dynamic>(options, &mut self.scope, &self.ast, hook, args);
+        // Move the document back out again, if the script didn't throw it away
+        if !this.is::<Document>() {
+            return Err(Error::Script("`this` must not be reassigned within a hook".to_string()));
+        }
+        *doc = this.cast::<Document>();
+        result.map_err(|e| Error::Script(e.to_string()))
+    }
+
+    /// Call the `on_event` hook, should be called after an event has been executed
+    /// # Errors
+    /// Returns an error if the hook failed to run.
+    pub fn on_event(&mut self, doc: &mut Document, ev: &Event) -> Result<()> {
+        self.call(doc, ON_EVENT, vec![event_to_map(ev).into()]).map(|_| ())
+    }
+}
+
+/// Convert an event into a map, which can be inspected from within scripts
+#[must_use]
+pub fn event_to_map(ev: &Event) -> Map {
+    let (kind, loc, text) = match ev {
+        Event::Insert(loc, st) => ("insert", *loc, st.as_str()),
+        Event::Delete(loc, st) => ("delete", *loc, st.as_str()),
+        Event::InsertLine(y, st) => ("insert_line", Loc::at(0, *y), st.as_str()),
+        Event::DeleteLine(y, st) => ("delete_line", Loc::at(0, *y), st.as_str()),
+        Event::SplitDown(loc) => ("split_down", *loc, ""),
+        Event::SpliceUp(loc) => ("splice_up", *loc, ""),
+    };
+    let mut map = Map::new();
+    map.insert("kind".into(), kind.into());
+    map.insert("x".into(), to_int(loc.x).into());
+    map.insert("y".into(), to_int(loc.y).into());
+    map.insert("text".into(), text.into());
+    map
+}
+
+/// Create a scripting engine with the document API registered
+#[must_use]
+pub fn engine() -> Engine {
+    let mut engine = Engine::new();
+    engine
+        .register_type_with_name::<Document>("Document")
+        // Editing
+        .register_fn("insert", |doc: &mut Document, x: INT, y: INT, st: &str| {
+            exe(doc, Event::Insert(Loc::at(to_usize(x)?, to_usize(y)?), st.to_string()))
+        })
+        .register_fn("delete", |doc: &mut Document, x: INT, y: INT, st: &str| {
+            exe(doc, Event::Delete(Loc::at(to_usize(x)?, to_usize(y)?), st.to_string()))
+        })
+        .register_fn("insert_line", |doc: &mut Document, y: INT, st: &str| {
+            exe(doc, Event::InsertLine(to_usize(y)?, st.to_string()))
+        })
+        .register_fn("delete_line", |doc: &mut Document, y: INT| {
+            let y = to_usize(y)?;
+            let line = doc.line(y).unwrap_or_default();
+            exe(doc, Event::DeleteLine(y, line))
+        })
+        .register_fn("split_down", |doc: &mut Document, x: INT, y: INT| {
+            exe(doc, Event::SplitDown(Loc::at(to_usize(x)?, to_usize(y)?)))
+        })
+        .register_fn("splice_up", |doc: &mut Document, y: INT| {
+            exe(doc, Event::SpliceUp(Loc::at(0, to_usize(y)?)))
+        })
+        .register_fn("commit", |doc: &mut Document| doc.event_mgmt.commit())
+        .register_fn("undo", |doc: &mut Document| doc.undo().map(|_| ()).map_err(script_error))
+        .register_fn("redo", |doc: &mut Document| doc.redo().map(|_| ()).map_err(script_error))
+        // Reading
+        .register_fn("line", |doc: &mut Document, y: INT| -> ScriptResult<Dynamic> {
+            Ok(doc.line(to_usize(y)?).map_or(Dynamic::UNIT, Dynamic::from))
+        })
+        .register_get("len_lines", |doc: &mut Document| to_int(doc.len_lines()))
+        .register_get("x", |doc: &mut Document| to_int(doc.char_ptr))
+        .register_get("y", |doc: &mut Document| to_int(doc.loc().y))
+        .register_get("modified", |doc: &mut Document| doc.modified)
+        .register_get("file_name", |doc: &mut Document| {
+            doc.file_name.clone().map_or(Dynamic::UNIT, Dynamic::from)
+        })
+        // Moving
+        .register_fn("goto", |doc: &mut Document, x: INT, y: INT| -> ScriptResult<()> {
+            doc.goto(&Loc::at(to_usize(x)?, to_usize(y)?));
+            Ok(())
+        })
+        .register_fn("move_up", |doc: &mut Document| { doc.move_up(); })
+        .register_fn("move_down", |doc: &mut Document| { doc.move_down(); })
+        .register_fn("move_left", |doc: &mut Document| { doc.move_left(); })
+        .register_fn("move_right", |doc: &mut Document| { doc.move_right(); })
+        .register_fn("move_home", Document::move_home)
+        .register_fn("move_end", Document::move_end)
+        .register_fn("move_top", Document::move_top)
+        .register_fn("move_bottom", Document::move_bottom);
+    engine
+}
+
+/// Execute an event on a document from within a script
+fn exe(doc: &mut Document, ev: Event) -> ScriptResult<()> {
+    doc.exe(ev).map(|_| ()).map_err(script_error)
+}
+
+/// Convert a kaolinite error into a script error
+#[allow(clippy::needless_pass_by_value, clippy::unnecessary_box_returns)]
+fn script_error(err: Error) -> Box<EvalAltResult> {
+    err.to_string().into()
+}
+
+/// Convert a script integer into an index
+fn to_usize(x: INT) -> ScriptResult<usize> {
+    usize::try_from(x).map_err(|_| format!("Invalid index: {x}").into())
+}
+
+/// Convert an index into a script integer
+fn to_int(x: usize) -> INT {
+    INT::try_from(x).unwrap_or(INT::MAX)
+}
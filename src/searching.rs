@@ -1,15 +1,37 @@
 /// searching.rs - utilities to assist with searching a document
 use crate::regex;
-use crate::utils::Loc;
+use crate::utils::{Loc, Span, StyledSpan};
 use regex::Regex;
 
 /// Stores information about a match in a document
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Match {
     pub loc: Loc,
     pub text: String,
 }
 
+impl Match {
+    /// Get the span of text that was matched, which ends on a later line if the match has
+    /// newlines in it (see `Document::find_across_lines`)
+    #[must_use]
+    pub fn span(&self) -> Span {
+        let end = match self.text.rsplit_once('\n') {
+            Some((before, last)) => {
+                Loc::at(last.chars().count(), self.loc.y + before.matches('\n').count() + 1)
+            }
+            None => Loc::at(self.loc.x + self.text.chars().count(), self.loc.y),
+        };
+        Span::new(self.loc, end)
+    }
+
+    /// Get the characters that were matched on their line, styled as a search result
+    #[must_use]
+    pub fn styled_span(&self) -> StyledSpan {
+        let range = self.loc.x..self.loc.x + self.text.chars().count();
+        StyledSpan::new(range, StyledSpan::MATCH)
+    }
+}
+
 /// Struct to abstract searching
 pub struct Searcher {
     pub re: Regex,
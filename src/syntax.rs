@@ -0,0 +1,155 @@
+/// syntax.rs - finds the strings and comments on a line, for tools that need to skip over them
+use crate::utils::StyledSpan;
+use std::ops::Range;
+
+/// What a region of a line is
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum RegionKind {
+    /// A string literal, including its quotes
+    String,
+    /// A comment, including the characters that start (and end) it
+    Comment,
+}
+
+/// A string or comment found on a line
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Region {
+    /// What the region is
+    pub kind: RegionKind,
+    /// The character indices the region covers
+    pub range: Range<usize>,
+}
+
+impl Region {
+    /// Get the region as a styled span, for simple highlighting
+    #[must_use]
+    pub fn styled_span(&self) -> StyledSpan {
+        let style_id = match self.kind {
+            RegionKind::String => StyledSpan::STRING,
+            RegionKind::Comment => StyledSpan::COMMENT,
+        };
+        StyledSpan::new(self.range.clone(), style_id)
+    }
+}
+
+/// How strings and comments are written in a type of file.
+/// This is enough to tell code apart from strings and comments a line at a time (to skip
+/// brackets inside strings when matching them, or to toggle comments), without a full
+/// highlighter. Block comments and strings that carry on from another line aren't known about.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Syntax {
+    /// The characters that start and end strings
+    pub quotes: Vec<char>,
+    /// The character that stops the character after it ending a string, if there is one
+    pub escape: Option<char>,
+    /// What starts a comment that runs to the end of the line (e.g. `//`)
+    pub line_comment: Option<String>,
+    /// What starts and ends a block comment (e.g. `/*` and `*/`)
+    pub block_comment: Option<(String, String)>,
+}
+
+impl Default for Syntax {
+    /// Double and single quoted strings with backslash escapes, and no comments
+    fn default() -> Self {
+        Self { quotes: vec!['"', '\''], escape: Some('\\'), line_comment: None, block_comment: None }
+    }
+}
+
+impl Syntax {
+    /// Get the syntax for a file type, as given by `utils::filetype`.
+    /// Unknown file types get the default syntax.
+    #[must_use]
+    pub fn for_filetype(file_type: &str) -> Self {
+        let c_like = Self::with_comments(Some("//"), Some(("/*", "*/")));
+        let hash = Self::with_comments(Some("#"), None);
+        match file_type {
+            "C" | "C Header" | "C++" | "C++ Header" | "C#" | "Java" | "Kotlin" | "Scala"
+            | "Dart" | "Groovy" | "Objective-C" | "Arduino" | "Cuda" | "HLSL" | "OpenCL"
+            | "Vala" | "PHP" | "Swift" => c_like,
+            "JavaScript" | "JSX" | "TypeScript" | "Go" => {
+                Self { quotes: vec!['"', '\'', '`'], ..c_like }
+            }
+            // Single quotes are also used for lifetimes, so only double quotes start strings
+            "Rust" => Self { quotes: vec!['"'], ..c_like },
+            "CSS" | "SCSS" | "Sass" => Self { line_comment: None, ..c_like },
+            "Python" | "Cython" | "NumPy" | "Ruby" | "Shell" | "Zsh" | "Fish" | "Perl" | "R"
+            | "TOML" | "Yaml" | "Makefile" | "Elixr" | "Julia" | "Tcl" | "PowerShell"
+            | "Dockerfile" | "CoffeeScript" | "Crystal" | "GDScript" | "Nix" => hash,
+            "Lua" => Self::with_comments(Some("--"), Some(("--[[", "]]"))),
+            "SQL" => Self::with_comments(Some("--"), Some(("/*", "*/"))),
+            "Haskell" | "Elm" => Self::with_comments(Some("--"), Some(("{-", "-}"))),
+            "HTML" | "XML" | "Vue" | "Markdown" => Self {
+                escape: None,
+                ..Self::with_comments(None, Some(("<!--", "-->")))
+            },
+            "Clojure" | "Common Lisp" | "Scheme" | "Racket" | "Emacs Lisp" => {
+                Self { quotes: vec!['"'], ..Self::with_comments(Some(";"), None) }
+            }
+            "Erlang" | "TeX" | "Matlab" => Self::with_comments(Some("%"), None),
+            "INI" | "Assembly" => Self::with_comments(Some(";"), None),
+            _ => Self::default(),
+        }
+    }
+
+    /// The default strings, with some comments
+    fn with_comments(line: Option<&str>, block: Option<(&str, &str)>) -> Self {
+        Self {
+            line_comment: line.map(str::to_string),
+            block_comment: block.map(|(start, end)| (start.to_string(), end.to_string())),
+            ..Self::default()
+        }
+    }
+
+    /// Find the strings and comments on a line, in order.
+    /// Strings and block comments that aren't closed run to the end of the line.
+    #[must_use]
+    pub fn regions(&self, line: &str) -> Vec<Region> {
+        let chars: Vec<char> = line.chars().collect();
+        let starts_at = |x: usize, pattern: &str| {
+            let pattern: Vec<char> = pattern.chars().collect();
+            chars[x..].starts_with(&pattern)
+        };
+        let mut regions = vec![];
+        let mut x = 0;
+        while x < chars.len() {
+            let block = self.block_comment.as_ref().filter(|(start, _)| starts_at(x, start));
+            if let Some((start, end)) = block {
+                // Block comments are checked first, in case they start like line comments
+                let mut to = x + start.chars().count();
+                while to < chars.len() && !starts_at(to, end) {
+                    to += 1;
+                }
+                let to = (to + end.chars().count()).min(chars.len());
+                regions.push(Region { kind: RegionKind::Comment, range: x..to });
+                x = to;
+            } else if self.line_comment.as_ref().is_some_and(|start| starts_at(x, start)) {
+                regions.push(Region { kind: RegionKind::Comment, range: x..chars.len() });
+                break;
+            } else if self.quotes.contains(&chars[x]) {
+                let quote = chars[x];
+                let mut to = x + 1;
+                while to < chars.len() && chars[to] != quote {
+                    to += if Some(chars[to]) == self.escape { 2 } else { 1 };
+                }
+                let to = (to + 1).min(chars.len());
+                regions.push(Region { kind: RegionKind::String, range: x..to });
+                x = to;
+            } else {
+                x += 1;
+            }
+        }
+        regions
+    }
+
+    /// Determine if a character on a line is code, rather than part of a string or comment
+    #[must_use]
+    pub fn is_code(&self, line: &str, x: usize) -> bool {
+        !self.regions(line).iter().any(|region| region.range.contains(&x))
+    }
+
+    /// Get the region a character on a line is in, if it is in a string or comment
+    #[must_use]
+    pub fn region_at(&self, line: &str, x: usize) -> Option<Region> {
+        self.regions(line).into_iter().find(|region| region.range.contains(&x))
+    }
+}
@@ -0,0 +1,252 @@
+/// tags.rs - symbol indexes from ctags files or simple patterns, for jumping to definitions
+/// and outlining documents
+use crate::document::Document;
+use crate::event::Result;
+use crate::regex;
+use crate::searching::Searcher;
+use crate::utils::{filetype, width, Loc};
+use regex::Regex;
+use std::collections::HashMap;
+use std::path::Path;
+
+/// Where a symbol is defined within its file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TagAddress {
+    /// An exact location
+    Loc(Loc),
+    /// The contents of the line it is defined on, as ctags uses to survive edits
+    Pattern(String),
+}
+
+/// A symbol definition
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Tag {
+    /// The name of the symbol
+    pub name: String,
+    /// The file it is defined in
+    pub file: String,
+    /// Where in the file it is defined
+    pub address: TagAddress,
+    /// What sort of symbol it is (e.g. `f` for a function in ctags), if known
+    pub kind: Option<String>,
+}
+
+/// An entry in the outline of a document, such as a function or a heading
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Symbol {
+    /// Where the name of the symbol is, in character indices
+    pub loc: Loc,
+    /// What sort of symbol it is (the keyword that defines it, such as `fn` or `class`, or
+    /// `heading` and `section` for documents)
+    pub kind: String,
+    /// The name of the symbol
+    pub name: String,
+    /// How deeply the symbol is nested, starting at 0 (by indentation for code, and by the
+    /// level of the heading or section for documents)
+    pub level: usize,
+}
+
+/// An index of symbols, so that their definitions can be found
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Tags {
+    /// The definitions of each symbol
+    pub tags: HashMap<String, Vec<Tag>>,
+}
+
+impl Tags {
+    /// Create a new, empty index
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Read a ctags file, with file names relative to the directory it is in
+    /// # Errors
+    /// Returns an error if the file couldn't be read.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let mut tags = Self::parse(&std::fs::read_to_string(path)?);
+        let dir = path.parent().unwrap_or_else(|| Path::new(""));
+        for tag in tags.tags.values_mut().flatten() {
+            tag.file = dir.join(&tag.file).to_string_lossy().to_string();
+        }
+        Ok(tags)
+    }
+
+    /// Parse the contents of a ctags file. Lines that aren't valid tags are ignored.
+    #[must_use]
+    pub fn parse(text: &str) -> Self {
+        let mut tags = Self::new();
+        for line in text.lines().filter(|l| !l.starts_with("!_")) {
+            let mut parts = line.splitn(3, '\t');
+            let (Some(name), Some(file), Some(rest)) = (parts.next(), parts.next(), parts.next())
+            else {
+                continue;
+            };
+            // The address is followed by `;"` and any extra fields
+            let (address, extra) = rest.split_once(";\"").unwrap_or((rest, ""));
+            let address = if let Ok(line) = address.parse::<usize>() {
+                TagAddress::Loc(Loc::at(0, line.saturating_sub(1)))
+            } else if address.len() > 1 && (address.starts_with('/') || address.starts_with('?')) {
+                let pattern = &address[1..address.len() - 1];
+                let pattern = pattern.strip_prefix('^').unwrap_or(pattern);
+                let pattern = pattern.strip_suffix('$').unwrap_or(pattern);
+                TagAddress::Pattern(pattern.replace("\\/", "/").replace("\\\\", "\\"))
+            } else {
+                continue;
+            };
+            let kind = extra
+                .split('\t')
+                .map(|f| f.strip_prefix("kind:").unwrap_or(f))
+                .find(|f| !f.is_empty() && !f.contains(':'))
+                .map(str::to_string);
+            tags.add(Tag { name: name.to_string(), file: file.to_string(), address, kind });
+        }
+        tags
+    }
+
+    /// Add a definition to the index
+    pub fn add(&mut self, tag: Tag) {
+        self.tags.entry(tag.name.clone()).or_default().push(tag);
+    }
+
+    /// Find the definitions in a file using simple patterns for its file type, for when
+    /// there is no tags file. Only a handful of languages are supported.
+    pub fn index(&mut self, file: &str, contents: &str) {
+        let extension = Path::new(file).extension().map(|e| e.to_string_lossy().to_string());
+        let file_type = extension.as_deref().and_then(filetype);
+        let Some(pattern) = file_type.as_deref().and_then(definition_pattern) else { return };
+        let re = regex!(pattern);
+        for symbol in find_symbols(&re, contents.lines()) {
+            self.add(Tag {
+                name: symbol.name,
+                file: file.to_string(),
+                address: TagAddress::Loc(symbol.loc),
+                kind: Some(symbol.kind),
+            });
+        }
+    }
+
+    /// Find the definitions in a document, including any unsaved changes.
+    /// Any definitions from a previous index of the same file are replaced.
+    pub fn index_document(&mut self, doc: &Document) {
+        let Some(file) = &doc.file_name else { return };
+        for tags in self.tags.values_mut() {
+            tags.retain(|t| &t.file != file);
+        }
+        self.tags.retain(|_, t| !t.is_empty());
+        self.index(file, &doc.file.to_string());
+    }
+
+    /// Find where a symbol is defined, as file names and locations.
+    /// Definitions given as patterns are looked up in their files, and are left out if they
+    /// can no longer be found.
+    #[must_use]
+    pub fn goto_definition(&self, symbol: &str) -> Vec<(String, Loc)> {
+        let Some(tags) = self.tags.get(symbol) else { return vec![] };
+        tags.iter()
+            .filter_map(|tag| match &tag.address {
+                TagAddress::Loc(loc) => Some((tag.file.clone(), *loc)),
+                TagAddress::Pattern(pattern) => {
+                    let contents = std::fs::read_to_string(&tag.file).ok()?;
+                    let (y, line) = contents.lines().enumerate().find(|(_, l)| l == pattern)?;
+                    let x = line.find(symbol).map_or(0, |x| Searcher::raw_to_char(x, line));
+                    Some((tag.file.clone(), Loc::at(x, y)))
+                }
+            })
+            .collect()
+    }
+}
+
+/// Find the symbols in the lines of a file type, in order, for outlines and breadcrumbs.
+/// As well as the definitions found for jumping to them, this finds headings in Markdown,
+/// sections in TeX and tables in TOML and INI files.
+/// Code is nested by its indentation, with tabs `tab_width` columns wide.
+/// Unsupported file types have no symbols.
+#[must_use]
+pub fn outline<'a, I>(file_type: &str, lines: I, tab_width: usize) -> Vec<Symbol>
+where
+    I: IntoIterator<Item = &'a str>,
+{
+    let Some(pattern) = outline_pattern(file_type).or_else(|| definition_pattern(file_type)) else {
+        return vec![];
+    };
+    let lines: Vec<&str> = lines.into_iter().collect();
+    let mut symbols = find_symbols(&regex!(pattern), lines.iter().copied());
+    // Symbols are nested within the symbols before them that are less indented
+    let mut open: Vec<usize> = vec![];
+    for symbol in &mut symbols {
+        let heading = symbol.kind.starts_with('#');
+        let sections = ["part", "chapter", "section", "subsection", "subsubsection", "paragraph"];
+        if heading {
+            symbol.level = symbol.kind.len() - 1;
+            symbol.kind = "heading".to_string();
+        } else if let Some(level) = sections.iter().position(|s| *s == symbol.kind) {
+            symbol.level = level;
+            symbol.kind = "section".to_string();
+        } else if symbol.kind.starts_with('[') {
+            symbol.kind = "section".to_string();
+        } else {
+            let line = lines[symbol.loc.y];
+            let indent: String = line.chars().take_while(|c| c.is_whitespace()).collect();
+            let indent = width(&indent, tab_width);
+            while open.last().is_some_and(|last| *last >= indent) {
+                open.pop();
+            }
+            symbol.level = open.len();
+            open.push(indent);
+        }
+    }
+    symbols
+}
+
+/// Find the symbols that a pattern from `definition_pattern` or `outline_pattern` matches,
+/// with their kind as it was written, and no nesting
+fn find_symbols<'a>(re: &Regex, lines: impl Iterator<Item = &'a str>) -> Vec<Symbol> {
+    let mut symbols = vec![];
+    for (y, line) in lines.enumerate() {
+        for cap in re.captures_iter(line) {
+            let (Some(kind), Some(name)) = (cap.get(1), cap.get(2)) else { continue };
+            let x = Searcher::raw_to_char(name.start(), line);
+            // Shell functions don't need a keyword
+            let kind = kind.as_str().trim().trim_end_matches('!');
+            let kind = if kind.is_empty() { "function" } else { kind };
+            symbols.push(Symbol {
+                loc: Loc::at(x, y),
+                kind: kind.to_string(),
+                name: name.as_str().to_string(),
+                level: 0,
+            });
+        }
+    }
+    symbols
+}
+
+/// A regular expression that finds the parts of a document that aren't code, capturing the
+/// kind (the `#`s of a heading, the TeX command or the `[` of a table) and the name
+fn outline_pattern(file_type: &str) -> Option<&'static str> {
+    Some(match file_type {
+        "Markdown" => r"^(#{1,6})\s+(.*?)\s*#*\s*$",
+        "TeX" => r"^\s*\\(part|chapter|section|subsection|subsubsection|paragraph)\*?\{([^}]*)\}",
+        "TOML" | "INI" => r"^\s*(\[\[?)\s*([^\]]+?)\s*\]",
+        _ => return None,
+    })
+}
+
+/// A regular expression that finds definitions in a language, capturing the kind and the name
+fn definition_pattern(file_type: &str) -> Option<&'static str> {
+    Some(match file_type {
+        "Rust" => {
+            r"^\s*(?:pub(?:\([^)]*\))?\s+)?(?:(?:async|const|unsafe|extern)\s+)*(fn|struct|enum|trait|type|mod|const|static|macro_rules!)\s*([A-Za-z_]\w*)"
+        }
+        "Python" | "Cython" => r"^\s*(?:async\s+)?(def|class)\s+([A-Za-z_]\w*)",
+        "JavaScript" | "TypeScript" | "JSX" => {
+            r"^\s*(?:export\s+)?(?:default\s+)?(?:async\s+)?(function\*?|class|interface)\s+([A-Za-z_$][\w$]*)"
+        }
+        "Go" => r"^\s*(func|type)\s+(?:\([^)]*\)\s*)?([A-Za-z_]\w*)",
+        "Ruby" => r"^\s*(def|class|module)\s+(?:self\.)?([A-Za-z_]\w*[?!]?)",
+        "Lua" => r"^\s*(?:local\s+)?(function)\s+([A-Za-z_][\w.:]*)",
+        "Shell" | "Zsh" => r"^\s*(function\s+|)([A-Za-z_][\w-]*)\s*\(\)",
+        _ => return None,
+    })
+}
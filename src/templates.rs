@@ -0,0 +1,70 @@
+/// templates.rs - skeletons to fill new documents with, chosen by file extension
+use crate::document::Document;
+use crate::event::{Event, Result};
+use crate::utils::Loc;
+use std::collections::HashMap;
+use std::path::Path;
+
+/// Skeletons for new files, keyed by file extension (e.g. a licence header or `fn main()` for
+/// `rs` files).
+/// Templates can contain these placeholders:
+/// - `{file}` is replaced with the file name, without its directory
+/// - `{name}` is replaced with the file name, without its directory or extension
+/// - `{cursor}` is removed, and the cursor is placed where it was
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Templates {
+    /// The template for each file extension
+    pub templates: HashMap<String, String>,
+}
+
+impl Templates {
+    /// Create a new, empty set of templates
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a template for files with an extension, replacing any that was there before
+    pub fn add<E: Into<String>, T: Into<String>>(&mut self, extension: E, template: T) {
+        self.templates.insert(extension.into(), template.into());
+    }
+
+    /// Fill a document with the template for its extension, if it is empty.
+    /// This is done through events as a single patch, so it can be undone, and the document
+    /// is marked as modified.
+    /// Returns false if there is no template for the document or it isn't empty.
+    /// # Errors
+    /// Returns an error if the template failed to be inserted.
+    pub fn apply(&self, doc: &mut Document) -> Result<bool> {
+        let Some(file_name) = doc.file_name.clone() else { return Ok(false) };
+        let path = Path::new(&file_name);
+        let extension = path.extension().map(|e| e.to_string_lossy().to_string());
+        let Some(template) = extension.and_then(|e| self.templates.get(&e)) else {
+            return Ok(false);
+        };
+        if doc.len_lines() > 1 || doc.file.len_chars() > 1 {
+            return Ok(false);
+        }
+        let file = path.file_name().map_or(String::new(), |f| f.to_string_lossy().to_string());
+        let name = path.file_stem().map_or(String::new(), |f| f.to_string_lossy().to_string());
+        let text = template.replace("{file}", &file).replace("{name}", &name);
+        // Work out where the cursor goes, before the placeholder is taken out
+        let before = text.split("{cursor}").next().unwrap_or_default();
+        let y = before.matches('\n').count();
+        let x = before.rsplit('\n').next().unwrap_or_default().chars().count();
+        let text = text.replace("{cursor}", "");
+        // Documents always end with a newline already
+        let text = text.strip_suffix('\n').unwrap_or(&text);
+        doc.load_to(1);
+        doc.event_mgmt.begin_batch();
+        let result = Event::insert_str_at(Loc::at(0, 0), text)
+            .into_iter()
+            .try_for_each(|event| doc.exe(event).map(|_| ()));
+        doc.event_mgmt.end_batch();
+        result?;
+        doc.load_to(y + 1);
+        doc.goto(&Loc::at(x, y));
+        doc.old_cursor = doc.char_ptr;
+        Ok(true)
+    }
+}
@@ -0,0 +1,65 @@
+/// terminal.rs - a thin adapter for drawing documents with crossterm
+use crate::document::Document;
+use crate::layout::Layout;
+use crossterm::cursor::MoveTo;
+use crossterm::terminal::{ScrollDown, ScrollUp};
+use crossterm::QueueableCommand;
+use std::cmp::Ordering;
+use std::io::{Result, Write};
+
+/// Convert a position on the screen into a crossterm command, saturating at the largest
+/// position crossterm can address
+fn move_to(x: usize, y: usize) -> MoveTo {
+    let x = u16::try_from(x).unwrap_or(u16::MAX);
+    let y = u16::try_from(y).unwrap_or(u16::MAX);
+    MoveTo(x, y)
+}
+
+/// Get the command to move the terminal cursor to the cursor of a document
+#[must_use]
+pub fn cursor(layout: &Layout, doc: &Document) -> MoveTo {
+    let loc = layout.cursor(doc);
+    move_to(loc.x, loc.y)
+}
+
+/// Get the command to move to the start of a row of the viewport, before the gutter, for
+/// drawing line numbers
+#[must_use]
+pub fn gutter(layout: &Layout, row: usize) -> MoveTo {
+    move_to(0, layout.origin.y + row)
+}
+
+/// Get the command to move to a display column of a row of the viewport, after the gutter
+/// (e.g. column 0 is where the text of the line starts)
+#[must_use]
+pub fn column(layout: &Layout, row: usize, column: usize) -> MoveTo {
+    move_to(layout.origin.x + column, layout.origin.y + row)
+}
+
+/// Scroll the terminal to follow the document, when it has moved vertically since it was last
+/// drawn, so that only the newly revealed rows need drawing.
+/// Terminals scroll the whole screen, so bars above and below the viewport will need redrawing.
+/// Returns the rows of the viewport that need drawing, which is all of them if the document
+/// moved further than the height of the viewport.
+/// # Errors
+/// Returns an error if the command failed to be queued.
+pub fn scroll<W: Write>(out: &mut W, previous: usize, doc: &Document) -> Result<Vec<usize>> {
+    let (current, height) = (doc.offset.y, doc.size.h);
+    let distance = current.abs_diff(previous);
+    if distance >= height {
+        return Ok((0..height).collect());
+    }
+    // The distance is less than the height of the terminal, so it fits
+    let amount = u16::try_from(distance).unwrap_or(u16::MAX);
+    match current.cmp(&previous) {
+        Ordering::Greater => {
+            out.queue(ScrollUp(amount))?;
+            Ok((height - distance..height).collect())
+        }
+        Ordering::Less => {
+            out.queue(ScrollDown(amount))?;
+            Ok((0..distance).collect())
+        }
+        Ordering::Equal => Ok(vec![]),
+    }
+}
@@ -1,6 +1,10 @@
 /// utils.rs - utilities to assist in editing and keep code in document.rs readable
+use unicode_normalization::{is_nfc, UnicodeNormalization};
+use unicode_segmentation::UnicodeSegmentation;
 use unicode_width::UnicodeWidthStr;
-use std::ops::{Bound, RangeBounds};
+use std::cmp::Ordering;
+use std::fmt;
+use std::ops::{Bound, Range, RangeBounds};
 
 /// Utility for easily forming a regular expression from a string
 #[macro_export]
@@ -9,8 +13,9 @@ macro_rules! regex {
     ($ex:expr) => { regex::Regex::new($ex).unwrap() };
 }
 
-/// Represents a location
-#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+/// Represents a location.
+/// Locations are ordered the way they appear in a document: by line, and then by column.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
 pub struct Loc {
     pub x: usize,
     pub y: usize,
@@ -22,6 +27,162 @@ impl Loc {
     pub fn at(x: usize, y: usize) -> Self {
         Self { x, y }
     }
+
+    /// Determine if this location is within a range of locations (e.g. `start..end`)
+    #[must_use]
+    pub fn is_within<R: RangeBounds<Loc>>(&self, range: &R) -> bool {
+        range.contains(self)
+    }
+
+    /// Add an offset to both parts of this location, stopping at the maximum value
+    #[must_use]
+    pub fn saturating_add(self, other: Loc) -> Self {
+        Self::at(self.x.saturating_add(other.x), self.y.saturating_add(other.y))
+    }
+
+    /// Take an offset away from both parts of this location, stopping at zero
+    #[must_use]
+    pub fn saturating_sub(self, other: Loc) -> Self {
+        Self::at(self.x.saturating_sub(other.x), self.y.saturating_sub(other.y))
+    }
+}
+
+impl Ord for Loc {
+    fn cmp(&self, other: &Self) -> Ordering {
+        (self.y, self.x).cmp(&(other.y, other.x))
+    }
+}
+
+impl PartialOrd for Loc {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl fmt::Display for Loc {
+    /// Displays as a 1-based `line:column`, the same way locations are written after file names
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.y + 1, self.x + 1)
+    }
+}
+
+/// Represents a span of text between two locations, such as a selection or a search result.
+/// The start is included and the end isn't, and the start is never after the end.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Span {
+    pub start: Loc,
+    pub end: Loc,
+}
+
+impl Span {
+    /// Create a span between two locations, which can be given in either order
+    #[must_use]
+    pub fn new(a: Loc, b: Loc) -> Self {
+        Self { start: a.min(b), end: a.max(b) }
+    }
+
+    /// Determine if the span doesn't cover any text
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.start == self.end
+    }
+
+    /// Determine if a location is within the span
+    #[must_use]
+    pub fn contains(&self, loc: Loc) -> bool {
+        loc.is_within(&(self.start..self.end))
+    }
+
+    /// Determine if this span covers any of the same text as another
+    #[must_use]
+    pub fn overlaps(&self, other: &Span) -> bool {
+        self.start < other.end && other.start < self.end
+    }
+
+    /// Split the span into one span for each line it covers, for rendering.
+    /// `line_len` gives the length of a line, which is where the spans on all but the last line
+    /// end. A span ending at the start of a line doesn't include that line.
+    pub fn split_lines<F: Fn(usize) -> usize>(&self, line_len: F) -> Vec<Span> {
+        if self.start.y == self.end.y {
+            return vec![*self];
+        }
+        let last = if self.end.x == 0 { self.end.y - 1 } else { self.end.y };
+        (self.start.y..=last)
+            .map(|y| {
+                let start = if y == self.start.y { self.start.x } else { 0 };
+                let end = if y == self.end.y { self.end.x } else { line_len(y) };
+                Span { start: Loc::at(start, y), end: Loc::at(end.max(start), y) }
+            })
+            .collect()
+    }
+}
+
+impl From<Range<Loc>> for Span {
+    fn from(range: Range<Loc>) -> Self {
+        Self::new(range.start, range.end)
+    }
+}
+
+impl fmt::Display for Span {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}-{}", self.start, self.end)
+    }
+}
+
+/// A range of characters on a line that should be drawn in a certain style, such as a
+/// selection or the text past the ruler.
+/// Style ids are just numbers, which front ends map to their own colours and attributes, so
+/// that the same spans can be drawn by any terminal library or in a browser.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Hash)]
+pub struct StyledSpan {
+    /// The character indices the style covers
+    pub range: Range<usize>,
+    /// What the text should look like
+    pub style_id: usize,
+}
+
+impl StyledSpan {
+    /// The style id for selected text
+    pub const SELECTION: usize = 0;
+    /// The style id for text that goes past the ruler
+    pub const RULER: usize = 1;
+    /// The style id for search results
+    pub const MATCH: usize = 2;
+    /// The style id for strings (see `syntax::Region`)
+    pub const STRING: usize = 3;
+    /// The style id for comments (see `syntax::Region`)
+    pub const COMMENT: usize = 4;
+    /// The first style id that isn't used by kaolinite, for front ends to add their own
+    pub const CUSTOM: usize = 16;
+
+    /// Shorthand to produce a styled span
+    #[must_use]
+    pub fn new(range: Range<usize>, style_id: usize) -> Self {
+        Self { range, style_id }
+    }
+
+    /// Turn spans that could overlap into spans that don't, in order, so that they can be
+    /// drawn from left to right. Where spans overlap, the later one in the list wins.
+    #[must_use]
+    pub fn flatten(spans: &[StyledSpan]) -> Vec<StyledSpan> {
+        let mut bounds: Vec<usize> =
+            spans.iter().flat_map(|s| [s.range.start, s.range.end]).collect();
+        bounds.sort_unstable();
+        bounds.dedup();
+        let mut result: Vec<StyledSpan> = vec![];
+        for pair in bounds.windows(2) {
+            let (start, end) = (pair[0], pair[1]);
+            let covering = |s: &&StyledSpan| s.range.start <= start && end <= s.range.end;
+            let Some(top) = spans.iter().rev().find(covering) else { continue };
+            match result.last_mut() {
+                Some(last) if last.range.end == start && last.style_id == top.style_id => {
+                    last.range.end = end;
+                }
+                _ => result.push(StyledSpan::new(start..end, top.style_id)),
+            }
+        }
+        result
+    }
 }
 
 /// Represents a size
@@ -39,12 +200,18 @@ impl Size {
     }
 }
 
+impl fmt::Display for Size {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}x{}", self.w, self.h)
+    }
+}
+
 /// Takes a string and cuts it from a start point to a specified length.
 /// Works with double width characters.
 /// This allows x offset to work well with double width characters.
 #[must_use]
 pub fn trim(string: &str, start: usize, length: usize, tab_width: usize) -> String {
-    let string = string.replace('\t', &" ".repeat(tab_width));
+    let string = expand_tabs(string, tab_width);
     if start >= string.width() {
         return "".to_string();
     }
@@ -80,11 +247,319 @@ pub fn get_range<R>(range: &R, min: usize, max: usize) -> (usize, usize) where R
     (start, end)
 }
 
-/// Utility function to determine the width of a string, with variable tab width
+/// Utility function to determine the width of a string, with variable tab width.
+/// Tabs stretch to the next tab stop, assuming the string starts at the first column.
 #[must_use]
 pub fn width(st: &str, tab_width: usize) -> usize {
-    let tabs = st.matches('\t').count();
-    (st.width() + tabs * tab_width).saturating_sub(tabs)
+    let mut column = 0;
+    for (i, part) in st.split('\t').enumerate() {
+        if i > 0 {
+            column += tab_stop(column, tab_width);
+        }
+        column += part.width();
+    }
+    column
+}
+
+/// Utility function to count the user-perceived characters (roughly, grapheme clusters) in a
+/// string (see `graphemes`)
+#[must_use]
+pub fn grapheme_len(st: &str) -> usize {
+    graphemes(st).len()
+}
+
+/// Utility function to split a string into user-perceived characters (extended grapheme
+/// clusters), so combining marks, emoji joined with zero width joiners, flags, Hangul syllables
+/// and `\r\n` each stay together.
+/// The result can be walked backwards for rendering from the end of a line.
+#[must_use]
+pub fn graphemes(st: &str) -> Vec<&str> {
+    UnicodeSegmentation::graphemes(st, true).collect()
+}
+
+/// Utility function to determine if a character index falls between two user-perceived
+/// characters (see `graphemes`), rather than in the middle of one (such as between a letter and
+/// its accent). The start and end of the string are always boundaries.
+#[must_use]
+pub fn is_boundary(st: &str, idx: usize) -> bool {
+    let mut at = 0;
+    for cluster in graphemes(st) {
+        if at >= idx {
+            return at == idx;
+        }
+        at += cluster.chars().count();
+    }
+    at == idx
+}
+
+/// Utility function to put a string into Unicode normalization form C, where characters are
+/// composed where possible (e.g. `e` followed by a combining acute accent becomes `é`), so that
+/// strings that look the same are the same
+#[must_use]
+pub fn nfc(st: &str) -> String {
+    if is_nfc(st) {
+        st.to_string()
+    } else {
+        st.nfc().collect()
+    }
+}
+
+/// Utility function to determine how many columns a tab takes up when it starts at a certain
+/// display column, so that it reaches the next tab stop
+#[must_use]
+pub fn tab_stop(column: usize, tab_width: usize) -> usize {
+    if tab_width == 0 {
+        0
+    } else {
+        tab_width - column % tab_width
+    }
+}
+
+/// Utility function to replace tabs with the spaces needed to reach the next tab stop
+#[must_use]
+pub fn expand_tabs(st: &str, tab_width: usize) -> String {
+    let mut result = String::new();
+    for (i, part) in st.split('\t').enumerate() {
+        if i > 0 {
+            result.push_str(&" ".repeat(tab_stop(result.width(), tab_width)));
+        }
+        result.push_str(part);
+    }
+    result
+}
+
+/// Represents how text is aligned within a space
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Align {
+    #[default]
+    Left,
+    Center,
+    Right,
+}
+
+/// Utility function to cut a string down to a display width, ending it with an ellipsis
+/// (such as `…`) if anything had to be removed. Works with double width and tab characters.
+#[must_use]
+pub fn truncate(st: &str, max: usize, ellipsis: &str, tab_width: usize) -> String {
+    if width(st, tab_width) <= max {
+        return st.to_string();
+    }
+    let budget = max.saturating_sub(width(ellipsis, tab_width));
+    let mut result = String::new();
+    for ch in st.chars() {
+        result.push(ch);
+        if width(&result, tab_width) > budget {
+            result.pop();
+            break;
+        }
+    }
+    // Add as much of the ellipsis as will fit
+    for ch in ellipsis.chars() {
+        result.push(ch);
+        if width(&result, tab_width) > max {
+            result.pop();
+            break;
+        }
+    }
+    result
+}
+
+/// Utility function to pad a string with spaces up to a display width.
+/// Strings that are already wider are left alone.
+#[must_use]
+pub fn pad_to(st: &str, target: usize, align: Align, tab_width: usize) -> String {
+    let space = target.saturating_sub(width(st, tab_width));
+    let (left, right) = match align {
+        Align::Left => (0, space),
+        Align::Center => (space / 2, space - space / 2),
+        Align::Right => (space, 0),
+    };
+    // Tabs would stretch differently after padding, so they are expanded into spaces
+    let st = if left > 0 { expand_tabs(st, tab_width) } else { st.to_string() };
+    format!("{}{st}{}", " ".repeat(left), " ".repeat(right))
+}
+
+/// Utility function to place text at the left and right edges of a space, for status lines.
+/// If there isn't enough room, the side without priority is truncated first, so that the
+/// output is always exactly the width of the space.
+#[must_use]
+pub fn align_sides(
+    lhs: &str,
+    rhs: &str,
+    space: usize,
+    priority: Align,
+    tab_width: usize,
+) -> String {
+    let (mut lhs, mut rhs) = (lhs.to_string(), rhs.to_string());
+    if width(&lhs, tab_width) + width(&rhs, tab_width) > space {
+        // Truncate the side without priority, leaving a gap between the two sides
+        let (keep, cut) = if priority == Align::Right {
+            (&mut rhs, &mut lhs)
+        } else {
+            (&mut lhs, &mut rhs)
+        };
+        *keep = truncate(keep, space, "…", tab_width);
+        let room = space.saturating_sub(width(keep, tab_width) + 1);
+        *cut = truncate(cut, room, "…", tab_width);
+    }
+    let gap = space.saturating_sub(width(&lhs, tab_width) + width(&rhs, tab_width));
+    format!("{lhs}{}{}", " ".repeat(gap), expand_tabs(&rhs, tab_width))
+}
+
+/// Utility function to place text at the left, middle and right of a space.
+/// The middle text takes priority and stays centred, with the sides truncated to fit around it.
+#[must_use]
+pub fn align_middle(lhs: &str, middle: &str, rhs: &str, space: usize, tab_width: usize) -> String {
+    let middle = truncate(middle, space, "…", tab_width);
+    let remaining = space.saturating_sub(width(&middle, tab_width));
+    let (left, right) = (remaining / 2, remaining - remaining / 2);
+    // Each side leaves a gap next to the middle text
+    let lhs = truncate(lhs, left.saturating_sub(1), "…", tab_width);
+    let rhs = truncate(rhs, right.saturating_sub(1), "…", tab_width);
+    format!(
+        "{}{}{}",
+        pad_to(&lhs, left, Align::Left, tab_width),
+        expand_tabs(&middle, tab_width),
+        pad_to(&rhs, right, Align::Right, tab_width),
+    )
+}
+
+/// Utility function to shorten a file path to fit within a display width, for status lines.
+/// The home directory (if provided) is replaced with `~`, then directories are cut down to
+/// their first character from the left (e.g. `src/document/editor/main.rs` becomes
+/// `s/d/editor/main.rs`) until the path fits. If it still doesn't fit, the start of the path
+/// is replaced with `…`.
+#[must_use]
+pub fn shorten_path(path: &str, max: usize, home: Option<&str>) -> String {
+    let mut path = path.to_string();
+    if let Some(home) = home.map(|h| h.trim_end_matches('/')).filter(|h| !h.is_empty()) {
+        if path == home || path.starts_with(&format!("{home}/")) {
+            path = format!("~{}", &path[home.len()..]);
+        }
+    }
+    let mut parts: Vec<String> = path.split('/').map(str::to_string).collect();
+    let dirs = parts.len().saturating_sub(1);
+    for i in 0..dirs {
+        if parts.join("/").width() <= max {
+            break;
+        }
+        // Hidden directories keep their dot so they stay recognisable
+        let keep = if parts[i].starts_with('.') { 2 } else { 1 };
+        if parts[i] != "~" {
+            parts[i] = parts[i].chars().take(keep).collect();
+        }
+    }
+    let path = parts.join("/");
+    if path.width() <= max {
+        return path;
+    }
+    // Keep as much of the end of the path as possible
+    let mut tail: Vec<char> = vec![];
+    for ch in path.chars().rev() {
+        tail.insert(0, ch);
+        if tail.iter().collect::<String>().width() + 1 > max {
+            tail.remove(0);
+            break;
+        }
+    }
+    if max == 0 {
+        String::new()
+    } else {
+        format!("…{}", tail.into_iter().collect::<String>())
+    }
+}
+
+/// Utility function to split a location off the end of a file name, such as those given in
+/// compiler errors (`src/main.rs:42:7`) or on the command line in vim style (`+42 src/main.rs`).
+/// Lines and columns start at 1 in the input, and at 0 in the returned location.
+#[must_use]
+pub fn parse_location(spec: &str) -> (&str, Option<Loc>) {
+    let spec = spec.trim();
+    let line_only = |line: &str| line.parse::<usize>().ok().map(|y| Loc::at(0, y.saturating_sub(1)));
+    // Vim style, with the line number before or after the file name
+    if let Some((line, file)) = spec.strip_prefix('+').and_then(|s| s.split_once(' ')) {
+        if let Some(loc) = line_only(line) {
+            return (file.trim(), Some(loc));
+        }
+    }
+    if let Some((file, line)) = spec.rsplit_once(" +") {
+        if let Some(loc) = line_only(line) {
+            return (file.trim(), Some(loc));
+        }
+    }
+    // Compiler style, with the line and column after colons
+    let mut file = spec.trim_end_matches(':');
+    let mut numbers = vec![];
+    while numbers.len() < 2 {
+        match file.rsplit_once(':').map(|(rest, n)| (rest, n.parse::<usize>())) {
+            Some((rest, Ok(n))) => {
+                numbers.insert(0, n.saturating_sub(1));
+                file = rest;
+            }
+            _ => break,
+        }
+    }
+    match numbers[..] {
+        [y] => (file, Some(Loc::at(0, y))),
+        [y, x] => (file, Some(Loc::at(x, y))),
+        _ => (spec, None),
+    }
+}
+
+/// Utility function to re-wrap lines of prose so that they fit within a display width.
+/// Paragraphs are separated by blank lines or a change of comment or quote prefix, and each
+/// paragraph keeps the indentation and comment prefix (such as `// ` or `# `) of its first line.
+/// Words that are wider than the width are placed on a line of their own.
+#[must_use]
+pub fn reflow(lines: &[String], max: usize, tab_width: usize) -> Vec<String> {
+    let re = regex!(r"^\s*(//+|#+|--+|;+|>+|\*)?\s*");
+    let mut result = vec![];
+    let mut words: Vec<&str> = vec![];
+    let mut prefix = String::new();
+    // Wrap the words of a paragraph
+    let flush = |words: &mut Vec<&str>, prefix: &str, result: &mut Vec<String>| {
+        let mut line = prefix.to_string();
+        let mut empty = true;
+        for word in words.drain(..) {
+            if !empty && width(&format!("{line} {word}"), tab_width) > max {
+                result.push(line);
+                line = prefix.to_string();
+                empty = true;
+            }
+            if !empty {
+                line.push(' ');
+            }
+            line.push_str(word);
+            empty = false;
+        }
+        if !empty {
+            result.push(line);
+        }
+    };
+    for line in lines {
+        let found = re.find(line).map_or("", |m| m.as_str());
+        let content = &line[found.len()..];
+        if content.trim().is_empty() {
+            // Blank line, which marks the end of a paragraph
+            flush(&mut words, &prefix, &mut result);
+            result.push(line.trim_end().to_string());
+        } else {
+            if !words.is_empty() && found.trim() != prefix.trim() {
+                // The comment or quote prefix changed, which also ends a paragraph
+                flush(&mut words, &prefix, &mut result);
+            }
+            if words.is_empty() {
+                // Start of a new paragraph
+                prefix = found.to_string();
+                if !prefix.ends_with(char::is_whitespace) && !prefix.is_empty() {
+                    prefix.push(' ');
+                }
+            }
+            words.extend(content.split_whitespace());
+        }
+    }
+    flush(&mut words, &prefix, &mut result);
+    result
 }
 
 /// Utility function to take a line and determine where spaces should be treated as tabs (forwards)
@@ -0,0 +1,200 @@
+/// wasm.rs - a small JavaScript friendly facade over documents for WebAssembly targets
+use crate::document::Document;
+use crate::event::Event;
+use crate::utils::{Loc, Size};
+use wasm_bindgen::prelude::*;
+
+/// Wraps a document, exposing only types that are able to cross the JavaScript boundary.
+/// Documents are always created in memory, as there is no file system to open from.
+#[wasm_bindgen]
+pub struct Buffer {
+    doc: Document,
+}
+
+#[wasm_bindgen]
+impl Buffer {
+    /// Create a new buffer of a certain size, containing the text provided
+    /// # Errors
+    /// Returns an error if the text failed to be read into the document.
+    #[wasm_bindgen(constructor)]
+    pub fn new(w: usize, h: usize, text: &str) -> Result<Buffer, JsValue> {
+        let mut doc = Document::from_reader(Size::is(w, h), text.as_bytes()).map_err(js_error)?;
+        doc.load_to(h);
+        Ok(Self { doc })
+    }
+
+    /// Resize the viewport of this buffer
+    pub fn resize(&mut self, w: usize, h: usize) {
+        self.doc.size = Size::is(w, h);
+        self.doc.load_to(self.doc.offset.y + h);
+    }
+
+    /// Insert text at a character location
+    /// # Errors
+    /// Returns an error if the location is out of range.
+    pub fn insert(&mut self, x: usize, y: usize, text: &str) -> Result<(), JsValue> {
+        self.doc
+            .exe(Event::Insert(Loc::at(x, y), text.to_string()))
+            .map(|_| ())
+            .map_err(js_error)
+    }
+
+    /// Delete text starting at a character location
+    /// # Errors
+    /// Returns an error if the location is out of range.
+    pub fn delete(&mut self, x: usize, y: usize, text: &str) -> Result<(), JsValue> {
+        self.doc
+            .exe(Event::Delete(Loc::at(x, y), text.to_string()))
+            .map(|_| ())
+            .map_err(js_error)
+    }
+
+    /// Split a line in two at a character location
+    /// # Errors
+    /// Returns an error if the location is out of range.
+    pub fn split_down(&mut self, x: usize, y: usize) -> Result<(), JsValue> {
+        self.doc
+            .exe(Event::SplitDown(Loc::at(x, y)))
+            .map(|_| ())
+            .map_err(js_error)
+    }
+
+    /// Join a line with the line below it
+    /// # Errors
+    /// Returns an error if the location is out of range.
+    pub fn splice_up(&mut self, x: usize, y: usize) -> Result<(), JsValue> {
+        self.doc
+            .exe(Event::SpliceUp(Loc::at(x, y)))
+            .map(|_| ())
+            .map_err(js_error)
+    }
+
+    /// Undo the last patch
+    /// # Errors
+    /// Returns an error if the patch failed to be reversed.
+    pub fn undo(&mut self) -> Result<(), JsValue> {
+        self.doc.undo().map(|_| ()).map_err(js_error)
+    }
+
+    /// Redo the last patch
+    /// # Errors
+    /// Returns an error if the patch failed to be re-executed.
+    pub fn redo(&mut self) -> Result<(), JsValue> {
+        self.doc.redo().map(|_| ()).map_err(js_error)
+    }
+
+    /// Commit the current patch, making it a single undo step
+    pub fn commit(&mut self) {
+        self.doc.event_mgmt.commit();
+    }
+
+    /// Get a line from the buffer
+    #[must_use]
+    pub fn line(&self, y: usize) -> Option<String> {
+        self.doc.line(y)
+    }
+
+    /// Get a line from the buffer, trimmed to fit within the viewport
+    #[must_use]
+    pub fn line_trim(&self, y: usize, start: usize, length: usize) -> Option<String> {
+        self.doc.line_trim(y, start, length)
+    }
+
+    /// Get the line number text for a line
+    #[must_use]
+    pub fn line_number(&self, y: usize) -> String {
+        self.doc.line_number(y)
+    }
+
+    /// Get the entire contents of the buffer
+    /// # Errors
+    /// Returns an error if the contents were unable to be written out.
+    pub fn text(&self) -> Result<String, JsValue> {
+        let mut out = vec![];
+        self.doc.write_to(&mut out).map_err(js_error)?;
+        String::from_utf8(out).map_err(|e| JsValue::from_str(&e.to_string()))
+    }
+
+    /// Get the number of lines in the buffer
+    #[must_use]
+    pub fn len_lines(&self) -> usize {
+        self.doc.len_lines()
+    }
+
+    /// Get the x position of the cursor on the screen
+    #[must_use]
+    pub fn cursor_x(&self) -> usize {
+        self.doc.cursor.x
+    }
+
+    /// Get the y position of the cursor on the screen
+    #[must_use]
+    pub fn cursor_y(&self) -> usize {
+        self.doc.cursor.y
+    }
+
+    /// Get the x offset of the viewport
+    #[must_use]
+    pub fn offset_x(&self) -> usize {
+        self.doc.offset.x
+    }
+
+    /// Get the y offset of the viewport
+    #[must_use]
+    pub fn offset_y(&self) -> usize {
+        self.doc.offset.y
+    }
+
+    /// Get the character index of the cursor within the current line
+    #[must_use]
+    pub fn char_ptr(&self) -> usize {
+        self.doc.char_ptr
+    }
+
+    /// Returns true if the buffer has been modified
+    #[must_use]
+    pub fn modified(&self) -> bool {
+        self.doc.modified
+    }
+
+    /// Move the cursor to a character location
+    pub fn goto(&mut self, x: usize, y: usize) {
+        self.doc.goto(&Loc::at(x, y));
+    }
+
+    /// Move the cursor up
+    pub fn move_up(&mut self) {
+        self.doc.move_up();
+    }
+
+    /// Move the cursor down
+    pub fn move_down(&mut self) {
+        self.doc.move_down();
+    }
+
+    /// Move the cursor left
+    pub fn move_left(&mut self) {
+        self.doc.move_left();
+    }
+
+    /// Move the cursor right
+    pub fn move_right(&mut self) {
+        self.doc.move_right();
+    }
+
+    /// Move the cursor to the start of the line
+    pub fn move_home(&mut self) {
+        self.doc.move_home();
+    }
+
+    /// Move the cursor to the end of the line
+    pub fn move_end(&mut self) {
+        self.doc.move_end();
+    }
+}
+
+/// Convert a kaolinite error into a JavaScript value
+#[allow(clippy::needless_pass_by_value)]
+fn js_error(err: crate::event::Error) -> JsValue {
+    JsValue::from_str(&err.to_string())
+}
@@ -0,0 +1,589 @@
+/// workspace.rs - manages a set of open documents, for editors with several files open at once
+use crate::document::Document;
+use crate::event::{Error, Result, Status};
+use crate::searching::Searcher;
+use crate::utils::{Loc, Size};
+use regex::Regex;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// A location found by a search, such as an entry in vim's quickfix list
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct QuickfixEntry {
+    /// The file the location is in, if it has one
+    pub file: Option<String>,
+    /// The index of the document in the workspace, if it is open
+    pub document: Option<usize>,
+    /// The location within the file, in character indices
+    pub loc: Loc,
+    /// The contents of the line the location is on
+    pub text: String,
+}
+
+/// A summary of an open document, for listing them in a buffer switcher
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BufferEntry {
+    /// The index of the document in the workspace
+    pub index: usize,
+    /// The name of the file without its directory, or `[No Name]` if it doesn't have one
+    pub name: String,
+    /// The path of the file, as it was opened, if the document has one
+    pub path: Option<String>,
+    /// Whether the document has been modified since it was last saved
+    pub modified: bool,
+    /// The line the cursor is on, starting from 1
+    pub line: usize,
+    /// The type of file, determined from the extension
+    pub file_type: Option<String>,
+    /// Whether this is the document being edited
+    pub active: bool,
+}
+
+/// A replacement that has been worked out but not yet made
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProposedEdit {
+    /// The file the edit is in, if it has one
+    pub file: Option<String>,
+    /// The index of the document in the workspace, if it is open
+    pub document: Option<usize>,
+    /// The location of the text to replace, in character indices
+    pub loc: Loc,
+    /// The text that will be replaced
+    pub old: String,
+    /// The text it will be replaced with
+    pub new: String,
+    /// Whether this edit should be made
+    pub approved: bool,
+}
+
+/// A set of proposed edits across several files, for previewing before they are made
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Replacement {
+    pub edits: Vec<ProposedEdit>,
+}
+
+impl Replacement {
+    /// Approve an edit, so that it will be made
+    pub fn approve(&mut self, idx: usize) {
+        if let Some(edit) = self.edits.get_mut(idx) {
+            edit.approved = true;
+        }
+    }
+
+    /// Reject an edit, so that it won't be made
+    pub fn reject(&mut self, idx: usize) {
+        if let Some(edit) = self.edits.get_mut(idx) {
+            edit.approved = false;
+        }
+    }
+
+    /// Approve or reject every edit within a file
+    pub fn set_file(&mut self, file: &str, approved: bool) {
+        for edit in &mut self.edits {
+            if edit.file.as_deref() == Some(file) {
+                edit.approved = approved;
+            }
+        }
+    }
+}
+
+/// What to do about unsaved changes when closing a document
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum ClosePolicy {
+    /// Refuse to close documents that have unsaved changes
+    #[default]
+    FailIfModified,
+    /// Close documents anyway, throwing away any unsaved changes
+    Force,
+    /// Save documents that have unsaved changes first, refusing to close them if that fails
+    SaveFirst,
+}
+
+/// A change made to one or more documents in the workspace, which is undone as a unit
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct GlobalChange {
+    /// The indices of the documents that were changed, with how many patches each one gained
+    pub documents: Vec<(usize, usize)>,
+}
+
+/// A log of the changes made across the documents in a workspace, so that the last change can
+/// be undone whichever document it was in (see `Workspace::enable_history`)
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct GlobalHistory {
+    /// The changes that can be undone, with the most recent last
+    pub undo: Vec<GlobalChange>,
+    /// The changes that have been undone, with the most recently undone last
+    pub redo: Vec<GlobalChange>,
+    /// The number of patches each document had committed when changes were last recorded
+    committed: Vec<usize>,
+}
+
+/// Keeps track of the documents that are open, and which one is being edited
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Workspace {
+    /// The documents that are open
+    pub documents: Vec<Document>,
+    /// The index of the document that is being edited
+    pub active: usize,
+    /// The indices of the open documents, from the most recently used to the least
+    pub recent: Vec<usize>,
+    /// Where the cursor and viewport were (as a location and an offset) in files that have been
+    /// closed, so they can be put back when the file is opened again
+    pub positions: HashMap<String, (Loc, Loc)>,
+    /// The log of changes across documents, if it has been turned on
+    pub history: Option<GlobalHistory>,
+}
+
+impl Workspace {
+    /// Create a new workspace, with no documents open
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a document to the workspace, making it the active one and returning its index.
+    /// If its file was open before, the cursor and viewport are put back where they were.
+    pub fn add(&mut self, mut doc: Document) -> usize {
+        if let Some(position) = doc.file_name.as_ref().and_then(|f| self.positions.remove(f)) {
+            restore_position(&mut doc, position);
+        }
+        self.documents.push(doc);
+        self.switch(self.documents.len() - 1);
+        self.active
+    }
+
+    /// Open a file in the workspace, making it the active document and returning its index.
+    /// If the file is already open (even through a different path), that document is used.
+    /// # Errors
+    /// Returns an error if the file fails to open (see `Document::open`).
+    pub fn open<S: Into<String>>(&mut self, size: Size, file_name: S) -> Result<usize> {
+        let file_name = file_name.into();
+        if let Some(idx) = self.find(&file_name) {
+            self.switch(idx);
+            return Ok(idx);
+        }
+        Ok(self.add(Document::open(size, file_name)?))
+    }
+
+    /// Make a document the active one, moving it to the front of the recently used list.
+    /// Each document keeps its own cursor and viewport, so they are as they were left.
+    /// Returns false if there is no document at the index.
+    pub fn switch(&mut self, idx: usize) -> bool {
+        if idx >= self.documents.len() {
+            return false;
+        }
+        self.active = idx;
+        self.recent.retain(|&i| i != idx);
+        self.recent.insert(0, idx);
+        true
+    }
+
+    /// Get the index of the document that was used before the active one, if there is one
+    #[must_use]
+    pub fn previous(&self) -> Option<usize> {
+        self.recent.iter().copied().find(|&idx| idx != self.active)
+    }
+
+    /// Switch to the document that was used before the active one, like vim's Ctrl+^,
+    /// returning its index
+    pub fn switch_to_previous(&mut self) -> Option<usize> {
+        let idx = self.previous()?;
+        self.switch(idx);
+        Some(idx)
+    }
+
+    /// Find the index of the document behind a path, if it is open
+    #[must_use]
+    pub fn find<P: AsRef<Path>>(&self, path: P) -> Option<usize> {
+        self.documents.iter().position(|doc| doc.is_same_file(&path))
+    }
+
+    /// Close a document, returning it, with a policy for what to do if it has unsaved changes.
+    /// The active document stays the same where possible, and if it is the one being closed,
+    /// the most recently used document takes its place.
+    /// Where the cursor and viewport were is remembered for when its file is opened again.
+    /// # Errors
+    /// Returns `Error::OutOfRange` if there is no document at the index,
+    /// `Error::UnsavedChanges` if it has unsaved changes and the policy is
+    /// `ClosePolicy::FailIfModified`, or the error from saving it with `ClosePolicy::SaveFirst`.
+    /// The document is left open if there is an error.
+    pub fn close(&mut self, idx: usize, policy: ClosePolicy) -> Result<Document> {
+        let doc = self.documents.get_mut(idx).ok_or(Error::OutOfRange)?;
+        if doc.modified {
+            match policy {
+                ClosePolicy::FailIfModified => return Err(Error::UnsavedChanges),
+                ClosePolicy::SaveFirst => doc.save()?,
+                ClosePolicy::Force => (),
+            }
+        }
+        let doc = self.documents.remove(idx);
+        if let Some(file_name) = &doc.file_name {
+            self.positions.insert(file_name.clone(), (doc.loc(), doc.offset));
+        }
+        let was_active = self.active == idx;
+        self.recent.retain(|&i| i != idx);
+        for i in &mut self.recent {
+            if *i > idx {
+                *i -= 1;
+            }
+        }
+        if self.active > idx || self.active >= self.documents.len() {
+            self.active = self.active.saturating_sub(1);
+        }
+        if let Some(history) = &mut self.history {
+            if idx < history.committed.len() {
+                history.committed.remove(idx);
+            }
+            for change in history.undo.iter_mut().chain(history.redo.iter_mut()) {
+                change.documents.retain(|(i, _)| *i != idx);
+                for (i, _) in &mut change.documents {
+                    if *i > idx {
+                        *i -= 1;
+                    }
+                }
+            }
+            history.undo.retain(|change| !change.documents.is_empty());
+            history.redo.retain(|change| !change.documents.is_empty());
+        }
+        let next = match self.recent.first() {
+            Some(&last) if was_active => last,
+            _ => self.active,
+        };
+        self.switch(next);
+        Ok(doc)
+    }
+
+    /// Close every document, such as when quitting, with a policy for what to do about unsaved
+    /// changes (see `Workspace::close`).
+    /// Returns the documents that couldn't be closed, which are left open, so that the user can
+    /// be asked what to do about them.
+    pub fn close_all(&mut self, policy: ClosePolicy) -> Vec<BufferEntry> {
+        let mut idx = 0;
+        while idx < self.documents.len() {
+            if self.close(idx, policy).is_err() {
+                idx += 1;
+            }
+        }
+        self.list()
+    }
+
+    /// Get the document that is being edited
+    #[must_use]
+    pub fn active(&self) -> Option<&Document> {
+        self.documents.get(self.active)
+    }
+
+    /// Get the document that is being edited, mutably
+    pub fn active_mut(&mut self) -> Option<&mut Document> {
+        self.documents.get_mut(self.active)
+    }
+
+    /// Start keeping a log of the changes made across documents, so that `Workspace::undo` can
+    /// undo the last change in any document, and changes to several files at once (such as
+    /// `Workspace::apply`) can be undone as one.
+    /// Edits made so far aren't included.
+    pub fn enable_history(&mut self) {
+        let committed = self.documents.iter().map(|doc| doc.event_mgmt.committed).collect();
+        self.history = Some(GlobalHistory { committed, ..GlobalHistory::default() });
+    }
+
+    /// Commit the current patch of every document, and log the patches made since the last time
+    /// this was called as a single change (if the history is turned on).
+    /// Front ends can call this after each action, instead of committing documents themselves.
+    pub fn record(&mut self) {
+        let Some(history) = &mut self.history else { return };
+        let mut change = GlobalChange::default();
+        for (idx, doc) in self.documents.iter_mut().enumerate() {
+            doc.event_mgmt.commit();
+            if idx >= history.committed.len() {
+                history.committed.push(0);
+            }
+            let patches = doc.event_mgmt.committed_since(history.committed[idx]);
+            if patches > 0 {
+                change.documents.push((idx, patches));
+            }
+            history.committed[idx] = doc.event_mgmt.committed;
+        }
+        if !change.documents.is_empty() {
+            history.undo.push(change);
+            history.redo.clear();
+        }
+    }
+
+    /// Undo the last change made in any document (or across several), making the first document
+    /// it changed the active one.
+    /// Returns false if there is nothing to undo, or the history isn't turned on.
+    /// # Errors
+    /// Returns an error if a document failed to undo.
+    pub fn undo(&mut self) -> Result<bool> {
+        self.record();
+        let Some(change) = self.history.as_mut().and_then(|h| h.undo.pop()) else {
+            return Ok(false);
+        };
+        self.replay(&change, Document::undo)?;
+        if let Some(history) = &mut self.history {
+            history.redo.push(change);
+        }
+        Ok(true)
+    }
+
+    /// Redo the last change that was undone with `Workspace::undo`.
+    /// Returns false if there is nothing to redo, or the history isn't turned on.
+    /// # Errors
+    /// Returns an error if a document failed to redo.
+    pub fn redo(&mut self) -> Result<bool> {
+        self.record();
+        let Some(change) = self.history.as_mut().and_then(|h| h.redo.pop()) else {
+            return Ok(false);
+        };
+        self.replay(&change, Document::redo)?;
+        if let Some(history) = &mut self.history {
+            history.undo.push(change);
+        }
+        Ok(true)
+    }
+
+    /// Undo or redo the patches of a change in each of its documents
+    fn replay<F>(&mut self, change: &GlobalChange, mut op: F) -> Result<()>
+    where
+        F: FnMut(&mut Document) -> Result<Status>,
+    {
+        for &(idx, patches) in change.documents.iter().rev() {
+            let Some(doc) = self.documents.get_mut(idx) else { continue };
+            for _ in 0..patches {
+                op(doc)?;
+            }
+            let committed = doc.event_mgmt.committed;
+            if let Some(seen) = self.history.as_mut().and_then(|h| h.committed.get_mut(idx)) {
+                *seen = committed;
+            }
+            self.switch(idx);
+        }
+        Ok(())
+    }
+
+    /// Describe each open document, in the order they are in the workspace
+    #[must_use]
+    pub fn list(&self) -> Vec<BufferEntry> {
+        self.documents
+            .iter()
+            .enumerate()
+            .map(|(index, doc)| {
+                let info = doc.status_info();
+                BufferEntry {
+                    index,
+                    name: info.file_name.unwrap_or_else(|| "[No Name]".to_string()),
+                    path: doc.file_name.clone(),
+                    modified: info.modified,
+                    line: info.line,
+                    file_type: info.file_type,
+                    active: index == self.active,
+                }
+            })
+            .collect()
+    }
+
+    /// Search every open document for a regular expression, including unsaved changes
+    /// # Errors
+    /// Returns an error if the pattern isn't a valid regular expression.
+    pub fn search(&self, pattern: &str) -> Result<Vec<QuickfixEntry>> {
+        Ok(self.search_cancellable(pattern, &AtomicBool::new(false))?.unwrap_or_default())
+    }
+
+    /// Search every open document for a regular expression, like `Workspace::search`,
+    /// stopping part way through if `cancel` is set, in which case `None` is returned.
+    /// # Errors
+    /// Returns an error if the pattern isn't a valid regular expression.
+    pub fn search_cancellable(
+        &self,
+        pattern: &str,
+        cancel: &AtomicBool,
+    ) -> Result<Option<Vec<QuickfixEntry>>> {
+        let mut results = vec![];
+        let re = Regex::new(pattern)?;
+        // Without a directory to look through, the search can't fail
+        let finished = self.scan(None, cancel, |document, file, y, line| {
+            results.extend(find_all(&re, line, y).into_iter().map(|loc| QuickfixEntry {
+                file: file.clone(),
+                document,
+                loc,
+                text: line.to_string(),
+            }));
+        });
+        Ok(matches!(finished, Ok(true)).then_some(results))
+    }
+
+    /// Search the open documents and then the files on disk under a directory for a regular
+    /// expression, passing each result to a callback as soon as it is found.
+    /// Hidden files and directories are skipped, as are files that can't be read as text.
+    /// Files that are open are searched from memory rather than disk, so unsaved changes count.
+    /// Setting `cancel` stops the search, and `false` is returned if it was cancelled.
+    /// # Errors
+    /// Returns an error if the pattern isn't a valid regular expression, or the directory
+    /// couldn't be read.
+    pub fn search_files<P, F>(
+        &self,
+        pattern: &str,
+        root: P,
+        cancel: &AtomicBool,
+        mut on_match: F,
+    ) -> Result<bool>
+    where
+        P: AsRef<Path>,
+        F: FnMut(QuickfixEntry),
+    {
+        let re = Regex::new(pattern)?;
+        self.scan(Some(root.as_ref()), cancel, |document, file, y, line| {
+            for loc in find_all(&re, line, y) {
+                on_match(QuickfixEntry { file: file.clone(), document, loc, text: line.to_string() });
+            }
+        })
+    }
+
+    /// Work out the replacements for every match of a regular expression in the open documents,
+    /// and in the files under a directory if one is given, without changing anything yet.
+    /// The replacement text can refer to capture groups (e.g. `$1`).
+    /// All the edits start off approved, use `Workspace::apply` to carry them out.
+    /// # Errors
+    /// Returns an error if the pattern isn't a valid regular expression, or the directory
+    /// couldn't be read.
+    pub fn plan_replace(
+        &self,
+        pattern: &str,
+        into: &str,
+        root: Option<&Path>,
+    ) -> Result<Replacement> {
+        let re = Regex::new(pattern)?;
+        let mut edits = vec![];
+        self.scan(root, &AtomicBool::new(false), |document, file, y, line| {
+            for cap in re.captures_iter(line) {
+                let Some(m) = cap.get(0) else { continue };
+                let mut new = String::new();
+                cap.expand(into, &mut new);
+                edits.push(ProposedEdit {
+                    file: file.clone(),
+                    document,
+                    loc: Loc::at(Searcher::raw_to_char(m.start(), line), y),
+                    old: m.as_str().to_string(),
+                    new,
+                    approved: true,
+                });
+            }
+        })?;
+        Ok(Replacement { edits })
+    }
+
+    /// Carry out the approved edits of a replacement, opening any files that aren't open yet.
+    /// Each document is edited through its event stack as a single undo patch.
+    /// Edits where the text no longer matches (because the document changed) are skipped.
+    /// If the history is turned on, the edits are logged as a single change.
+    /// Returns the number of edits that were made.
+    /// # Errors
+    /// Returns an error if a file fails to open, or an edit fails.
+    pub fn apply(&mut self, size: Size, replacement: &Replacement) -> Result<usize> {
+        self.record();
+        let active = self.active;
+        let mut targets: Vec<(usize, &ProposedEdit)> = vec![];
+        for edit in replacement.edits.iter().filter(|e| e.approved) {
+            let idx = match (&edit.file, edit.document) {
+                (_, Some(idx)) => idx,
+                (Some(file), None) => self.open(size, file.clone())?,
+                (None, None) => continue,
+            };
+            targets.push((idx, edit));
+        }
+        self.switch(active);
+        // Work backwards so that earlier edits aren't moved by later ones
+        targets.sort_by_key(|(idx, edit)| (*idx, edit.loc));
+        // Stop the grouping policy of each document from splitting up its edits
+        for doc in &mut self.documents {
+            doc.event_mgmt.begin_batch();
+        }
+        let mut result = Ok(());
+        let mut count = 0;
+        for (idx, edit) in targets.into_iter().rev() {
+            let Some(doc) = self.documents.get_mut(idx) else { continue };
+            doc.load_to(edit.loc.y + 1);
+            let current: String = doc
+                .line(edit.loc.y)
+                .unwrap_or_default()
+                .chars()
+                .skip(edit.loc.x)
+                .take(edit.old.chars().count())
+                .collect();
+            if current == edit.old {
+                result = doc.replace(edit.loc, &edit.old, &edit.new);
+                if result.is_err() {
+                    break;
+                }
+                count += 1;
+            }
+        }
+        for doc in &mut self.documents {
+            doc.event_mgmt.end_batch();
+        }
+        self.record();
+        result.map(|()| count)
+    }
+
+    /// Run through every line of the open documents, and then the files on disk under a
+    /// directory (if one is given) that aren't open
+    fn scan<F>(&self, root: Option<&Path>, cancel: &AtomicBool, mut on_line: F) -> Result<bool>
+    where
+        F: FnMut(Option<usize>, &Option<String>, usize, &str),
+    {
+        for (idx, doc) in self.documents.iter().enumerate() {
+            if cancel.load(Ordering::Relaxed) {
+                return Ok(false);
+            }
+            for (y, line) in doc.file.lines().enumerate() {
+                let line = line.to_string();
+                on_line(Some(idx), &doc.file_name, y, line.trim_end_matches(['\n', '\r']));
+            }
+        }
+        let Some(root) = root else { return Ok(true) };
+        let mut dirs = vec![root.to_path_buf()];
+        while let Some(dir) = dirs.pop() {
+            let mut entries: Vec<PathBuf> = std::fs::read_dir(&dir)?
+                .filter_map(|e| e.ok().map(|e| e.path()))
+                .filter(|p| !p.file_name().is_some_and(|n| n.to_string_lossy().starts_with('.')))
+                .collect();
+            entries.sort();
+            for path in entries {
+                if cancel.load(Ordering::Relaxed) {
+                    return Ok(false);
+                }
+                if path.is_dir() {
+                    dirs.push(path);
+                } else if self.find(&path).is_none() {
+                    let Ok(contents) = std::fs::read_to_string(&path) else { continue };
+                    let file = Some(path.to_string_lossy().to_string());
+                    for (y, line) in contents.lines().enumerate() {
+                        on_line(None, &file, y, line);
+                    }
+                }
+            }
+        }
+        Ok(true)
+    }
+}
+
+/// Put the cursor and viewport of a document back where they were, keeping the cursor on screen
+fn restore_position(doc: &mut Document, (loc, offset): (Loc, Loc)) {
+    doc.load_to(loc.y + 1);
+    doc.goto(&loc);
+    let y = doc.loc().y;
+    if (offset.y..offset.y + doc.size.h).contains(&y) {
+        doc.offset.y = offset.y;
+        doc.cursor.y = y - offset.y;
+        doc.load_to(offset.y + doc.size.h);
+    }
+}
+
+/// Find the locations of every match of a regular expression on a line
+fn find_all(re: &Regex, line: &str, y: usize) -> Vec<Loc> {
+    re.find_iter(line)
+        .map(|m| Loc::at(Searcher::raw_to_char(m.start(), line), y))
+        .collect()
+}
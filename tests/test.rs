@@ -1,6 +1,9 @@
 #[cfg(test)]
-use kaolinite::{document::*, event::*, utils::*, map::*, searching::*};
+use kaolinite::{anchors::*, commands::*, completion::*, diff::*, document::*, event::*, headless::*, jobs::*, keymap::*, layout::*, utils::*, map::*, searching::*, syntax::*, tags::*, templates::*, workspace::*};
 use sugars::hmap;
+use std::path::Path;
+use std::sync::atomic::AtomicBool;
+use std::time::Duration;
 
 #[test]
 fn char_mapping() {
@@ -66,7 +69,7 @@ fn char_mapping() {
                (vec![(8, 2), (10, 3), (16, 5)], vec![(0, 0), (4, 1), (12, 4)]));
     assert_eq!(form_map(&test_data_string1, 3), (vec![], vec![]));
     assert_eq!(form_map(&test_data_string2, 5),
-               (vec![(10, 2), (12, 3), (19, 5)], vec![(0, 0), (5, 1), (14, 4)]));
+               (vec![(10, 2), (12, 3), (15, 5)], vec![(0, 0), (5, 1), (14, 4)]));
 }
 
 #[test]
@@ -465,6 +468,7 @@ fn disk_writing() {
         "    arst的st了st在st为sts\n123\nhello world!\n".to_string()
     );
     doc1.save_as("demos/6test.txt");
+    assert_eq!(doc1.file_name, Some("demos/6test.txt".to_string()));
     assert_eq!(
         std::fs::read_to_string("demos/6test.txt").unwrap(), 
         "    arst的st了st在st为sts\n123\nhello world!\n".to_string()
@@ -473,13 +477,18 @@ fn disk_writing() {
     doc1.insert_line(1, "  art的st了st在st为sts".to_string());
     doc1.save();
     assert_eq!(
-        std::fs::read_to_string("demos/6.txt").unwrap(), 
+        std::fs::read_to_string("demos/6test.txt").unwrap(), 
         "    arst的st了st在st为sts\n  art的st了st在st为sts\nhello world!\n".to_string()
     );
     assert_eq!(
-        std::fs::read_to_string("demos/6test.txt").unwrap(), 
+        std::fs::read_to_string("demos/6.txt").unwrap(), 
         "    arst的st了st在st为sts\n123\nhello world!\n".to_string()
     );
+    doc1.save_as("demos/6.txt");
+    assert_eq!(
+        std::fs::read_to_string("demos/6.txt").unwrap(), 
+        "    arst的st了st在st为sts\n  art的st了st在st为sts\nhello world!\n".to_string()
+    );
 }
 
 #[test]
@@ -561,6 +570,12 @@ fn undo() {
     assert_eq!(doc1.lines, orig2);
     doc1.redo();
     assert_eq!(doc1.lines, orig3);
+    assert_eq!(doc1.redo().unwrap(), Status::NothingToRedo);
+    assert_eq!(doc1.undo().unwrap(), Status::None);
+    assert!(doc1.modified);
+    assert_eq!(doc1.undo().unwrap(), Status::None);
+    assert!(!doc1.modified);
+    assert_eq!(doc1.undo().unwrap(), Status::NothingToUndo);
 }
 
 #[test]
@@ -668,11 +683,11 @@ fn fuzz() {
                 17 => { doc.move_page_down(); Ok(()) },
                 18 => { doc.move_prev_word(); Ok(()) },
                 19 => { doc.move_next_word(); Ok(()) },
-                20 => { doc.replace_all("a", "c"); Ok(()) },
+                20 => doc.replace_all("a", "c").map(|_| ()),
                 21 => { doc.event_mgmt.commit(); Ok(()) },
                 22 => { doc.event_mgmt.commit(); Ok(()) },
-                23 => { doc.undo() },
-                24 => { doc.redo() },
+                23 => doc.undo().map(|_| ()),
+                24 => doc.redo().map(|_| ()),
                 _ => Ok(()),
             };
             println!("{} | {}", doc.loc().x, doc.char_ptr);
@@ -693,6 +708,9 @@ fn blank_document() {
         std::fs::read_to_string("demos/dump.txt").unwrap(),
         "hello, world!\n".to_string()
     );
+    assert!(!document.is_untitled());
+    assert!(!document.modified);
+    assert!(document.save().is_ok());
 }
 
 #[test]
@@ -709,6 +727,3217 @@ fn read_only() {
     assert!(std::fs::read_to_string("demos/nonexist.txt").is_err());
 }
 
+#[test]
+#[allow(unused_must_use)]
+fn in_memory() {
+    // Test data
+    let size = Size { w: 10, h: 10 };
+    let mut doc1 = Document::from_reader(size, "hello\nworld\n".as_bytes()).unwrap();
+    doc1.load_to(10);
+    // Output
+    doc1.exe(Event::Insert(Loc { x: 5, y: 0 }, ", there".to_string()));
+    let mut out = vec![];
+    doc1.write_to(&mut out).unwrap();
+    // Verification
+    assert_eq!(doc1.file_name, None);
+    assert_eq!(doc1.line(1), Some("world".to_string()));
+    assert_eq!(String::from_utf8(out).unwrap(), "hello, there\nworld\n".to_string());
+    assert!(doc1.save().is_err());
+}
+
+#[test]
+#[cfg(feature = "ffi")]
+fn ffi() {
+    use kaolinite::ffi::*;
+    use std::ffi::{CStr, CString};
+    unsafe {
+        // Test data
+        let doc = kaolinite_document_new(10, 10);
+        let text = CString::new("hello").unwrap();
+        let path = CString::new("demos/8.txt").unwrap();
+        let insert = KaoliniteEventKind::Insert as i32;
+        let delete = KaoliniteEventKind::Delete as i32;
+        // Output
+        let code = kaolinite_document_exe(doc, insert, 0, 0, text.as_ptr());
+        let line = kaolinite_document_line(doc, 0);
+        let unknown = kaolinite_document_exe(doc, 9, 0, 0, text.as_ptr());
+        let long = kaolinite_document_open(path.as_ptr(), 10, 5);
+        let unloaded = kaolinite_document_exe(long, insert, 0, 50, text.as_ptr());
+        let past_end = kaolinite_document_exe(long, delete, 0, usize::MAX - 1, text.as_ptr());
+        // Verification
+        assert_eq!(code, KAOLINITE_OK);
+        assert_eq!(CStr::from_ptr(line).to_str(), Ok("hello"));
+        assert_eq!(kaolinite_document_char_loc(doc), KaoliniteLoc { x: 5, y: 0 });
+        assert!(kaolinite_document_line(doc, 5).is_null());
+        assert_eq!(kaolinite_document_save(doc), KAOLINITE_ERR);
+        assert_eq!(unknown, KAOLINITE_ERR);
+        assert_eq!(unloaded, KAOLINITE_OK);
+        assert_eq!(past_end, KAOLINITE_ERR);
+        kaolinite_string_free(line);
+        kaolinite_document_free(doc);
+        kaolinite_document_free(long);
+    }
+}
+
+#[test]
+#[cfg(feature = "scripting")]
+fn scripting() {
+    use kaolinite::scripting::ScriptHost;
+    // Test data
+    let mut doc = Document::new(Size { w: 10, h: 10 });
+    let mut host = ScriptHost::new(r#"
+        fn on_event(ev) {
+            if ev.kind == "insert" && ev.text == "(" {
+                this.insert(ev.x + 1, ev.y, ")");
+            }
+        }
+    "#).unwrap();
+    // Output
+    let ev = Event::Insert(Loc { x: 0, y: 0 }, "(".to_string());
+    doc.exe(ev.clone()).unwrap();
+    host.on_event(&mut doc, &ev).unwrap();
+    // Verification
+    assert!(host.has_hook("on_event"));
+    assert!(!host.has_hook("on_save"));
+    assert_eq!(doc.line(0), Some("()".to_string()));
+    assert!(ScriptHost::new("fn broken(").is_err());
+    let mut replacer = ScriptHost::new(r#"
+        fn on_save() {
+            this.insert(0, 0, "x");
+            this = 42;
+        }
+    "#).unwrap();
+    let replaced = replacer.call(&mut doc, "on_save", vec![]);
+    assert!(matches!(replaced, Err(Error::Script(_))));
+    assert_eq!(doc.line(0), Some(String::new()));
+}
+
+#[test]
+#[allow(unused_must_use)]
+fn command_registry() {
+    // Test data
+    let size = Size { w: 10, h: 10 };
+    let mut doc1 = Document::open(size, "demos/6.txt").unwrap();
+    doc1.load_to(100);
+    let mut cmds = Commands::default();
+    cmds.register("shout", |doc, args| {
+        doc.exe(Event::Insert(doc.char_loc(), args.join(" ").to_uppercase()))?;
+        Ok(Status::None)
+    });
+    // Output & Verification
+    assert_eq!(cmds.run(&mut doc1, "move_up", &[]).unwrap(), Status::StartOfFile);
+    cmds.run_line(&mut doc1, "goto 3 7").unwrap();
+    assert_eq!(doc1.char_loc(), Loc::at(6, 2));
+    cmds.run_line(&mut doc1, "shout big").unwrap();
+    assert_eq!(doc1.line(2), Some("hello BIGworld!".to_string()));
+    cmds.run_line(&mut doc1, "delete_line").unwrap();
+    assert_eq!(doc1.len_lines(), 2);
+    cmds.run_line(&mut doc1, "undo").unwrap();
+    assert_eq!(doc1.line(2), Some("hello world!".to_string()));
+    assert!(matches!(cmds.run_line(&mut doc1, "fly"), Err(Error::UnknownCommand(_))));
+    assert!(matches!(cmds.run_line(&mut doc1, "goto x"), Err(Error::InvalidArguments)));
+    assert!(cmds.names().contains(&"shout"));
+}
+
+#[test]
+fn keymapping() {
+    use std::time::{Duration, Instant};
+    // Test data
+    let mut keymap: Keymap<char> = Keymap::new("normal");
+    keymap.bind("normal", &['d', 'd'], "delete_line");
+    keymap.bind("normal", &['g'], "move_top");
+    keymap.bind("normal", &['g', 'g'], "move_top");
+    keymap.bind("normal", &['g', 'e'], "move_end");
+    keymap.bind("insert", &['j', 'k'], "normal_mode");
+    let start = Instant::now();
+    let later = start + Duration::from_secs(5);
+    // Output & Verification
+    assert_eq!(keymap.feed_at('d', start), KeyResult::Pending);
+    assert_eq!(keymap.feed_at('d', start), KeyResult::Command("delete_line".to_string()));
+    assert_eq!(keymap.feed_at('x', start), KeyResult::Unbound);
+    assert_eq!(keymap.feed_at('d', start), KeyResult::Pending);
+    assert_eq!(keymap.feed_at('x', start), KeyResult::Unbound);
+    assert!(!keymap.is_pending());
+    // Ambiguous sequences wait, then resolve on timeout
+    assert_eq!(keymap.feed_at('g', start), KeyResult::Pending);
+    assert_eq!(keymap.check_timeout(start), None);
+    assert_eq!(keymap.check_timeout(later), Some(KeyResult::Command("move_top".to_string())));
+    // Stale sequences are abandoned
+    assert_eq!(keymap.feed_at('d', start), KeyResult::Pending);
+    assert_eq!(keymap.feed_at('d', later), KeyResult::Pending);
+    // Modes
+    keymap.set_mode("insert");
+    assert_eq!(keymap.feed_at('d', start), KeyResult::Unbound);
+    assert_eq!(keymap.feed_at('j', start), KeyResult::Pending);
+    assert_eq!(keymap.feed_at('k', start), KeyResult::Command("normal_mode".to_string()));
+}
+
+#[test]
+#[allow(unused_must_use)]
+fn number_incrementing() {
+    // Test data
+    let size = Size { w: 10, h: 10 };
+    let text = "x = 9;\nid 007 and 0xfF\nlevel -3\nnone\n";
+    let mut doc1 = Document::from_reader(size, text.as_bytes()).unwrap();
+    doc1.load_to(10);
+    // Output & Verification
+    assert!(doc1.increment_number_at(&Loc::at(0, 0), 1).unwrap());
+    assert_eq!(doc1.line(0), Some("x = 10;".to_string()));
+    assert_eq!(doc1.char_loc(), Loc::at(5, 0));
+    doc1.increment_number_at(&Loc::at(3, 1), 5);
+    assert_eq!(doc1.line(1), Some("id 012 and 0xfF".to_string()));
+    doc1.increment_number_at(&Loc::at(7, 1), 1);
+    assert_eq!(doc1.line(1), Some("id 012 and 0x100".to_string()));
+    doc1.increment_number_at(&Loc::at(0, 2), 5);
+    assert_eq!(doc1.line(2), Some("level 2".to_string()));
+    doc1.increment_number_at(&Loc::at(0, 2), -4);
+    assert_eq!(doc1.line(2), Some("level -2".to_string()));
+    assert!(!doc1.increment_number_at(&Loc::at(0, 3), 1).unwrap());
+    assert!(!doc1.increment_number_at(&Loc::at(8, 2), 1).unwrap());
+    doc1.undo();
+    assert_eq!(doc1.line(2), Some("level 2".to_string()));
+    doc1.undo();
+    assert_eq!(doc1.line(2), Some("level -3".to_string()));
+    let mut doc2 = Document::from_str(size, "9223372036854775807 0x0 99999999999999999999\n");
+    doc2.load_to(2);
+    assert!(doc2.increment_number_at(&Loc::at(0, 0), 1).is_err());
+    assert!(doc2.increment_number_at(&Loc::at(20, 0), -1).is_err());
+    assert!(doc2.increment_number_at(&Loc::at(24, 0), 1).is_err());
+    assert!(doc2.increment_number_at(&Loc::at(0, 0), -7).unwrap());
+    assert_eq!(doc2.line(0), Some("9223372036854775800 0x0 99999999999999999999".to_string()));
+    let mut doc3 = Document::from_str(size, "x 9 y\n");
+    doc3.load_to(2);
+    doc3.set_grouping(GroupingPolicy::Count(1));
+    doc3.increment_number_at(&Loc::at(0, 0), 1);
+    assert_eq!(doc3.line(0), Some("x 10 y".to_string()));
+    doc3.undo();
+    assert_eq!(doc3.line(0), Some("x 9 y".to_string()));
+}
+
+#[test]
+#[allow(unused_must_use)]
+fn alignment() {
+    // Test data
+    let size = Size { w: 10, h: 10 };
+    let text = "a = 1\nlonger = 2\nno delimiter\n\t= 3\n蔼 = 4\n";
+    let mut doc1 = Document::from_reader(size, text.as_bytes()).unwrap();
+    doc1.load_to(10);
+    let orig = doc1.lines.clone();
+    // Output
+    doc1.align_on(0..5, "=");
+    // Verification
+    assert_eq!(doc1.lines[..5], vec![
+        "a      = 1".to_string(),
+        "longer = 2".to_string(),
+        "no delimiter".to_string(),
+        "\t   = 3".to_string(),
+        "蔼     = 4".to_string(),
+    ]);
+    assert!(doc1.align_on(0..20, "=").is_err());
+    doc1.undo();
+    assert_eq!(doc1.lines, orig);
+}
+
+#[test]
+#[allow(unused_must_use)]
+fn reflowing() {
+    // Test data
+    let size = Size { w: 10, h: 10 };
+    let text = "fn main() {\n    // the quick brown fox jumps over\n    // the lazy dog\n    //\n    // a extraordinarily long word\n}\n";
+    let mut doc1 = Document::from_reader(size, text.as_bytes()).unwrap();
+    doc1.load_to(10);
+    let orig = doc1.lines.clone();
+    // Output
+    doc1.reflow(1..5, 20);
+    // Verification
+    assert_eq!(doc1.lines[..11], vec![
+        "fn main() {".to_string(),
+        "    // the quick".to_string(),
+        "    // brown fox".to_string(),
+        "    // jumps over".to_string(),
+        "    // the lazy dog".to_string(),
+        "    //".to_string(),
+        "    // a".to_string(),
+        "    // extraordinarily".to_string(),
+        "    // long word".to_string(),
+        "}".to_string(),
+        "".to_string(),
+    ]);
+    assert_eq!(
+        reflow(&["# one two three".to_string()], 10, 4),
+        vec!["# one two".to_string(), "# three".to_string()],
+    );
+    let mixed = ["// a b".to_string(), "> c d".to_string(), "e f".to_string(), "// g".to_string()];
+    assert_eq!(
+        reflow(&mixed, 20, 4),
+        vec!["// a b".to_string(), "> c d".to_string(), "e f".to_string(), "// g".to_string()],
+    );
+    assert!(doc1.reflow(0..100, 20).is_err());
+    doc1.undo();
+    assert_eq!(doc1.lines, orig);
+}
+
+#[test]
+#[allow(unused_must_use)]
+fn final_newline() {
+    // Test data
+    let size = Size { w: 10, h: 10 };
+    let mut doc1 = Document::from_reader(size, "hello\nworld".as_bytes()).unwrap();
+    let mut doc2 = Document::from_reader(size, "hello\nworld\n".as_bytes()).unwrap();
+    let mut doc3 = Document::from_reader(size, "hello".as_bytes()).unwrap();
+    doc1.load_to(10);
+    doc2.load_to(10);
+    doc3.load_to(10);
+    // Output
+    let mut out1 = vec![];
+    let mut out2 = vec![];
+    let mut out3 = vec![];
+    doc1.write_to(&mut out1);
+    doc2.write_to(&mut out2);
+    doc3.set_insert_final_newline(true);
+    doc3.write_to(&mut out3);
+    // Verification
+    assert!(!doc1.insert_final_newline);
+    assert!(doc2.insert_final_newline);
+    assert_eq!(doc1.len_lines(), 2);
+    assert_eq!(doc1.line(1), Some("world".to_string()));
+    assert_eq!(out1, b"hello\nworld");
+    assert_eq!(out2, b"hello\nworld\n");
+    assert_eq!(out3, b"hello\n");
+}
+
+#[test]
+fn iterators() {
+    // Test data
+    let size = Size { w: 10, h: 10 };
+    let mut doc1 = Document::from_reader(size, "ab\ncd\n".as_bytes()).unwrap();
+    doc1.load_to(10);
+    // Output
+    let rows: Vec<&str> = doc1.iter_rows().rev().collect();
+    let forward: String = doc1.iter_chars_from(Loc::at(1, 0)).map(|(_, ch)| ch).collect();
+    let backward: String = doc1.iter_chars_before(Loc::at(1, 1)).map(|(_, ch)| ch).collect();
+    let locs: Vec<Loc> = doc1.iter_chars_before(Loc::at(0, 1)).map(|(loc, _)| loc).collect();
+    // Verification
+    assert_eq!(rows, vec!["cd", "ab"]);
+    assert_eq!(forward, "b\ncd\n");
+    assert_eq!(backward, "c\nba");
+    assert_eq!(locs, vec![Loc::at(2, 0), Loc::at(1, 0), Loc::at(0, 0)]);
+    assert_eq!(doc1.iter_chars_from(Loc::at(0, 2)).count(), 0);
+}
+
+#[test]
+#[allow(unused_must_use)]
+fn range_slicing() {
+    // Test data
+    let size = Size { w: 10, h: 10 };
+    let text = "hello world\n    the quick\nbrown fox\njumps\n";
+    let mut doc1 = Document::from_reader(size, text.as_bytes()).unwrap();
+    let mut doc2 = Document::from_reader(size, text.as_bytes()).unwrap();
+    doc1.load_to(10);
+    doc2.load_to(10);
+    let orig = doc1.lines.clone();
+    // Output
+    doc1.remove_range(Loc::at(3, 1), Loc::at(2, 3));
+    doc1.event_mgmt.commit();
+    doc2.remove_range(Loc::at(6, 0), Loc::at(11, 0));
+    // Verification
+    assert_eq!(doc2.text_in_range(Loc::at(6, 0), Loc::at(4, 1)).unwrap(), "\n    ");
+    assert_eq!(doc1.text_in_range(Loc::at(0, 0), Loc::at(6, 1)).unwrap(), "hello world\n   mps");
+    assert_eq!(doc1.loc(), Loc::at(3, 1));
+    assert_eq!(doc2.line(0), Some("hello ".to_string()));
+    assert!(doc1.text_in_range(Loc::at(0, 1), Loc::at(0, 0)).is_err());
+    assert!(doc1.text_in_range(Loc::at(0, 0), Loc::at(0, 10)).is_err());
+    doc1.undo();
+    assert_eq!(doc1.lines, orig);
+}
+
+#[test]
+#[allow(unused_must_use)]
+fn tab_width_deleting() {
+    // Test data
+    let size = Size { w: 10, h: 10 };
+    let text = "        x\n        y\n        z\n";
+    let mut doc = Document::from_str(size, text);
+    doc.set_tab_width(4);
+    doc.load_to(4);
+    // Output
+    doc.exe(Event::Delete(Loc::at(3, 0), " ".to_string()));
+    doc.exe(Event::Delete(Loc::at(3, 1), "  ".to_string()));
+    let removed = doc.remove_range(Loc::at(3, 2), Loc::at(4, 2));
+    // Verification
+    assert_eq!(doc.line(0), Some("    x".to_string()));
+    assert_eq!(doc.line(1), Some("      y".to_string()));
+    assert!(removed.is_ok());
+    assert_eq!(doc.line(2), Some("       z".to_string()));
+    doc.undo();
+    doc.undo();
+    assert_eq!(doc.file.to_string(), text);
+}
+
+#[test]
+#[allow(unused_must_use)]
+fn formatting() {
+    // Test data
+    let size = Size { w: 10, h: 10 };
+    let text = "fn main() {\nlet x = 1;\n    let y = 2;\n}\n";
+    let mut doc1 = Document::from_reader(size, text.as_bytes()).unwrap();
+    doc1.load_to(10);
+    doc1.goto(&Loc::at(1, 3));
+    let orig = doc1.lines.clone();
+    // Output
+    doc1.format_with(|text| {
+        text.lines()
+            .map(|l| if l.starts_with("let") { format!("    {l}") } else { l.to_string() })
+            .collect::<Vec<_>>()
+            .join("\n")
+    });
+    let patch = doc1.event_mgmt.undo_stack().last().unwrap().events.clone();
+    // Verification
+    assert_eq!(doc1.line(1), Some("    let x = 1;".to_string()));
+    assert_eq!(doc1.line(2), Some("    let y = 2;".to_string()));
+    assert_eq!(doc1.char_loc(), Loc::at(1, 3));
+    assert_eq!(patch.len(), 2);
+    doc1.format_with(|text| text.to_string());
+    assert_eq!(doc1.event_mgmt.undo_stack().len(), 1);
+    doc1.undo();
+    assert_eq!(doc1.lines, orig);
+}
+
+#[test]
+fn diffing() {
+    // Test data
+    let old = "a\nb\nc\nd\ne\n";
+    let new = "a\nc\nd\nx\ny\ne\nf\n";
+    // Output
+    let hunks = diff_lines(old, new);
+    // Verification
+    assert_eq!(hunks, vec![
+        Hunk { old: 1..2, new: 1..1 },
+        Hunk { old: 4..4, new: 3..5 },
+        Hunk { old: 5..5, new: 6..7 },
+    ]);
+    assert!(hunks[0].is_deletion());
+    assert!(hunks[1].is_insertion());
+    assert_eq!(diff_lines("a\nb\n", "a\nb\n"), vec![]);
+    assert_eq!(diff_lines("", "a\n"), vec![Hunk { old: 0..0, new: 0..1 }]);
+    assert_eq!(diff_lines("a\nb\n", "c\n"), vec![Hunk { old: 0..2, new: 0..1 }]);
+    assert_eq!(diff::<u8>(&[], &[]), vec![]);
+    // Many changes across a large document
+    let old: Vec<usize> = (0..2000).collect();
+    let new: Vec<usize> = old.iter().map(|n| if n % 2 == 0 { n + 2000 } else { *n }).collect();
+    let changed = diff(&old, &new);
+    assert_eq!(changed.len(), 1000);
+    assert_eq!(changed[1], Hunk { old: 2..3, new: 2..3 });
+    assert_eq!(map_line(&hunks, 0), 0);
+    assert_eq!(map_line(&hunks, 1), 1);
+    assert_eq!(map_line(&hunks, 3), 2);
+    assert_eq!(map_line(&hunks, 4), 5);
+}
+
+#[test]
+#[allow(unused_must_use)]
+fn gutter_states() {
+    // Test data
+    let size = Size { w: 10, h: 10 };
+    let text = "one\ntwo\nthree\n";
+    let mut doc1 = Document::from_reader(size, text.as_bytes()).unwrap();
+    doc1.load_to(10);
+    doc1.set_baseline(text);
+    // Output
+    let before: Vec<LineState> = (0..3).map(|y| doc1.line_state(y)).collect();
+    doc1.exe(Event::Insert(Loc::at(3, 1), "!".to_string()));
+    doc1.exe(Event::InsertLine(3, "four".to_string()));
+    let after: Vec<LineState> = (0..4).map(|y| doc1.line_state(y)).collect();
+    // Verification
+    assert_eq!(before, vec![LineState::Unchanged; 3]);
+    assert_eq!(after, vec![
+        LineState::Unchanged,
+        LineState::Modified,
+        LineState::Unchanged,
+        LineState::Added,
+    ]);
+    doc1.event_mgmt.commit();
+    doc1.undo();
+    assert_eq!(doc1.line_state(1), LineState::Unchanged);
+    // Line states follow edits without diffing, until they are refreshed
+    doc1.exe(Event::Insert(Loc::at(0, 0), "x".to_string()));
+    doc1.exe(Event::Delete(Loc::at(0, 0), "x".to_string()));
+    doc1.exe(Event::SplitDown(Loc::at(0, 2)));
+    let tracked: Vec<LineState> = (0..4).map(|y| doc1.line_state(y)).collect();
+    doc1.refresh_line_states();
+    let refreshed: Vec<LineState> = (0..4).map(|y| doc1.line_state(y)).collect();
+    assert_eq!(tracked, vec![
+        LineState::Modified,
+        LineState::Unchanged,
+        LineState::Added,
+        LineState::Unchanged,
+    ]);
+    assert_eq!(refreshed, vec![
+        LineState::Unchanged,
+        LineState::Unchanged,
+        LineState::Added,
+        LineState::Unchanged,
+    ]);
+    doc1.exe(Event::Insert(Loc::at(0, 0), "x".to_string()));
+    doc1.clear_baseline();
+    assert_eq!(doc1.line_state(0), LineState::Unchanged);
+}
+
+#[test]
+#[allow(unused_must_use)]
+fn merging() {
+    // Test data
+    let size = Size { w: 10, h: 10 };
+    let mut doc1 = Document::from_reader(size, "a\nb\nc\nd\n".as_bytes()).unwrap();
+    let mut doc2 = Document::from_reader(size, "a\nx\ny\nc\n".as_bytes()).unwrap();
+    doc1.load_to(10);
+    doc2.load_to(10);
+    // Output
+    let hunks = doc1.compare(&doc2);
+    let rows = align_lines(&hunks, 4, 4);
+    doc1.take_hunk(&doc2, &hunks[0]);
+    // Verification
+    assert_eq!(hunks, vec![Hunk { old: 1..2, new: 1..3 }, Hunk { old: 3..4, new: 4..4 }]);
+    assert_eq!(rows, vec![
+        (Some(0), Some(0)),
+        (Some(1), Some(1)),
+        (None, Some(2)),
+        (Some(2), Some(3)),
+        (Some(3), None),
+    ]);
+    assert_eq!(doc1.lines[..5], vec!["a", "x", "y", "c", "d"]);
+    assert_eq!(doc1.compare(&doc2).len(), 1);
+    assert!(doc1.take_hunk(&doc2, &Hunk { old: 0..9, new: 0..1 }).is_err());
+    doc1.undo();
+    assert_eq!(doc1.lines[..4], vec!["a", "b", "c", "d"]);
+}
+
+#[test]
+#[allow(unused_must_use)]
+fn empty_documents() {
+    // Test data
+    let size = Size { w: 10, h: 10 };
+    let mut doc1 = Document::new(size);
+    let mut doc2 = Document::from_reader(size, "".as_bytes()).unwrap();
+    doc2.load_to(10);
+    // Output
+    let mut out = vec![];
+    doc2.write_to(&mut out);
+    doc2.move_right();
+    doc2.move_end();
+    doc2.goto_x(0);
+    // Verification
+    assert_eq!(doc1.len_lines(), 1);
+    assert_eq!(doc2.len_lines(), 1);
+    assert_eq!(doc2.line(0), Some("".to_string()));
+    assert_eq!(doc2.loc(), Loc::at(0, 0));
+    assert_eq!(out, b"\n");
+    assert!(doc1.exe(Event::Insert(Loc::at(0, 0), "hi".to_string())).is_ok());
+    assert!(doc2.exe(Event::Insert(Loc::at(0, 0), "hi".to_string())).is_ok());
+    assert_eq!(doc1.line(0), Some("hi".to_string()));
+    assert_eq!(doc2.line(0), Some("hi".to_string()));
+    assert!(doc2.insert_final_newline);
+    let mut out = vec![];
+    doc2.write_to(&mut out);
+    assert_eq!(out, b"hi\n");
+}
+
+#[test]
+#[allow(unused_must_use)]
+fn cursor_clamping() {
+    // Test data
+    let size = Size { w: 10, h: 10 };
+    let mut doc1 = Document::from_reader(size, "hello\nhi\n\n".as_bytes()).unwrap();
+    doc1.load_to(10);
+    doc1.move_end();
+    // Output
+    doc1.set_cursor_past_end(false);
+    let clamped = doc1.char_loc();
+    let status = doc1.move_right();
+    doc1.move_down();
+    let below = doc1.char_loc();
+    doc1.move_down();
+    let empty = doc1.char_loc();
+    doc1.goto(&Loc::at(10, 0));
+    // Verification
+    assert_eq!(clamped, Loc::at(4, 0));
+    assert_eq!(status, Status::EndOfLine);
+    assert_eq!(below, Loc::at(1, 1));
+    assert_eq!(empty, Loc::at(0, 2));
+    assert_eq!(doc1.char_loc(), Loc::at(4, 0));
+    doc1.set_cursor_past_end(true);
+    doc1.move_end();
+    assert_eq!(doc1.char_loc(), Loc::at(5, 0));
+}
+
+#[test]
+#[allow(unused_must_use)]
+fn overwriting() {
+    // Test data
+    let size = Size { w: 10, h: 10 };
+    let mut doc1 = Document::from_reader(size, "hello world\n".as_bytes()).unwrap();
+    doc1.load_to(10);
+    doc1.set_overwrite(true);
+    // Output
+    doc1.exe(Event::Insert(Loc::at(0, 0), "J".to_string()));
+    doc1.event_mgmt.commit();
+    doc1.exe(Event::Insert(Loc::at(9, 0), "dies".to_string()));
+    doc1.event_mgmt.commit();
+    // Verification
+    assert_eq!(doc1.line(0), Some("Jello wordies".to_string()));
+    assert_eq!(doc1.char_loc(), Loc::at(13, 0));
+    doc1.undo();
+    assert_eq!(doc1.line(0), Some("Jello world".to_string()));
+    doc1.undo();
+    assert_eq!(doc1.line(0), Some("hello world".to_string()));
+    doc1.redo();
+    assert_eq!(doc1.line(0), Some("Jello world".to_string()));
+    let mut doc2 = Document::from_str(size, "foo bar\n");
+    doc2.load_to(2);
+    doc2.set_grouping(GroupingPolicy::Count(1));
+    doc2.replace(Loc::at(4, 0), "bar", "baz");
+    assert_eq!(doc2.line(0), Some("foo baz".to_string()));
+    doc2.undo();
+    assert_eq!(doc2.line(0), Some("foo bar".to_string()));
+}
+
+#[test]
+#[allow(unused_must_use)]
+fn cursor_wrapping() {
+    // Test data
+    let size = Size { w: 10, h: 10 };
+    let mut doc1 = Document::from_reader(size, "ab\ncd\n".as_bytes()).unwrap();
+    doc1.load_to(10);
+    // Output
+    let unwrapped = doc1.move_left();
+    doc1.set_wrap_cursor(true);
+    let start = doc1.move_left();
+    doc1.goto(&Loc::at(2, 0));
+    let right = doc1.move_right();
+    let after_right = doc1.char_loc();
+    let left = doc1.move_left();
+    let after_left = doc1.char_loc();
+    // Verification
+    assert_eq!(unwrapped, Status::StartOfLine);
+    assert_eq!(start, Status::StartOfLine);
+    assert_eq!(right, Status::None);
+    assert_eq!(after_right, Loc::at(0, 1));
+    assert_eq!(left, Status::None);
+    assert_eq!(after_left, Loc::at(2, 0));
+    doc1.move_bottom();
+    assert_eq!(doc1.move_right(), Status::EndOfLine);
+}
+
+#[test]
+#[allow(unused_must_use)]
+fn vertical_movement() {
+    // Test data
+    let size = Size { w: 10, h: 4 };
+    let text = "0\n1\n2\n3\n4\n5\n6\n7\n8\n9 long\n";
+    let mut doc1 = Document::from_reader(size, text.as_bytes()).unwrap();
+    doc1.load_to(4);
+    doc1.set_scroll_step(3);
+    // Output
+    doc1.move_half_page_down();
+    let first = (doc1.cursor, doc1.offset);
+    doc1.move_vertical(2);
+    let scrolled = (doc1.cursor, doc1.offset);
+    doc1.move_vertical(5);
+    doc1.move_end();
+    let bottom = doc1.char_loc();
+    doc1.move_vertical(-1);
+    let snapped = doc1.char_loc();
+    // Verification
+    assert_eq!(first, (Loc::at(0, 2), Loc::at(0, 0)));
+    assert_eq!(scrolled, (Loc::at(0, 1), Loc::at(0, 3)));
+    assert_eq!(bottom, Loc::at(6, 9));
+    assert_eq!(snapped, Loc::at(1, 8));
+    assert_eq!(doc1.move_vertical(-100), Status::None);
+    assert_eq!(doc1.char_loc(), Loc::at(1, 0));
+    assert_eq!(doc1.offset, Loc::at(0, 0));
+    assert_eq!(doc1.move_half_page_up(), Status::StartOfFile);
+    doc1.move_vertical(100);
+    assert_eq!(doc1.loc().y, 10);
+    assert_eq!(doc1.move_half_page_down(), Status::EndOfFile);
+}
+
+#[test]
+fn context_lines() {
+    // Test data
+    let size = Size { w: 10, h: 10 };
+    let mut doc1 = Document::from_reader(size, "a\nb\nc\nd\n".as_bytes()).unwrap();
+    doc1.load_to(10);
+    // Output
+    let middle = doc1.context(&Loc::at(0, 2), 1, 0);
+    let start = doc1.context(&Loc::at(0, 0), 2, 1);
+    let end = doc1.context(&Loc::at(0, 3), 0, 5);
+    // Verification
+    assert_eq!(middle, vec![(1, "b"), (2, "c")]);
+    assert_eq!(start, vec![(0, "a"), (1, "b")]);
+    assert_eq!(end, vec![(3, "d")]);
+    assert_eq!(doc1.context(&Loc::at(0, 9), 1, 1), vec![]);
+}
+
+#[test]
+fn rulers() {
+    // Test data
+    let size = Size { w: 10, h: 10 };
+    let mut doc1 = Document::from_reader(size, "\tab\n好好好\nshort\n".as_bytes()).unwrap();
+    doc1.load_to(10);
+    // Output
+    let unset = doc1.overflow(0);
+    doc1.set_ruler(Some(5));
+    // Verification
+    assert_eq!(unset, None);
+    assert_eq!(doc1.display_width_up_to(&Loc::at(2, 0)), 5);
+    assert_eq!(doc1.display_width_up_to(&Loc::at(2, 1)), 4);
+    assert_eq!(doc1.overflow(0), Some(2..3));
+    assert_eq!(doc1.overflow(1), Some(2..3));
+    assert_eq!(doc1.overflow(2), None);
+    assert_eq!(doc1.overflow(9), None);
+}
+
+#[test]
+fn guides() {
+    // Test data
+    let size = Size { w: 10, h: 10 };
+    let mut doc1 = Document::from_reader(size, "hello\nhello world\n".as_bytes()).unwrap();
+    doc1.load_to(10);
+    // Output
+    doc1.set_guides(vec![8, 3, 20, 8]);
+    // Verification
+    assert_eq!(doc1.guides, vec![3, 8, 20]);
+    assert_eq!(doc1.guides_in_view(), vec![3, 8]);
+    assert_eq!(doc1.line_trim_guides(0, 0, 10, '|'), Some("hello   |".to_string()));
+    assert_eq!(doc1.line_trim_guides(1, 0, 10, '|'), Some("hello worl".to_string()));
+    assert_eq!(doc1.line_trim_guides(0, 2, 10, '|'), Some("llo   |".to_string()));
+    doc1.offset.x = 5;
+    assert_eq!(doc1.guides_in_view(), vec![3]);
+}
+
+#[test]
+#[allow(unused_must_use)]
+fn tab_stops() {
+    // Test data
+    let size = Size { w: 20, h: 10 };
+    let mut doc1 = Document::from_reader(size, "ab\tc\nabcde\tf\n".as_bytes()).unwrap();
+    doc1.load_to(10);
+    // Output
+    doc1.goto(&Loc::at(3, 0));
+    let first = doc1.loc();
+    doc1.goto(&Loc::at(6, 1));
+    let second = doc1.loc();
+    doc1.move_left();
+    let left = doc1.loc();
+    doc1.exe(Event::Insert(Loc::at(0, 0), "x".to_string()));
+    doc1.move_end();
+    let inserted = doc1.loc();
+    // Verification
+    assert_eq!(tab_stop(0, 4), 4);
+    assert_eq!(tab_stop(6, 4), 2);
+    assert_eq!(width("ab\tc", 4), 5);
+    assert_eq!(expand_tabs("ab\tc\td", 4), "ab  c   d");
+    assert_eq!(first, Loc::at(4, 0));
+    assert_eq!(second, Loc::at(8, 1));
+    assert_eq!(left, Loc::at(5, 1));
+    assert_eq!(inserted, Loc::at(5, 0));
+    assert_eq!(doc1.width_of(1, 5), 3);
+    doc1.set_tab_width(2);
+    assert_eq!(doc1.width_of(1, 5), 1);
+}
+
+#[test]
+fn layouts() {
+    // Test data
+    let size = Size { w: 10, h: 10 };
+    let text = "line\n".repeat(120);
+    let mut doc1 = Document::from_reader(size, text.as_bytes()).unwrap();
+    doc1.load_to(10);
+    let options = LayoutOptions {
+        line_numbers: true,
+        gutter_padding: 2,
+        top_lines: 1,
+        bottom_lines: 1,
+        ..LayoutOptions::default()
+    };
+    // Output
+    let layout = Layout::new(Size::is(80, 24), &doc1, &options);
+    let bare = Layout::new(Size::is(80, 24), &doc1, &LayoutOptions::default());
+    let tiny = Layout::new(Size::is(3, 1), &doc1, &options);
+    layout.apply(&mut doc1);
+    doc1.goto(&Loc::at(2, 3));
+    // Verification
+    assert_eq!(layout, Layout { gutter: 5, origin: Loc::at(5, 1), size: Size::is(75, 22) });
+    assert_eq!(bare, Layout { gutter: 0, origin: Loc::at(0, 0), size: Size::is(80, 24) });
+    assert_eq!(tiny.size, Size::is(0, 0));
+    assert_eq!(doc1.size, Size::is(75, 22));
+    assert_eq!(layout.cursor(&doc1), Loc::at(7, 4));
+    assert!(layout.contains(&Loc::at(5, 1)));
+    assert!(!layout.contains(&Loc::at(4, 1)));
+    assert!(!layout.contains(&Loc::at(5, 23)));
+}
+
+#[test]
+fn truncating_and_padding() {
+    // Test data
+    let test1 = "hello world";
+    let test2 = "好好好好";
+    let test3 = "\tab";
+    // Output
+    let results = vec![
+        truncate(test1, 20, "…", 4),
+        truncate(test1, 8, "…", 4),
+        truncate(test1, 8, "...", 4),
+        truncate(test2, 6, "…", 4),
+        truncate(test3, 5, "…", 4),
+        truncate(test1, 2, "...", 4),
+        pad_to(test1, 13, Align::Left, 4),
+        pad_to(test1, 14, Align::Center, 4),
+        pad_to(test2, 10, Align::Right, 4),
+        pad_to(test3, 8, Align::Right, 4),
+        pad_to(test1, 5, Align::Left, 4),
+    ];
+    // Verification
+    assert_eq!(results, vec![
+        "hello world".to_string(),
+        "hello w…".to_string(),
+        "hello...".to_string(),
+        "好好…".to_string(),
+        "\t…".to_string(),
+        "..".to_string(),
+        "hello world  ".to_string(),
+        " hello world  ".to_string(),
+        "  好好好好".to_string(),
+        "      ab".to_string(),
+        "hello world".to_string(),
+    ]);
+}
+
+#[test]
+fn side_alignment() {
+    // Test data
+    let lhs = "main.rs [+]";
+    let rhs = "12/40";
+    // Output
+    let results = vec![
+        align_sides(lhs, rhs, 20, Align::Left, 4),
+        align_sides(lhs, rhs, 12, Align::Right, 4),
+        align_sides(lhs, rhs, 12, Align::Left, 4),
+        align_sides(lhs, rhs, 4, Align::Right, 4),
+        align_middle("a", "mid", "b", 11, 4),
+        align_middle("left side", "mid", "right side", 11, 4),
+        align_middle("a", "middle", "b", 4, 4),
+    ];
+    // Verification
+    assert_eq!(results, vec![
+        "main.rs [+]    12/40".to_string(),
+        "main.… 12/40".to_string(),
+        "main.rs [+] ".to_string(),
+        "12/…".to_string(),
+        "a   mid   b".to_string(),
+        "le… mid ri…".to_string(),
+        "mid…".to_string(),
+    ]);
+}
+
+#[test]
+fn status_info() {
+    // Test data
+    let size = Size { w: 10, h: 10 };
+    let mut doc1 = Document::from_reader(size, "hello\nworld\n".as_bytes()).unwrap();
+    let mut doc2 = Document::new(size);
+    doc1.load_to(10);
+    doc1.file_name = Some("src/main.rs".to_string());
+    doc2.file_name = Some("project/Makefile".to_string());
+    // Output
+    doc1.goto(&Loc::at(3, 1));
+    let info1 = doc1.status_info();
+    let info2 = doc2.status_info();
+    let info3 = Document::new(size).status_info();
+    // Verification
+    assert_eq!(info1, StatusInfo {
+        file_name: Some("main.rs".to_string()),
+        extension: Some("rs".to_string()),
+        file_type: Some("Rust".to_string()),
+        modified: false,
+        read_only: false,
+        line: 2,
+        total_lines: 2,
+        column: 4,
+    });
+    assert_eq!(info2.file_name, Some("Makefile".to_string()));
+    assert_eq!(info2.extension, None);
+    assert_eq!(info2.file_type, None);
+    assert_eq!(info3.file_name, None);
+}
+
+#[test]
+fn path_shortening() {
+    // Test data
+    let path = "/home/user/src/document/editor/main.rs";
+    let home = Some("/home/user");
+    // Output
+    let results = vec![
+        shorten_path(path, 40, home),
+        shorten_path(path, 27, home),
+        shorten_path(path, 20, home),
+        shorten_path(path, 14, None),
+        shorten_path("/home/user/.config/cactus/init.lua", 20, home),
+        shorten_path("/home/username/a.rs", 30, home),
+        shorten_path("main.rs", 3, None),
+    ];
+    // Verification
+    assert_eq!(results, vec![
+        "~/src/document/editor/main.rs".to_string(),
+        "~/s/document/editor/main.rs".to_string(),
+        "~/s/d/editor/main.rs".to_string(),
+        "…s/d/e/main.rs".to_string(),
+        "~/.c/cactus/init.lua".to_string(),
+        "/home/username/a.rs".to_string(),
+        "…rs".to_string(),
+    ]);
+}
+
+#[test]
+fn metadata() {
+    // Test data
+    let size = Size { w: 10, h: 10 };
+    let mut doc = Document::new(size);
+    doc.file_name = Some("src/main.rs".to_string());
+    // Output
+    let title1 = doc.title();
+    doc.set_title(Some("scratch".to_string()));
+    let title2 = doc.title();
+    let first = doc.set_meta("last_search", "foo");
+    let second = doc.set_meta("last_search", "bar");
+    let found = doc.meta("last_search").map(str::to_string);
+    let removed = doc.remove_meta("last_search");
+    // Verification
+    assert_eq!(title1, Some("main.rs".to_string()));
+    assert_eq!(title2, Some("scratch".to_string()));
+    assert_eq!(first, None);
+    assert_eq!(second, Some("foo".to_string()));
+    assert_eq!(found, Some("bar".to_string()));
+    assert_eq!(removed, Some("bar".to_string()));
+    assert_eq!(doc.meta("last_search"), None);
+    assert_eq!(Document::new(size).title(), None);
+}
+
+#[test]
+#[allow(unused_must_use)]
+fn untitled_documents() {
+    // Test data
+    let size = Size { w: 10, h: 10 };
+    let mut doc1 = Document::untitled(size);
+    let doc2 = Document::untitled(size);
+    // Output
+    let title1 = doc1.title().unwrap();
+    let title2 = doc2.title().unwrap();
+    doc1.exe(Event::Insert(Loc::at(0, 0), "hi".to_string()));
+    doc1.modified = true;
+    doc1.save_as("demos/untitled.txt");
+    // Verification
+    assert!(doc2.is_untitled());
+    assert!(title1.starts_with("untitled-"));
+    assert!(title2.starts_with("untitled-"));
+    assert_ne!(title1, title2);
+    assert!(!doc1.is_untitled());
+    assert!(!doc1.modified);
+    assert_eq!(doc1.title(), Some("untitled.txt".to_string()));
+    assert_eq!(std::fs::read_to_string("demos/untitled.txt").unwrap(), "hi\n".to_string());
+    std::fs::remove_file("demos/untitled.txt");
+}
+
+#[test]
+#[allow(unused_must_use)]
+fn renaming() {
+    // Test data
+    let size = Size { w: 10, h: 10 };
+    std::fs::write("demos/rename.txt", "hello\n");
+    let mut doc1 = Document::open(size, "demos/rename.txt").unwrap();
+    let mut doc2 = Document::untitled(size);
+    // Output
+    let result = doc1.rename("demos/renamed.rs");
+    doc2.rename("demos/never_saved.txt");
+    // Verification
+    assert!(result.is_ok());
+    assert_eq!(doc1.file_name, Some("demos/renamed.rs".to_string()));
+    assert_eq!(doc1.status_info().file_type, Some("Rust".to_string()));
+    assert!(std::fs::read_to_string("demos/rename.txt").is_err());
+    assert_eq!(std::fs::read_to_string("demos/renamed.rs").unwrap(), "hello\n".to_string());
+    assert_eq!(doc2.title(), Some("never_saved.txt".to_string()));
+    assert!(std::fs::read_to_string("demos/never_saved.txt").is_err());
+    std::fs::remove_file("demos/renamed.rs");
+}
+
+#[test]
+#[allow(unused_must_use)]
+fn permissions() {
+    // Test data
+    let size = Size { w: 10, h: 10 };
+    std::fs::write("demos/locked.txt", "hello\n");
+    let mut perms = std::fs::metadata("demos/locked.txt").unwrap().permissions();
+    perms.set_readonly(true);
+    std::fs::set_permissions("demos/locked.txt", perms.clone());
+    std::fs::write("demos/unlocked.txt", "hello\n");
+    let mut doc1 = Document::open(size, "demos/locked.txt").unwrap();
+    let mut doc2 = Document::open(size, "demos/unlocked.txt").unwrap();
+    // Output
+    let writable = (doc1.writable, doc1.read_only, doc2.writable);
+    doc1.load_to(2);
+    doc1.exe(Event::Insert(Loc::at(0, 0), "!".to_string()));
+    // Superusers can write to the file anyway, so the fallback isn't needed
+    let denied = std::fs::OpenOptions::new().write(true).open("demos/locked.txt").is_err();
+    let mut called = false;
+    let result1 = doc1.save_or_else(|temp, _| {
+        called = std::fs::read_to_string(temp).unwrap() == "!hello\n";
+        Ok(())
+    });
+    let result2 = doc2.save_or_else(|_, _| Ok(()));
+    // Verification
+    assert_eq!(writable, (false, false, true));
+    assert!(!doc2.read_only);
+    assert!(result1.is_ok());
+    assert!(result2.is_ok());
+    assert_eq!(called, denied);
+    #[allow(clippy::permissions_set_readonly_false)]
+    perms.set_readonly(false);
+    std::fs::set_permissions("demos/locked.txt", perms);
+    std::fs::remove_file("demos/locked.txt");
+    std::fs::remove_file("demos/unlocked.txt");
+}
+
+#[test]
+#[allow(unused_must_use)]
+fn canonical_paths() {
+    // Test data
+    let size = Size { w: 10, h: 10 };
+    let doc1 = Document::open(size, "demos/../demos/1.txt").unwrap();
+    let doc2 = Document::new(size);
+    #[cfg(unix)]
+    std::os::unix::fs::symlink("1.txt", "demos/link.txt");
+    // Output & Verification
+    assert_eq!(doc1.file_name, Some("demos/../demos/1.txt".to_string()));
+    assert_eq!(doc1.canonical_path, std::fs::canonicalize("demos/1.txt").ok());
+    assert!(doc1.is_same_file("demos/1.txt"));
+    assert!(doc1.is_same_file("./demos/1.txt"));
+    assert!(!doc1.is_same_file("demos/2.txt"));
+    assert!(!doc1.is_same_file("demos/nonexist.txt"));
+    assert!(!doc2.is_same_file("demos/1.txt"));
+    #[cfg(unix)]
+    {
+        assert!(doc1.is_same_file("demos/link.txt"));
+        std::fs::remove_file("demos/link.txt");
+    }
+}
+
+#[test]
+#[allow(unused_must_use)]
+fn directory_listing() {
+    // Test data
+    let size = Size { w: 10, h: 10 };
+    std::fs::create_dir_all("demos/listing/sub");
+    std::fs::write("demos/listing/b.txt", "hello\n");
+    std::fs::write("demos/listing/a.rs", "fn main() {}\n");
+    std::fs::write("demos/listing/sub/c.txt", "world\n");
+    let mut doc = Document::open(size, "demos/listing").unwrap();
+    doc.load_to(10);
+    // Output
+    let lines = doc.lines.clone();
+    let sub = doc.entry_at(1).cloned();
+    let mut file = doc.enter(2).unwrap().unwrap();
+    file.load_to(10);
+    let entered = doc.enter(1).unwrap();
+    let sub_lines = doc.lines.clone();
+    // Verification
+    assert!(doc.is_listing());
+    assert!(doc.read_only);
+    assert!(Document::new(size).entry_at(0).is_none());
+    assert_eq!(lines, vec!["../", "sub/", "a.rs", "b.txt", ""]);
+    assert!(sub.as_ref().unwrap().is_dir);
+    assert_eq!(sub.unwrap().path, std::path::PathBuf::from("demos/listing/sub"));
+    assert_eq!(file.file_name, Some("demos/listing/a.rs".to_string()));
+    assert_eq!(file.line(0), Some("fn main() {}".to_string()));
+    assert!(entered.is_none());
+    assert_eq!(sub_lines, vec!["../", "c.txt", ""]);
+    assert_eq!(doc.entry_at(1).unwrap().len, 6);
+    assert_eq!(doc.entry_at(0).unwrap().path, std::path::PathBuf::from("demos/listing"));
+    assert!(doc.enter(5).is_err());
+    std::fs::remove_dir_all("demos/listing");
+}
+
+#[test]
+fn location_parsing() {
+    // Test data
+    let size = Size { w: 10, h: 10 };
+    // Output
+    let results = vec![
+        parse_location("src/main.rs:42:7"),
+        parse_location("src/main.rs:42"),
+        parse_location("src/main.rs:42:7: error"),
+        parse_location("src/main.rs:42:7:"),
+        parse_location("+42 src/main.rs"),
+        parse_location("src/main.rs +42"),
+        parse_location("src/main.rs"),
+        parse_location("C:\\main.rs:3"),
+    ];
+    let doc1 = Document::open_at(size, "demos/8.txt:100:3").unwrap();
+    let doc2 = Document::open_at(size, "+2 demos/3.txt").unwrap();
+    let doc3 = Document::open_at(size, "demos/3.txt:1000").unwrap();
+    // Verification
+    assert_eq!(results, vec![
+        ("src/main.rs", Some(Loc::at(6, 41))),
+        ("src/main.rs", Some(Loc::at(0, 41))),
+        ("src/main.rs:42:7: error", None),
+        ("src/main.rs", Some(Loc::at(6, 41))),
+        ("src/main.rs", Some(Loc::at(0, 41))),
+        ("src/main.rs", Some(Loc::at(0, 41))),
+        ("src/main.rs", None),
+        ("C:\\main.rs", Some(Loc::at(0, 2))),
+    ]);
+    assert_eq!(doc1.char_loc(), Loc::at(2, 99));
+    assert_eq!(doc1.offset.y, 94);
+    assert_eq!(doc1.cursor.y, 5);
+    assert_eq!(doc2.char_loc(), Loc::at(0, 1));
+    assert_eq!(doc2.offset.y, 0);
+    assert_eq!(doc3.char_loc().y, doc3.len_lines() - 1);
+}
+
+#[test]
+#[allow(unused_must_use)]
+fn workspace_search() {
+    // Test data
+    let size = Size { w: 10, h: 10 };
+    std::fs::create_dir_all("demos/search/.hidden");
+    std::fs::write("demos/search/a.txt", "needle\nhay needle\n");
+    std::fs::write("demos/search/b.txt", "hay\n");
+    std::fs::write("demos/search/.hidden/c.txt", "needle\n");
+    let mut ws = Workspace::new();
+    let a = ws.open(size, "demos/search/a.txt").unwrap();
+    let memory = ws.add(Document::from_reader(size, "a 好needle\n".as_bytes()).unwrap());
+    let again = ws.open(size, "demos/search/../search/a.txt").unwrap();
+    std::fs::write("demos/search/d.txt", "needle\n");
+    // Output
+    ws.documents[a].load_to(10);
+    ws.documents[a].exe(Event::Insert(Loc::at(0, 1), "needle ".to_string()));
+    let open = ws.search("needle").unwrap();
+    let invalid = ws.search("needle(");
+    let mut streamed = vec![];
+    let complete = ws.search_files("needle", "demos/search", &AtomicBool::new(false), |entry| {
+        streamed.push((entry.file.unwrap_or_default(), entry.loc));
+    });
+    let mut cancelled = vec![];
+    let incomplete = ws.search_files("needle", "demos/search", &AtomicBool::new(true), |entry| {
+        cancelled.push(entry);
+    });
+    // Verification
+    assert_eq!(a, again);
+    assert_eq!(ws.documents.len(), 2);
+    assert_eq!(ws.active, a);
+    assert_eq!(open.len(), 4);
+    assert_eq!(open[2].loc, Loc::at(11, 1));
+    assert_eq!(open[2].text, "needle hay needle".to_string());
+    assert_eq!(open[3], QuickfixEntry {
+        file: None,
+        document: Some(memory),
+        loc: Loc::at(3, 0),
+        text: "a 好needle".to_string(),
+    });
+    assert!(matches!(invalid, Err(Error::InvalidRegex(_))));
+    assert!(complete.unwrap());
+    assert_eq!(streamed.len(), 5);
+    assert_eq!(streamed[4], ("demos/search/d.txt".to_string(), Loc::at(0, 0)));
+    assert!(!incomplete.unwrap());
+    assert!(cancelled.is_empty());
+    assert_eq!(ws.close(a, ClosePolicy::Force).unwrap().file_name, Some("demos/search/a.txt".to_string()));
+    assert_eq!(ws.active, 0);
+    std::fs::remove_dir_all("demos/search");
+}
+
+#[test]
+#[allow(unused_must_use)]
+fn workspace_replace() {
+    // Test data
+    let size = Size { w: 10, h: 10 };
+    std::fs::create_dir_all("demos/replace");
+    std::fs::write("demos/replace/a.txt", "foo(1) foo(2)\nfoo(3)\n");
+    std::fs::write("demos/replace/b.txt", "foo(4)\n");
+    let mut ws = Workspace::new();
+    ws.open(size, "demos/replace/a.txt").unwrap();
+    ws.add(Document::from_reader(size, "foo(5)\n".as_bytes()).unwrap());
+    ws.active = 0;
+    // Output
+    let mut plan = ws.plan_replace(r"foo\((\d)\)", "bar[$1]", Some(Path::new("demos/replace")))
+        .unwrap();
+    let proposed: Vec<(Loc, String, String)> = plan.edits
+        .iter()
+        .map(|e| (e.loc, e.old.clone(), e.new.clone()))
+        .collect();
+    plan.reject(1);
+    let invalid = ws.plan_replace("foo(", "bar", None);
+    let applied = ws.apply(size, &plan).unwrap();
+    ws.documents[0].undo();
+    let undone = ws.documents[0].line(0);
+    let undone_below = ws.documents[0].line(1);
+    ws.documents[0].redo();
+    // Verification
+    assert_eq!(proposed[0], (Loc::at(0, 0), "foo(1)".to_string(), "bar[1]".to_string()));
+    assert_eq!(proposed[1], (Loc::at(7, 0), "foo(2)".to_string(), "bar[2]".to_string()));
+    assert_eq!(plan.edits.len(), 5);
+    assert_eq!(plan.edits[4].document, None);
+    assert!(matches!(invalid, Err(Error::InvalidRegex(_))));
+    assert_eq!(applied, 4);
+    assert_eq!(ws.active, 0);
+    assert_eq!(ws.documents.len(), 3);
+    assert_eq!(ws.documents[0].line(0), Some("bar[1] foo(2)".to_string()));
+    assert_eq!(ws.documents[0].line(1), Some("bar[3]".to_string()));
+    assert_eq!(ws.documents[1].line(0), Some("bar[5]".to_string()));
+    assert_eq!(ws.documents[2].line(0), Some("bar[4]".to_string()));
+    assert_eq!(undone, Some("foo(1) foo(2)".to_string()));
+    assert_eq!(undone_below, Some("foo(3)".to_string()));
+    assert_eq!(std::fs::read_to_string("demos/replace/b.txt").unwrap(), "foo(4)\n".to_string());
+    std::fs::remove_dir_all("demos/replace");
+}
+
+#[test]
+#[allow(unused_must_use)]
+fn symbol_tags() {
+    // Test data
+    let size = Size { w: 10, h: 10 };
+    std::fs::create_dir_all("demos/tags");
+    std::fs::write("demos/tags/lib.c", "int x;\nint parse(char *s) {\n}\n");
+    std::fs::write("demos/tags/tags", concat!(
+        "!_TAG_FILE_FORMAT\t2\t/extended format/\n",
+        "parse\tlib.c\t/^int parse(char *s) {$/;\"\tf\n",
+        "x\tlib.c\t1;\"\tkind:v\n",
+        "gone\tlib.c\t/^void gone() {$/;\"\tf\n",
+        "invalid line\n",
+    ));
+    let ctags = Tags::load("demos/tags/tags").unwrap();
+    let mut index = Tags::new();
+    let mut doc = Document::from_reader(size, "pub fn parse() {}\nstruct Row;\n".as_bytes()).unwrap();
+    doc.file_name = Some("src/main.rs".to_string());
+    // Output
+    index.index("script.py", "class Parser:\n    def parse(self):\n        pass\n");
+    index.index_document(&doc);
+    doc.load_to(10);
+    doc.exe(Event::InsertLine(0, "".to_string()));
+    index.index_document(&doc);
+    // Verification
+    assert_eq!(ctags.tags["parse"][0].kind, Some("f".to_string()));
+    assert_eq!(ctags.tags["x"][0].kind, Some("v".to_string()));
+    assert_eq!(ctags.goto_definition("parse"), vec![("demos/tags/lib.c".to_string(), Loc::at(4, 1))]);
+    assert_eq!(ctags.goto_definition("x"), vec![("demos/tags/lib.c".to_string(), Loc::at(0, 0))]);
+    assert!(ctags.goto_definition("gone").is_empty());
+    assert!(ctags.goto_definition("missing").is_empty());
+    assert_eq!(index.goto_definition("parse"), vec![
+        ("script.py".to_string(), Loc::at(8, 1)),
+        ("src/main.rs".to_string(), Loc::at(7, 1)),
+    ]);
+    assert_eq!(index.goto_definition("Row"), vec![("src/main.rs".to_string(), Loc::at(7, 2))]);
+    assert_eq!(index.tags["Parser"][0].kind, Some("class".to_string()));
+    std::fs::remove_dir_all("demos/tags");
+}
+
+#[test]
+#[allow(unused_must_use)]
+fn word_completion() {
+    // Test data
+    let size = Size { w: 10, h: 10 };
+    let mut doc = Document::from_reader(size, "parse parent\nparse par\n".as_bytes()).unwrap();
+    doc.load_to(10);
+    // Output
+    let first = doc.complete("par");
+    doc.exe(Event::Insert(Loc::at(0, 1), "partial ".to_string()));
+    doc.exe(Event::DeleteLine(0, "parse parent".to_string()));
+    let second = doc.complete("par");
+    doc.event_mgmt.commit();
+    doc.exe(Event::SplitDown(Loc::at(7, 0)));
+    doc.exe(Event::Insert(Loc::at(0, 1), "x".to_string()));
+    let third = doc.complete("par");
+    doc.undo();
+    let fourth = doc.complete("x");
+    let failed = doc.exe(Event::Insert(Loc::at(100, 0), "party".to_string()));
+    let fifth = doc.complete("par");
+    // Verification
+    assert_eq!(first, vec!["parse".to_string(), "parent".to_string()]);
+    assert_eq!(second, vec!["parse".to_string(), "partial".to_string()]);
+    assert_eq!(third, vec!["parse".to_string(), "partial".to_string()]);
+    assert!(fourth.is_empty());
+    assert!(failed.is_err());
+    assert_eq!(fifth, vec!["parse".to_string(), "partial".to_string()]);
+    assert_eq!(doc.words, Some(WordIndex::new(&doc.file.to_string())));
+    assert_eq!(WordIndex::new("a_b c1 c1 a_b a_b").complete(""), vec![
+        "a_b".to_string(),
+        "c1".to_string(),
+    ]);
+}
+
+#[test]
+#[allow(unused_must_use)]
+fn completion_insertion() {
+    // Test data
+    let size = Size { w: 20, h: 10 };
+    let mut doc = Document::from_reader(size, "let x = par;\n".as_bytes()).unwrap();
+    doc.load_to(10);
+    // Output
+    doc.set_overwrite(true);
+    doc.insert_completion(Loc::at(11, 0), 3, "parse()");
+    let completed = doc.line(0);
+    let cursor = doc.char_loc();
+    doc.undo();
+    let undone = doc.line(0);
+    let failed = doc.insert_completion(Loc::at(2, 0), 3, "parse");
+    // Verification
+    assert_eq!(completed, Some("let x = parse();".to_string()));
+    assert_eq!(cursor, Loc::at(15, 0));
+    assert_eq!(undone, Some("let x = par;".to_string()));
+    assert!(failed.is_err());
+    assert_eq!(doc.line(0), Some("let x = par;".to_string()));
+}
+
+#[test]
+#[allow(unused_must_use)]
+fn event_replay() {
+    // Test data
+    let size = Size { w: 10, h: 10 };
+    let mut doc = Document::from_reader(size, "hello\nworld\n".as_bytes()).unwrap();
+    doc.load_to(10);
+    let events = vec![
+        Event::Insert(Loc::at(5, 0), "!".to_string()),
+        Event::SplitDown(Loc::at(2, 1)),
+        Event::InsertLine(0, "top".to_string()),
+    ];
+    let invalid = vec![
+        Event::Insert(Loc::at(0, 0), "a".to_string()),
+        Event::InsertLine(1, "b".to_string()),
+        Event::Delete(Loc::at(50, 0), "c".to_string()),
+    ];
+    // Output
+    doc.replay_events(&events, Direction::Forward).unwrap();
+    let forward = doc.lines.clone();
+    doc.replay_events(&events, Direction::Backward).unwrap();
+    let backward = doc.lines.clone();
+    let failed = doc.replay_events(&invalid, Direction::Forward);
+    // Verification
+    assert_eq!(forward, vec!["top", "hello!", "wo", "rld", ""]);
+    assert_eq!(backward, vec!["hello", "world", ""]);
+    assert!(failed.is_err());
+    assert_eq!(doc.lines, vec!["hello", "world", ""]);
+    assert!(doc.event_mgmt.is_patch_empty());
+    let mut indented = Document::from_str(size, "        a\n");
+    indented.set_tab_width(4);
+    indented.load_to(2);
+    indented.replay_events(&[Event::Delete(Loc::at(3, 0), " ".to_string())], Direction::Forward);
+    assert_eq!(indented.line(0), Some("       a".to_string()));
+    assert!(indented.event_mgmt.is_patch_empty());
+}
+
+#[test]
+fn commit_suggestions() {
+    // Test data
+    let size = Size { w: 10, h: 10 };
+    let mut doc = Document::from_reader(size, "hello\n".as_bytes()).unwrap();
+    doc.load_to(10);
+    // Output
+    let results = vec![
+        doc.exe(Event::Insert(Loc::at(5, 0), "a".to_string())).unwrap(),
+        doc.exe(Event::Insert(Loc::at(6, 0), " ".to_string())).unwrap(),
+        doc.exe(Event::Insert(Loc::at(7, 0), "b\t".to_string())).unwrap(),
+        doc.exe(Event::Delete(Loc::at(7, 0), "b".to_string())).unwrap(),
+        doc.exe(Event::SplitDown(Loc::at(2, 0))).unwrap(),
+        doc.exe(Event::InsertLine(0, "x".to_string())).unwrap(),
+    ];
+    // Verification
+    assert_eq!(results, vec![
+        Status::None,
+        Status::WordBoundary,
+        Status::WordBoundary,
+        Status::None,
+        Status::StructuralChange,
+        Status::StructuralChange,
+    ]);
+    assert_eq!(
+        results.iter().map(Status::suggests_commit).collect::<Vec<bool>>(),
+        vec![false, true, true, false, true, true],
+    );
+}
+
+#[test]
+#[allow(unused_must_use)]
+fn grouping_policies() {
+    // Test data
+    let size = Size { w: 10, h: 10 };
+    let mut doc = Document::from_reader(size, "\n".as_bytes()).unwrap();
+    doc.load_to(10);
+    let type_text = |doc: &mut Document| {
+        for (x, ch) in "ab c".chars().enumerate() {
+            doc.exe(Event::Insert(Loc::at(x, 0), ch.to_string()));
+        }
+        doc.exe(Event::SplitDown(Loc::at(4, 0)));
+        doc.exe(Event::Insert(Loc::at(0, 1), "d".to_string()));
+    };
+    let mut mgmt = EventMgmt::default();
+    let start = std::time::Instant::now();
+    let second = std::time::Duration::from_secs(1);
+    // Output
+    type_text(&mut doc);
+    let manual = (doc.event_mgmt.undo_stack().len(), doc.event_mgmt.patch.len());
+    let mut results = vec![];
+    for policy in [GroupingPolicy::Word, GroupingPolicy::Line, GroupingPolicy::Count(2)] {
+        let mut doc = Document::from_reader(size, "\n".as_bytes()).unwrap();
+        doc.load_to(10);
+        doc.set_grouping(policy);
+        type_text(&mut doc);
+        results.push((doc.event_mgmt.undo_stack().len(), doc.event_mgmt.patch.len()));
+    }
+    mgmt.policy = GroupingPolicy::Time(second);
+    mgmt.group_before(start);
+    mgmt.register(Event::InsertLine(0, "a".to_string()));
+    mgmt.group_before(start + second / 2);
+    mgmt.register(Event::InsertLine(1, "b".to_string()));
+    mgmt.group_before(start + second * 2);
+    mgmt.register(Event::InsertLine(2, "c".to_string()));
+    // Verification
+    assert_eq!(manual, (0, 6));
+    assert_eq!(results, vec![(2, 1), (1, 1), (3, 0)]);
+    assert_eq!(doc.event_mgmt.policy, GroupingPolicy::Manual);
+    assert_eq!(mgmt.undo_stack().len(), 1);
+    assert_eq!(mgmt.undo_stack()[0].events.len(), 2);
+    assert_eq!(mgmt.patch.len(), 1);
+}
+
+#[test]
+#[allow(unused_must_use)]
+fn counted_operations() {
+    // Test data
+    let size = Size { w: 10, h: 10 };
+    let text = "one\ntwo\nthree\nfour\nfive\n";
+    let mut doc = Document::from_reader(size, text.as_bytes()).unwrap();
+    doc.load_to(10);
+    doc.set_grouping(GroupingPolicy::Word);
+    let cmds = Commands::default();
+    // Output
+    let deleted = doc.exe_n(2, |doc| {
+        let y = doc.loc().y;
+        Event::DeleteLine(y, doc.line(y).unwrap_or_default())
+    });
+    let after_delete = doc.lines.clone();
+    let patches = doc.event_mgmt.undo_stack().len();
+    doc.undo();
+    let undone = doc.lines.clone();
+    let moved = cmds.run_line(&mut doc, "10 move_down");
+    let cursor = doc.loc().y;
+    let failed = doc.repeat(3, |doc| {
+        doc.exe(Event::InsertLine(0, "new".to_string()))?;
+        doc.exe(Event::Delete(Loc::at(20, 0), "x".to_string()))
+    });
+    // Verification
+    assert_eq!(deleted.unwrap(), Status::None);
+    assert_eq!(after_delete, vec!["three", "four", "five", ""]);
+    assert_eq!(patches, 1);
+    assert_eq!(undone, vec!["one", "two", "three", "four", "five", ""]);
+    assert_eq!(moved.unwrap(), Status::EndOfFile);
+    assert_eq!(cursor, 5);
+    assert!(failed.is_err());
+    assert_eq!(doc.lines, undone);
+    assert_eq!(doc.event_mgmt.policy, GroupingPolicy::Word);
+    assert!(matches!(cmds.run_line(&mut doc, "3"), Err(Error::InvalidArguments)));
+}
+
+#[test]
+fn open_progress() {
+    // Test data
+    let size = Size { w: 10, h: 10 };
+    let mut reports = vec![];
+    // Output
+    let doc = Document::open_with_progress(size, "demos/8.txt", |read, total| {
+        reports.push((read, total));
+    })
+    .unwrap();
+    let dir = Document::open_with_progress(size, "demos", |_, _| panic!()).unwrap();
+    // Verification
+    assert!(reports.len() > 1);
+    assert!(reports.windows(2).all(|w| w[0].0 <= w[1].0));
+    assert_eq!(reports.last(), Some(&(396_297, 396_297)));
+    assert_eq!(doc.file_name, Some("demos/8.txt".to_string()));
+    assert!(dir.is_listing());
+}
+
+#[test]
+fn cancellation() {
+    // Test data
+    let size = Size { w: 10, h: 10 };
+    let mut doc = Document::open(size, "demos/5.txt").unwrap();
+    let go = AtomicBool::new(false);
+    let stop = AtomicBool::new(true);
+    // Output
+    let all = doc.search_all("a", &go).unwrap().unwrap();
+    let cancelled = doc.search_all("a", &stop).unwrap();
+    let invalid = doc.search_all("a(", &go);
+    let finished = doc.replace_all_cancellable("a", "b", &stop).unwrap();
+    let invalid_replace = doc.replace_all_cancellable("a(", "b", &go);
+    let unchanged = doc.file.to_string();
+    let finished2 = doc.replace_all_cancellable("a", "b", &go).unwrap();
+    let opened = Document::open_cancellable(size, "demos/5.txt", &go).unwrap();
+    let open_cancelled = Document::open_cancellable(size, "demos/5.txt", &stop).unwrap();
+    let missing = Document::open_cancellable(size, "demos/missing.txt", &go);
+    let mut ws = Workspace::new();
+    ws.add(Document::open(size, "demos/5.txt").unwrap());
+    let ws_all = ws.search_cancellable("a", &go).unwrap();
+    let ws_cancelled = ws.search_cancellable("a", &stop).unwrap();
+    let mut doubled = Document::from_str(size, "aba\nxa\n");
+    let doubled_finished = doubled.replace_all_cancellable("a", "aa", &go).unwrap();
+    let mut marked = Document::from_str(size, "ab\n");
+    marked.replace_all_cancellable("x*", "-", &go).unwrap();
+    // Verification
+    assert!(!all.is_empty());
+    assert!(all.windows(2).all(|w| (w[0].loc.y, w[0].loc.x) < (w[1].loc.y, w[1].loc.x)));
+    assert!(all.iter().all(|m| m.text == "a"));
+    assert_eq!(cancelled, None);
+    assert!(matches!(invalid, Err(Error::InvalidRegex(_))));
+    assert!(!finished);
+    assert!(matches!(invalid_replace, Err(Error::InvalidRegex(_))));
+    assert_eq!(unchanged, std::fs::read_to_string("demos/5.txt").unwrap());
+    assert!(finished2);
+    assert!(doc.search_all("a", &go).unwrap().unwrap().is_empty());
+    assert_eq!(opened.unwrap().file.to_string(), unchanged);
+    assert!(open_cancelled.is_none());
+    assert!(missing.is_err());
+    assert_eq!(ws_all.map(|r| r.len()), Some(all.len()));
+    assert_eq!(ws_cancelled, None);
+    assert!(doubled_finished);
+    assert_eq!(doubled.file.to_string(), "aabaa\nxaa\n");
+    assert_eq!(marked.line(0), Some("-a-b-".to_string()));
+}
+
+#[test]
+fn background_jobs() {
+    // Test data
+    let size = Size { w: 10, h: 10 };
+    let doc = Document::open(size, "demos/8.txt").unwrap();
+    let mut scheduler = Scheduler::new();
+    let words = scheduler.add(WordIndexJob::new(&doc));
+    let search = scheduler.add(SearchJob::new(&doc, "the").unwrap());
+    let cancelled = scheduler.add(SearchJob::new(&doc, "a").unwrap());
+    let invalid = SearchJob::new(&doc, "the(");
+    // Output
+    let first = scheduler.run_for(Duration::ZERO);
+    let early = scheduler.take::<WordIndexJob>(words);
+    let was_cancelled = scheduler.cancel(cancelled);
+    while !scheduler.run_for(Duration::from_millis(2)) {}
+    let wrong_type = scheduler.take::<WordIndexJob>(search);
+    let words = scheduler.take::<WordIndexJob>(words).unwrap();
+    let search = scheduler.take::<SearchJob>(search).unwrap();
+    // Verification
+    assert!(!first);
+    assert!(early.is_none());
+    assert!(was_cancelled);
+    assert!(!scheduler.cancel(cancelled));
+    assert!(wrong_type.is_none());
+    assert!(scheduler.is_idle());
+    assert!(matches!(invalid, Err(Error::InvalidRegex(_))));
+    assert_eq!(words.index, WordIndex::new(&doc.file.to_string()));
+    assert_eq!(words.line, doc.len_lines());
+    assert_eq!(search.line, doc.len_lines());
+    assert_eq!(Some(search.matches), doc.search_all("the", &AtomicBool::new(false)).unwrap());
+}
+
+#[test]
+fn text_checksums() {
+    // Test data
+    let size = Size { w: 10, h: 10 };
+    let mut doc1 = Document::open(size, "demos/3.txt").unwrap();
+    let doc2 = Document::open(size, "demos/3.txt").unwrap();
+    let doc3 = Document::open(size, "demos/4.txt").unwrap();
+    // Output
+    doc1.load_to(100);
+    doc1.move_down();
+    let loaded = (doc1 == doc2, doc1.same_text(&doc2), doc1.checksum() == doc2.checksum());
+    doc1.exe(Event::Insert(Loc::at(0, 0), "x".to_string())).unwrap();
+    let edited = (doc1.same_text(&doc2), doc1.checksum() == doc2.checksum());
+    doc1.undo().unwrap();
+    let undone = (doc1.same_text(&doc2), doc1.checksum() == doc2.checksum());
+    // Verification
+    assert_eq!(loaded, (false, true, true));
+    assert_eq!(edited, (false, false));
+    assert_eq!(undone, (true, true));
+    assert!(!doc2.same_text(&doc3));
+    assert_ne!(doc2.checksum(), doc3.checksum());
+    assert_eq!(Document::new(size).checksum(), Document::new(size).checksum());
+}
+
+#[test]
+fn cache_equality() {
+    // Test data
+    let size = Size { w: 10, h: 10 };
+    let mut doc1 = Document::open(size, "demos/5.txt").unwrap();
+    let mut doc2 = Document::open(size, "demos/5.txt").unwrap();
+    // Output
+    doc1.load_to(100);
+    let _ = doc1.complete("th");
+    let loaded = doc1 == doc2;
+    let snapshot = doc1.clone();
+    doc2.load_to(100);
+    doc2.move_down();
+    // Verification
+    assert!(loaded);
+    assert_eq!(snapshot, doc1);
+    assert_eq!(snapshot.lines, doc1.lines);
+    assert_ne!(doc1, doc2);
+}
+
+#[test]
+fn documents_from_text() {
+    // Test data
+    let size = Size { w: 10, h: 10 };
+    let text = std::fs::read_to_string("demos/3.txt").unwrap();
+    // Output
+    let mut doc1 = Document::from_str(size, &text);
+    let mut doc2 = Document::open(size, "demos/3.txt").unwrap();
+    let mut doc3 = Document::from_str(size, "no newline");
+    doc1.load_to(size.h);
+    doc2.load_to(size.h);
+    doc3.load_to(size.h);
+    // Verification
+    assert!(doc1.same_text(&doc2));
+    assert_eq!(doc1.file_name, None);
+    assert!(!doc1.modified);
+    assert!(doc1.event_mgmt.is_undo_empty());
+    assert_eq!(doc1.line(0), doc2.line(0));
+    assert_eq!(doc3.line(0), Some("no newline".to_string()));
+    assert_eq!(doc3.len_lines(), 1);
+    assert!(!doc3.insert_final_newline);
+}
+
+#[test]
+fn display_output() {
+    // Test data
+    let size = Size { w: 10, h: 2 };
+    let mut doc = Document::from_str(size, "hello\nworld\nagain\n");
+    doc.load_to(3);
+    // Output
+    doc.move_down();
+    doc.move_right();
+    doc.move_right();
+    doc.exe(Event::Insert(doc.char_loc(), "好".to_string())).unwrap();
+    let dump = doc.debug_dump();
+    // Verification
+    assert_eq!(Loc::at(3, 0).to_string(), "1:4");
+    assert_eq!(Size::is(80, 24).to_string(), "80x24");
+    assert_eq!(Event::Insert(Loc::at(0, 1), "a\"b".to_string()).to_string(), "insert \"a\\\"b\" at 2:1");
+    assert_eq!(Event::DeleteLine(4, "x".to_string()).to_string(), "delete line \"x\" at 5");
+    assert_eq!(Event::SpliceUp(Loc::at(2, 2)).to_string(), "splice up at 3:3");
+    assert_eq!(Status::NothingToUndo.to_string(), "nothing to undo");
+    assert_eq!(dump, "[no name] (3 lines, 10x2, cursor at 2:4, modified)\n     1 hello\n>    2 wo好|rld\n");
+}
+
+#[test]
+fn loc_ordering() {
+    // Test data
+    let a = Loc::at(5, 0);
+    let b = Loc::at(0, 1);
+    let c = Loc::at(3, 1);
+    let mut locs = vec![c, a, b];
+    // Output
+    locs.sort();
+    // Verification
+    assert_eq!(locs, vec![a, b, c]);
+    assert!(a < b && b < c);
+    assert_eq!(a.max(b), b);
+    assert_eq!(c.min(a), a);
+    assert!(b.is_within(&(a..c)));
+    assert!(!c.is_within(&(a..c)));
+    assert!(c.is_within(&(a..=c)));
+    assert!(a.is_within(&(..b)));
+    assert_eq!(c.saturating_add(Loc::at(1, 2)), Loc::at(4, 3));
+    assert_eq!(c.saturating_sub(Loc::at(5, 1)), Loc::at(0, 0));
+    assert_eq!(Loc::at(usize::MAX, 0).saturating_add(a), Loc::at(usize::MAX, 0));
+}
+
+#[test]
+fn spans() {
+    // Test data
+    let size = Size { w: 10, h: 10 };
+    let mut doc = Document::from_str(size, "hello\nworld\nagain\n");
+    doc.load_to(3);
+    let span1 = Span::new(Loc::at(2, 1), Loc::at(3, 0));
+    let span2: Span = (Loc::at(1, 1)..Loc::at(4, 1)).into();
+    let span3 = Span::new(Loc::at(4, 1), Loc::at(0, 2));
+    let len = |y| doc.line(y).map_or(0, |l| l.chars().count());
+    // Output
+    let mtch = doc.search_all("or", &AtomicBool::new(false)).unwrap().unwrap().remove(0);
+    // Verification
+    assert_eq!(span1.start, Loc::at(3, 0));
+    assert_eq!(span1.to_string(), "1:4-2:3");
+    assert!(span1.contains(Loc::at(4, 0)));
+    assert!(!span1.contains(Loc::at(2, 1)));
+    assert!(span1.overlaps(&span2));
+    assert!(!span2.overlaps(&span3));
+    assert!(Span::new(Loc::at(1, 1), Loc::at(1, 1)).is_empty());
+    assert_eq!(span1.split_lines(len), vec![
+        Span::new(Loc::at(3, 0), Loc::at(5, 0)),
+        Span::new(Loc::at(0, 1), Loc::at(2, 1)),
+    ]);
+    assert_eq!(span3.split_lines(len), vec![Span::new(Loc::at(4, 1), Loc::at(5, 1))]);
+    assert_eq!(span2.split_lines(len), vec![span2]);
+    assert_eq!(mtch.span(), Span::new(Loc::at(1, 1), Loc::at(3, 1)));
+}
+
+#[test]
+fn event_helpers() {
+    // Test data
+    let size = Size { w: 10, h: 10 };
+    let mut doc = Document::from_str(size, "hello\nworld\nagain\n");
+    doc.load_to(4);
+    // Output
+    let inserts = Event::insert_str_at(Loc::at(2, 0), "ab\r\n\ncd");
+    for event in inserts.clone() {
+        doc.exe(event).unwrap();
+    }
+    let inserted = doc.file.to_string();
+    doc.event_mgmt.commit();
+    doc.load_to(10);
+    let deletes = Event::delete_span(Span::new(Loc::at(1, 2), Loc::at(3, 4)), &doc).unwrap();
+    for event in deletes.clone() {
+        doc.exe(event).unwrap();
+    }
+    let deleted = doc.file.to_string();
+    doc.undo().unwrap();
+    // Verification
+    assert_eq!(inserts, vec![
+        Event::Insert(Loc::at(2, 0), "ab".to_string()),
+        Event::SplitDown(Loc::at(4, 0)),
+        Event::SplitDown(Loc::at(0, 1)),
+        Event::Insert(Loc::at(0, 2), "cd".to_string()),
+    ]);
+    assert_eq!(inserted, "heab\n\ncdllo\nworld\nagain\n");
+    assert_eq!(deletes.len(), 4);
+    assert_eq!(deleted, "heab\n\ncin\n");
+    assert_eq!(doc.file.to_string(), inserted);
+    assert!(Event::insert_str_at(Loc::at(0, 0), "").is_empty());
+    assert!(Event::delete_span(Span::new(Loc::at(0, 0), Loc::at(0, 9)), &doc).is_err());
+}
+
+#[test]
+fn event_validation() {
+    // Test data
+    let size = Size { w: 10, h: 10 };
+    let mut doc = Document::from_str(size, "hello\nworld\n");
+    // Output & Verification
+    assert!(doc.validate(&Event::Insert(Loc::at(5, 1), "!".to_string())).is_ok());
+    assert!(doc.validate(&Event::Insert(Loc::at(6, 1), "!".to_string())).is_err());
+    assert!(doc.validate(&Event::Insert(Loc::at(0, 2), "!".to_string())).is_err());
+    assert!(doc.validate(&Event::Delete(Loc::at(1, 0), "ell".to_string())).is_ok());
+    assert!(doc.validate(&Event::Delete(Loc::at(1, 0), "elo".to_string())).is_err());
+    assert!(doc.validate(&Event::Delete(Loc::at(3, 0), "lo!".to_string())).is_err());
+    assert!(doc.validate(&Event::InsertLine(2, "!".to_string())).is_ok());
+    assert!(doc.validate(&Event::InsertLine(3, "!".to_string())).is_err());
+    assert!(doc.validate(&Event::DeleteLine(1, "world".to_string())).is_ok());
+    assert!(doc.validate(&Event::DeleteLine(1, "hello".to_string())).is_err());
+    assert!(doc.validate(&Event::SplitDown(Loc::at(2, 0))).is_ok());
+    assert!(doc.validate(&Event::SpliceUp(Loc::at(5, 0))).is_ok());
+    assert!(doc.validate(&Event::SpliceUp(Loc::at(5, 1))).is_err());
+    assert!(doc.lines.is_empty());
+    doc.read_only = true;
+    assert!(matches!(
+        doc.validate(&Event::Insert(Loc::at(0, 0), "!".to_string())),
+        Err(Error::ReadOnlyFile),
+    ));
+}
+
+#[test]
+fn event_simulation() {
+    // Test data
+    let size = Size { w: 10, h: 10 };
+    let mut doc = Document::from_str(size, "hello\nworld\n");
+    doc.load_to(3);
+    let original = doc.clone();
+    // Output
+    let split = doc.simulate(&Event::SplitDown(Loc::at(2, 1))).unwrap();
+    let insert = doc.simulate(&Event::Insert(Loc::at(5, 0), " there".to_string())).unwrap();
+    let delete = doc.simulate(&Event::DeleteLine(0, "hello".to_string())).unwrap();
+    doc.overwrite = true;
+    let overwrite = doc.simulate(&Event::Insert(Loc::at(0, 1), "W".to_string())).unwrap();
+    doc.overwrite = false;
+    let invalid = doc.simulate(&Event::Delete(Loc::at(0, 0), "help".to_string()));
+    // Verification
+    assert_eq!(split.before, 1..2);
+    assert_eq!(split.after, 1..3);
+    assert_eq!(split.lines, vec!["wo".to_string(), "rld".to_string()]);
+    assert_eq!(split.cursor, Loc::at(0, 2));
+    assert_eq!(split.status, Status::StructuralChange);
+    assert_eq!(insert.lines, vec!["hello there".to_string()]);
+    assert_eq!(insert.cursor, Loc::at(11, 0));
+    assert_eq!((delete.before, delete.after, delete.lines.len()), (0..1, 0..0, 0));
+    assert_eq!(overwrite.lines, vec!["World".to_string()]);
+    assert!(invalid.is_err());
+    assert_eq!(doc, original);
+    assert!(doc.event_mgmt.is_undo_empty());
+}
+
+#[test]
+fn selection_undo() {
+    // Test data
+    let size = Size { w: 10, h: 10 };
+    let mut doc = Document::from_str(size, "hello world\n");
+    doc.load_to(2);
+    let word = Span::new(Loc::at(6, 0), Loc::at(11, 0));
+    // Output
+    doc.selection = Some(word);
+    doc.remove_range(word.start, word.end).unwrap();
+    doc.selection = None;
+    doc.exe(Event::Insert(Loc::at(6, 0), "there".to_string())).unwrap();
+    doc.event_mgmt.commit();
+    doc.exe(Event::Insert(Loc::at(11, 0), "!".to_string())).unwrap();
+    doc.undo().unwrap();
+    let first = doc.selection;
+    doc.undo().unwrap();
+    let retyped = doc.selection;
+    doc.undo().unwrap();
+    let second = doc.selection;
+    doc.redo().unwrap();
+    let redone = doc.selection;
+    doc.undo().unwrap();
+    // Verification
+    assert_eq!(first, None);
+    assert_eq!(retyped, None);
+    assert_eq!(second, Some(word));
+    assert_eq!(redone, None);
+    assert_eq!(doc.selection, Some(word));
+    assert_eq!(doc.line(0), Some("hello world".to_string()));
+}
+
+#[test]
+fn indentation_blocks() {
+    // Test data
+    let size = Size { w: 10, h: 10 };
+    let text = "def a():\n    x = 1\n\n    if x:\n\t\ty()\n\n    return x\n\ndef b():\n  pass\n";
+    let mut doc = Document::from_str(size, text);
+    doc.load_to(20);
+    // Output & Verification
+    assert_eq!(doc.indent_block_at(0), 0..10);
+    assert_eq!(doc.indent_block_at(1), 1..7);
+    assert_eq!(doc.indent_block_at(3), 1..7);
+    assert_eq!(doc.indent_block_at(4), 4..5);
+    assert_eq!(doc.indent_block_at(2), 2..3);
+    assert_eq!(doc.indent_block_at(9), 9..10);
+}
+
+#[test]
+fn structural_navigation() {
+    // Test data
+    let size = Size { w: 10, h: 10 };
+    let text = "def a():\n    x = 1\n\n    if x:\n        y()\n    return x\n\ndef b():\n  pass\n";
+    let mut doc = Document::from_str(size, text);
+    doc.load_to(20);
+    // Output & Verification
+    assert_eq!(doc.next_blank_line(0), Some(2));
+    assert_eq!(doc.next_blank_line(2), Some(6));
+    assert_eq!(doc.next_blank_line(6), None);
+    assert_eq!(doc.prev_blank_line(6), Some(2));
+    assert_eq!(doc.prev_blank_line(2), None);
+    assert_eq!(doc.matching_indent_line(1, Direction::Forward), Some(3));
+    assert_eq!(doc.matching_indent_line(3, Direction::Forward), Some(5));
+    assert_eq!(doc.matching_indent_line(5, Direction::Forward), None);
+    assert_eq!(doc.matching_indent_line(5, Direction::Backward), Some(3));
+    assert_eq!(doc.matching_indent_line(1, Direction::Backward), None);
+    assert_eq!(doc.matching_indent_line(0, Direction::Forward), Some(7));
+    assert_eq!(doc.matching_indent_line(4, Direction::Forward), None);
+    assert_eq!(doc.matching_indent_line(2, Direction::Forward), None);
+}
+
+#[test]
+fn document_statistics() {
+    // Test data
+    let size = Size { w: 10, h: 10 };
+    let doc1 = Document::open(size, "demos/6dos.txt").unwrap();
+    let doc2 = Document::from_str(size, "a:\n  b:\n    c\n  d\n\ne:\n  f\n");
+    let doc3 = Document::from_str(size, "a {\n\tb {\n\t\tc\n\t}\n}\n");
+    let mut doc4 = Document::from_str(size, "no indentation");
+    doc4.set_tab_width(8);
+    // Output & Verification
+    assert_eq!(doc1.line_ending_name(), "CRLF");
+    assert_eq!(doc2.line_ending_name(), "LF");
+    assert_eq!(doc4.line_ending_name(), "LF");
+    assert_eq!(doc1.encoding_name(), "UTF-8");
+    assert_eq!(doc2.indentation_summary(), "Spaces: 2");
+    assert_eq!(doc3.indentation_summary(), "Tabs: 4");
+    assert_eq!(doc4.indentation_summary(), "Spaces: 8");
+}
+
+#[test]
+#[allow(unused_must_use)]
+fn reloading() {
+    // Test data
+    let size = Size { w: 10, h: 10 };
+    std::fs::write("demos/reload.txt", "one\ntwo\nthree\n");
+    let mut doc = Document::open(size, "demos/reload.txt").unwrap();
+    doc.load_to(size.h);
+    doc.exe(Event::Insert(Loc::at(3, 0), "!".to_string()));
+    doc.event_mgmt.commit();
+    doc.goto(&Loc::at(1, 2));
+    // Output
+    std::fs::write("demos/reload.txt", "zero\none\ntwo\nthree");
+    doc.reload_preserving_state().unwrap();
+    let reloaded = (doc.file.to_string(), doc.modified, doc.char_loc());
+    doc.undo();
+    let undone = doc.file.to_string();
+    doc.undo();
+    // Verification
+    assert_eq!(reloaded, ("zero\none\ntwo\nthree\n".to_string(), false, Loc::at(1, 3)));
+    assert!(!doc.insert_final_newline);
+    assert_eq!(undone, "one!\ntwo\nthree\n");
+    assert_eq!(doc.file.to_string(), "one\ntwo\nthree\n");
+    assert!(matches!(Document::new(size).reload_preserving_state(), Err(Error::NoFileName)));
+    std::fs::write("demos/reload.txt", "");
+    doc.reload_preserving_state().unwrap();
+    assert!(doc.insert_final_newline);
+    assert_eq!(doc.file.to_string(), "\n");
+    std::fs::remove_file("demos/reload.txt");
+}
+
+#[test]
+#[allow(unused_must_use)]
+fn partial_saving() {
+    // Test data
+    let size = Size { w: 10, h: 10 };
+    let doc1 = Document::open(size, "demos/6dos.txt").unwrap();
+    let mut doc2 = Document::from_str(size, "one\ntwo\nthree");
+    doc2.load_to(size.h);
+    doc2.exe(Event::Insert(Loc::at(0, 0), "!".to_string()));
+    // Output
+    doc1.save_range(1..3, "demos/range1.txt").unwrap();
+    doc2.save_range(1..3, "demos/range2.txt").unwrap();
+    let result = doc2.save_range(2..4, "demos/range3.txt");
+    // Verification
+    let original = std::fs::read_to_string("demos/6dos.txt").unwrap();
+    let expected: String = original.split_inclusive('\n').skip(1).take(2).collect();
+    assert_eq!(std::fs::read_to_string("demos/range1.txt").unwrap(), expected);
+    assert_eq!(std::fs::read_to_string("demos/range2.txt").unwrap(), "two\nthree\n");
+    assert!(matches!(result, Err(Error::OutOfRange)));
+    assert!(!Path::new("demos/range3.txt").exists());
+    assert!(doc2.modified);
+    assert_eq!(doc2.file_name, None);
+    std::fs::remove_file("demos/range1.txt");
+    std::fs::remove_file("demos/range2.txt");
+}
+
+#[test]
+#[allow(unused_must_use)]
+fn filtering_and_appending() {
+    // Test data
+    let size = Size { w: 10, h: 10 };
+    let mut doc = Document::from_str(size, "b\nc\na\nz\ny\n");
+    doc.load_to(size.h);
+    std::fs::write("demos/append.txt", "start\n");
+    // Output
+    doc.filter_lines(0..3, |text| {
+        let mut lines: Vec<&str> = text.lines().collect();
+        lines.sort_unstable();
+        lines.join("\n") + "\n"
+    }).unwrap();
+    let filtered = doc.file.to_string();
+    doc.undo();
+    let result = doc.filter_lines(3..6, |text| text.to_string());
+    doc.save_append("demos/append.txt").unwrap();
+    doc.save_append("demos/append.txt").unwrap();
+    // Verification
+    assert_eq!(filtered, "a\nb\nc\nz\ny\n");
+    assert_eq!(doc.file.to_string(), "b\nc\na\nz\ny\n");
+    assert!(matches!(result, Err(Error::OutOfRange)));
+    assert_eq!(
+        std::fs::read_to_string("demos/append.txt").unwrap(),
+        "start\nb\nc\na\nz\ny\nb\nc\na\nz\ny\n",
+    );
+    std::fs::remove_file("demos/append.txt");
+}
+
+#[test]
+fn file_templates() {
+    // Test data
+    let size = Size { w: 10, h: 10 };
+    let mut templates = Templates::new();
+    templates.add("rs", "// {file}\nfn main() {\n    {cursor}\n}\n");
+    templates.add("py", "# {name}\n");
+    let mut doc1 = Document::new(size);
+    doc1.file_name = Some("src/main.rs".to_string());
+    let mut doc2 = Document::new(size);
+    doc2.file_name = Some("notes.txt".to_string());
+    let mut doc3 = Document::open(size, "demos/3.txt").unwrap();
+    doc3.file_name = Some("demos/3.py".to_string());
+    let mut doc4 = Document::new(size);
+    doc4.file_name = Some("tool.py".to_string());
+    // Output
+    let applied = templates.apply(&mut doc1).unwrap();
+    let text = doc1.file.to_string();
+    let cursor = doc1.char_loc();
+    let modified = doc1.modified;
+    doc1.undo().unwrap();
+    // Verification
+    assert!(applied);
+    assert_eq!(text, "// main.rs\nfn main() {\n    \n}\n");
+    assert_eq!(cursor, Loc::at(4, 2));
+    assert!(modified);
+    assert_eq!(doc1.file.to_string(), "\n");
+    assert!(!templates.apply(&mut doc2).unwrap());
+    assert!(!templates.apply(&mut doc3).unwrap());
+    assert!(templates.apply(&mut doc4).unwrap());
+    assert_eq!(doc4.file.to_string(), "# tool\n");
+}
+
+#[test]
+fn render_requests() {
+    // Test data
+    let size = Size { w: 10, h: 2 };
+    let mut doc = Document::from_str(size, "a\nb\nc\nd\n");
+    doc.load_to(5);
+    // Output
+    let first = doc.take_full_render_request();
+    let again = doc.take_full_render_request();
+    doc.exe(Event::Insert(Loc::at(1, 1), "!".to_string())).unwrap();
+    doc.exe(Event::Insert(Loc::at(0, 0), "?".to_string())).unwrap();
+    let edited = (doc.take_full_render_request(), doc.take_line_render_requests());
+    let cleared = doc.take_line_render_requests();
+    doc.exe(Event::SplitDown(Loc::at(0, 0))).unwrap();
+    let split = (doc.take_full_render_request(), doc.take_line_render_requests());
+    doc.move_bottom();
+    let scrolled = doc.take_full_render_request();
+    doc.undo().unwrap();
+    let undone = doc.take_full_render_request();
+    // Verification
+    assert!(first);
+    assert!(!again);
+    assert_eq!(edited, (false, vec![0, 1]));
+    assert!(cleared.is_empty());
+    assert_eq!(split, (true, vec![]));
+    assert!(scrolled);
+    assert!(undone);
+}
+
+#[test]
+fn line_clamping() {
+    // Test data
+    let size = Size { w: 10, h: 10 };
+    let long = "ab".repeat(1500);
+    let mut doc = Document::from_str(size, &format!("short\n{long}\n好好好好\n"));
+    doc.load_to(4);
+    let default = doc.max_line_length;
+    doc.set_max_line_length(Some(2000));
+    // Output
+    let clamped = doc.clamped_lines();
+    let start = doc.line_trim(1, 0, 5);
+    let middle = doc.line_trim(1, 2500, 5);
+    let end = doc.line_trim(1, 2000, 5);
+    doc.set_max_line_length(Some(3));
+    let wide = (doc.is_clamped(2), doc.line_trim(2, 0, 10));
+    doc.set_max_line_length(None);
+    // Verification
+    assert_eq!(default, None);
+    assert_eq!(clamped, vec![1]);
+    assert!(!doc.is_clamped(1));
+    assert_eq!(start, Some("ababa".to_string()));
+    assert_eq!(middle, Some("".to_string()));
+    assert_eq!(end, Some("".to_string()));
+    assert_eq!(wide, (true, Some("好好好".to_string())));
+    assert_eq!(doc.line_trim(1, 2500, 5), Some("ababa".to_string()));
+}
+
+#[test]
+fn length_units() {
+    // Test data
+    let size = Size { w: 10, h: 10 };
+    let mut doc = Document::from_str(size, "cafe\u{301}\n\t好x\n👩\u{200d}💻\n");
+    doc.set_tab_width(4);
+    doc.load_to(4);
+    // Output
+    let chars: Vec<_> = (0..3).map(|y| doc.line_char_len(y)).collect();
+    let clusters: Vec<_> = (0..3).map(|y| doc.line_grapheme_len(y)).collect();
+    let widths: Vec<_> = (0..3).map(|y| doc.line_display_width(y)).collect();
+    // Verification
+    assert_eq!(chars, vec![Some(5), Some(3), Some(3)]);
+    assert_eq!(clusters, vec![Some(4), Some(3), Some(1)]);
+    assert_eq!(widths, vec![Some(4), Some(7), Some(2)]);
+    assert_eq!(doc.line_char_len(5), None);
+    assert_eq!(doc.char_len(), 11);
+    assert_eq!(doc.grapheme_len(), 8);
+    assert_eq!(doc.display_width(), 7);
+    assert_eq!(grapheme_len("\u{301}a\u{301}\u{301}"), 2);
+    assert_eq!(grapheme_len(""), 0);
+}
+
+#[test]
+fn render_windows() {
+    // Test data
+    let size = Size { w: 10, h: 10 };
+    let mut doc = Document::from_str(size, "好好好好\nab\n\tx\n");
+    doc.set_tab_width(4);
+    doc.load_to(4);
+    // Output
+    let cut_left = doc.render_window(0, 1, 4);
+    let cut_right = doc.render_window(0, 0, 5);
+    let cut_both = doc.render_window(0, 1, 5);
+    let short = doc.render_window(1, 0, 5);
+    let past_end = doc.render_window(1, 5, 3);
+    let tab = doc.render_window(2, 2, 4);
+    // Verification
+    assert_eq!(cut_left, Some(" 好 ".to_string()));
+    assert_eq!(cut_right, Some("好好 ".to_string()));
+    assert_eq!(cut_both, Some(" 好好".to_string()));
+    assert_eq!(short, Some("ab   ".to_string()));
+    assert_eq!(past_end, Some("   ".to_string()));
+    assert_eq!(tab, Some("  x ".to_string()));
+    assert_eq!(doc.render_window(5, 0, 3), None);
+}
+
+#[test]
+fn bottom_up_rendering() {
+    // Test data
+    let size = Size { w: 10, h: 10 };
+    let mut doc = Document::from_str(size, "one\ntwo\nthree\ne\u{301}👩\u{200d}💻!\n");
+    doc.load_to(5);
+    // Output
+    let tail = doc.render_from_bottom(Size::is(4, 2));
+    let padded = doc.render_from_bottom(Size::is(3, 6));
+    doc.offset.x = 2;
+    let scrolled = doc.render_from_bottom(Size::is(3, 1));
+    let backwards: Vec<&str> = doc.line_graphemes(3).unwrap().into_iter().rev().collect();
+    // Verification
+    assert_eq!(tail, vec!["thre", "e\u{301}👩\u{200d}💻!"]);
+    assert_eq!(padded, vec!["   ", "   ", "one", "two", "thr", "e\u{301}👩\u{200d}💻"]);
+    assert_eq!(scrolled, vec![" ! "]);
+    assert_eq!(backwards, vec!["!", "👩\u{200d}💻", "e\u{301}"]);
+    assert_eq!(doc.line_graphemes(10), None);
+    assert_eq!(graphemes("\u{301}a\u{301}"), vec!["\u{301}", "a\u{301}"]);
+    assert_eq!(graphemes("🇬🇧🇫🇷"), vec!["🇬🇧", "🇫🇷"]);
+    assert_eq!(graphemes("\u{1100}\u{1161}\u{11a8}a"), vec!["\u{1100}\u{1161}\u{11a8}", "a"]);
+    assert_eq!(graphemes("a\r\nb"), vec!["a", "\r\n", "b"]);
+}
+
+#[test]
+fn styled_spans() {
+    // Test data
+    let size = Size { w: 10, h: 10 };
+    let mut doc = Document::from_str(size, "hello world\nfoo\n");
+    doc.load_to(3);
+    doc.set_ruler(Some(8));
+    doc.selection = Some(Span::new(Loc::at(6, 0), Loc::at(2, 1)));
+    // Output
+    let first = doc.decorations(0);
+    let second = doc.decorations(1);
+    let found = doc.next_match("o w", 0).unwrap().styled_span();
+    let mut spans = first.clone();
+    spans.push(found.clone());
+    let flat = StyledSpan::flatten(&spans);
+    // Verification
+    assert_eq!(first, vec![
+        StyledSpan::new(8..11, StyledSpan::RULER),
+        StyledSpan::new(6..11, StyledSpan::SELECTION),
+    ]);
+    assert_eq!(second, vec![StyledSpan::new(0..2, StyledSpan::SELECTION)]);
+    assert_eq!(found, StyledSpan::new(4..7, StyledSpan::MATCH));
+    assert_eq!(flat, vec![
+        StyledSpan::new(4..7, StyledSpan::MATCH),
+        StyledSpan::new(7..11, StyledSpan::SELECTION),
+    ]);
+    assert!(doc.decorations(5).is_empty());
+}
+
+#[test]
+#[cfg(feature = "terminal")]
+fn terminal_adapter() {
+    use crossterm::cursor::MoveTo;
+    use kaolinite::terminal;
+    // Test data
+    let mut doc = Document::from_str(Size { w: 10, h: 10 }, &"line\n".repeat(100));
+    let options = LayoutOptions {
+        line_numbers: true,
+        gutter_padding: 2,
+        top_lines: 1,
+        ..LayoutOptions::default()
+    };
+    let layout = Layout::new(Size::is(20, 6), &doc, &options);
+    layout.apply(&mut doc);
+    doc.load_to(100);
+    doc.goto(&Loc::at(2, 10));
+    // Output
+    let cursor = terminal::cursor(&layout, &doc);
+    let gutter = terminal::gutter(&layout, 3);
+    let column = terminal::column(&layout, 3, 4);
+    let mut out = vec![];
+    let up = terminal::scroll(&mut out, doc.offset.y - 2, &doc).unwrap();
+    let mut still = vec![];
+    let same = terminal::scroll(&mut still, doc.offset.y, &doc).unwrap();
+    let far = terminal::scroll(&mut still, 0, &doc).unwrap();
+    // Verification
+    assert_eq!(layout.gutter, 5);
+    assert_eq!(cursor, MoveTo(7, 5));
+    assert_eq!(gutter, MoveTo(0, 4));
+    assert_eq!(column, MoveTo(9, 4));
+    assert_eq!(up, vec![3, 4]);
+    assert_eq!(String::from_utf8(out).unwrap(), "\x1b[2S");
+    assert!(same.is_empty());
+    assert_eq!(far, vec![0, 1, 2, 3, 4]);
+    assert!(still.is_empty());
+}
+
+#[test]
+fn headless_driver() {
+    // Test data
+    let mut doc = Document::new(Size { w: 10, h: 3 });
+    doc.set_grouping(GroupingPolicy::Word);
+    let mut driver = Driver::new(doc);
+    driver.keymap.bind("normal", &["ctrl+z".to_string()], "undo");
+    driver.keymap.bind("normal", &["g".to_string(), "g".to_string()], "goto 1");
+    let script = "
+        # Type two words, and take the second back
+        type hello world
+        key ctrl+z
+        type there\\nline two\\nthree\\nfour
+        key g
+        key g
+    ";
+    // Output
+    driver.play_script(script).unwrap();
+    let recording = driver.script();
+    let mut replay = Driver::new(Document::new(Size { w: 10, h: 3 }));
+    replay.doc.set_grouping(GroupingPolicy::Word);
+    replay.keymap = driver.keymap.clone();
+    replay.play_script(&recording).unwrap();
+    // Verification
+    assert_eq!(driver.text(), "hello there\nline two\nthree\nfour\n");
+    assert_eq!(driver.doc.loc(), Loc::at(0, 0));
+    assert_eq!(driver.doc.offset.y, 0);
+    assert_eq!(driver.recording.len(), 5);
+    assert_eq!(driver.recording[0], Step::Type("hello world".to_string()));
+    assert_eq!(replay.text(), driver.text());
+    assert!(driver.play_script("no_such_command").is_err());
+}
+
+#[test]
+fn viewport_snapshots() {
+    // Test data
+    let options = LayoutOptions {
+        line_numbers: true,
+        gutter_padding: 1,
+        bottom_lines: 1,
+        ..LayoutOptions::default()
+    };
+    let terminal = Size::is(12, 5);
+    let mut doc = Document::from_str(Size::is(10, 4), "\tone\n好好好好好\nthree\n");
+    doc.set_tab_width(4);
+    Layout::new(terminal, &doc, &options).apply(&mut doc);
+    doc.load_to(4);
+    // Output
+    let top = snapshot(&doc, terminal, &options, "{file}{modified} {line}/{total}");
+    let mut driver = Driver::new(doc);
+    driver.play_script("goto 3 2\ninsert x").unwrap();
+    driver.doc.offset.x = 1;
+    let scrolled = driver.snapshot(terminal, &options, "{type} {line}:{column}{modified}");
+    // Verification
+    assert_eq!(top, [
+        "1     one   ",
+        "2 好好好好好",
+        "3 three     ",
+        "~           ",
+        "[No Name] 1…",
+        "",
+    ].join("\n"));
+    assert_eq!(scrolled, [
+        "1    one    ",
+        "2  好好好好 ",
+        "3 xhree     ",
+        "~           ",
+        "Unknown 3:3…",
+        "",
+    ].join("\n"));
+}
+
+#[test]
+fn grapheme_boundaries() {
+    // Test data
+    let size = Size { w: 10, h: 10 };
+    let mut doc = Document::from_str(size, "cafe\u{301}s\n👩\u{200d}💻\n");
+    doc.load_to(3);
+    // Output
+    let boundaries: Vec<bool> = (0..7).map(|x| doc.is_boundary(&Loc::at(x, 0))).collect();
+    let inside = doc.validate(&Event::SplitDown(Loc::at(4, 0)));
+    let split_inside = doc.exe(Event::SplitDown(Loc::at(2, 1)));
+    let unchanged = doc.line(1);
+    let split_after = doc.exe(Event::SplitDown(Loc::at(5, 0)));
+    // Verification
+    assert_eq!(boundaries, vec![true, true, true, true, false, true, true]);
+    assert!(matches!(inside, Err(Error::NotBoundary)));
+    assert!(matches!(split_inside, Err(Error::NotBoundary)));
+    assert_eq!(unchanged, Some("👩\u{200d}💻".to_string()));
+    assert!(split_after.is_ok());
+    assert_eq!(doc.line(0), Some("cafe\u{301}".to_string()));
+    assert_eq!(doc.line(1), Some("s".to_string()));
+    assert!(!doc.is_boundary(&Loc::at(0, 5)));
+    assert!(is_boundary("\u{301}a", 1));
+    assert!(!is_boundary("a\u{301}", 1));
+    assert!(!is_boundary("🇬🇧", 1));
+}
+
+#[test]
+fn unicode_normalization() {
+    // Test data
+    let size = Size { w: 10, h: 10 };
+    let mut doc = Document::from_str(size, "cafe\u{301}\ncafé\n");
+    doc.load_to(3);
+    let mut plain = Document::from_str(size, "cafe\u{301}\n");
+    plain.load_to(2);
+    // Output
+    let before = doc.next_match("café", 0).map(|m| m.loc);
+    doc.set_normalize(true);
+    let after = doc.next_match("café", 0).map(|m| m.loc);
+    let committed = doc.event_mgmt.is_patch_empty() && doc.event_mgmt.undo_stack().len() == 1;
+    doc.exe(Event::Insert(Loc::at(0, 1), "e\u{301}".to_string())).unwrap();
+    plain.set_normalize(false);
+    let mut undone = doc.clone();
+    undone.undo().unwrap();
+    undone.undo().unwrap();
+    // Verification
+    assert_eq!(before, Some(Loc::at(0, 1)));
+    assert_eq!(after, Some(Loc::at(0, 0)));
+    assert!(doc.normalized);
+    assert!(committed);
+    assert_eq!(doc.line(0), Some("café".to_string()));
+    assert_eq!(doc.line(1), Some("écafé".to_string()));
+    assert_eq!(doc.char_loc(), Loc::at(1, 1));
+    assert_eq!(undone.line(0), Some("cafe\u{301}".to_string()));
+    assert_eq!(undone.line(1), Some("café".to_string()));
+    assert!(!plain.normalized);
+    assert_eq!(plain.line(0), Some("cafe\u{301}".to_string()));
+    assert_eq!(nfc("e\u{301}"), "é");
+}
+
+#[test]
+fn search_wrapping() {
+    // Test data
+    let size = Size { w: 10, h: 10 };
+    let mut doc = Document::from_str(size, "foo one\nbar\nfoo two\n");
+    doc.load_to(1);
+    // Output
+    let first = doc.find_wrapping("foo", Direction::Forward);
+    doc.goto(&Loc::at(0, 2));
+    let wrapped = doc.find_wrapping("foo", Direction::Forward);
+    doc.goto(&Loc::at(0, 0));
+    let backwards = doc.find_wrapping("foo", Direction::Backward);
+    let missing = doc.find_wrapping("baz", Direction::Forward);
+    // Verification
+    assert_eq!(first.0.map(|m| m.loc), Some(Loc::at(0, 2)));
+    assert_eq!(first.1, Status::None);
+    assert_eq!(wrapped.0.map(|m| m.loc), Some(Loc::at(0, 0)));
+    assert_eq!(wrapped.1, Status::WrappedAround);
+    assert_eq!(backwards.0.map(|m| m.loc), Some(Loc::at(0, 2)));
+    assert_eq!(backwards.1, Status::WrappedAround);
+    assert_eq!(missing, (None, Status::NoMatches));
+    assert_eq!(Status::WrappedAround.to_string(), "search wrapped around");
+}
+
+#[test]
+fn search_history() {
+    // Test data
+    let size = Size { w: 10, h: 10 };
+    let mut doc = Document::from_str(size, "a1 b1\na2 b2\na3 b3\n");
+    doc.load_to(4);
+    // Output
+    let nothing = doc.repeat_search(Direction::Forward);
+    doc.search("b", Direction::Forward);
+    let found = doc.search("a", Direction::Forward).0.unwrap();
+    doc.goto(&found.loc);
+    let next = doc.repeat_search(Direction::Forward).0.unwrap();
+    doc.goto(&next.loc);
+    let prev = doc.repeat_search(Direction::Backward).0.unwrap();
+    doc.search("b", Direction::Backward);
+    let reversed = doc.repeat_search(Direction::Forward).0.unwrap();
+    // Verification
+    assert_eq!(nothing, (None, Status::NoMatches));
+    assert_eq!(found.loc, Loc::at(0, 1));
+    assert_eq!(next.loc, Loc::at(0, 2));
+    assert_eq!(prev.loc, Loc::at(0, 1));
+    assert_eq!(reversed.loc, Loc::at(3, 1));
+    assert_eq!(doc.search_history, vec!["a".to_string(), "b".to_string()]);
+    assert_eq!(doc.search_history_at(0), Some("b"));
+    assert_eq!(doc.search_history_at(1), Some("a"));
+    assert_eq!(doc.search_history_at(2), None);
+}
+
+#[test]
+#[allow(unused_must_use)]
+fn confirmed_replacing() {
+    // Test data
+    let size = Size { w: 10, h: 10 };
+    let mut doc = Document::from_str(size, "let a = 1;\nlet b = 2;\nlet c = 3;\n");
+    doc.load_to(4);
+    // Output
+    let mut seen = vec![];
+    let into = "const ${name}: i32 = $2";
+    let count = doc.replace_all_with(r"let (?<name>\w) = (\d)", into, |m, new| {
+        seen.push((m.loc, new.to_string()));
+        m.loc.y != 1
+    });
+    let after = doc.file.to_string();
+    doc.undo();
+    let undone = doc.file.to_string();
+    doc.replace_all("(\\d);", "($1);");
+    let invalid = doc.replace_all_with("(\\d", "$1", |_, _| true);
+    let invalid_all = doc.replace_all("(\\d", "$1");
+    let cmds = Commands::default();
+    let invalid_cmd = cmds.run_line(&mut doc, "replace_all (\\d x");
+    // Verification
+    assert_eq!(count.unwrap(), ReplaceCount { replaced: 2, skipped: 1 });
+    assert_eq!(seen, vec![
+        (Loc::at(0, 0), "const a: i32 = 1".to_string()),
+        (Loc::at(0, 1), "const b: i32 = 2".to_string()),
+        (Loc::at(0, 2), "const c: i32 = 3".to_string()),
+    ]);
+    assert_eq!(after, "const a: i32 = 1;\nlet b = 2;\nconst c: i32 = 3;\n");
+    assert_eq!(undone, "let a = 1;\nlet b = 2;\nlet c = 3;\n");
+    assert_eq!(doc.line(2), Some("let c = (3);".to_string()));
+    assert!(matches!(invalid, Err(Error::InvalidRegex(_))));
+    assert!(matches!(invalid_all, Err(Error::InvalidRegex(_))));
+    assert!(matches!(invalid_cmd, Err(Error::InvalidRegex(_))));
+}
+
+#[test]
+fn multi_line_searching() {
+    // Test data
+    let size = Size { w: 10, h: 10 };
+    let text = "fn a() {\n}\n\nfn b() {\n    body\n}\n/* one\ntwo\nthree */\n";
+    let mut doc = Document::from_str(size, text);
+    // Output
+    let literal = doc.find_across_lines("\\{\n}", Loc::at(0, 0)).unwrap().unwrap();
+    let second = doc.find_across_lines(r"\{\n}", Loc::at(8, 0)).unwrap();
+    let dotall = doc.find_across_lines(r"(?s)/\*.*?\*/", Loc::at(0, 0)).unwrap().unwrap();
+    let spanning = doc.find_across_lines(r"(?s)b\(\).*}", Loc::at(0, 1)).unwrap().unwrap();
+    let missing = doc.find_across_lines(r"two\nfour", Loc::at(0, 0)).unwrap();
+    let after_end = doc.find_across_lines("x", Loc::at(0, 40)).unwrap();
+    let invalid = doc.find_across_lines(r"(\{\n}", Loc::at(0, 0));
+    // Verification
+    assert_eq!(literal, Match { loc: Loc::at(7, 0), text: "{\n}".to_string() });
+    assert_eq!(literal.span(), Span::new(Loc::at(7, 0), Loc::at(1, 1)));
+    assert_eq!(second, None);
+    assert_eq!(dotall.loc, Loc::at(0, 6));
+    assert_eq!(dotall.text, "/* one\ntwo\nthree */");
+    assert_eq!(dotall.span(), Span::new(Loc::at(0, 6), Loc::at(8, 8)));
+    assert_eq!(spanning.loc, Loc::at(3, 3));
+    assert_eq!(spanning.span().end, Loc::at(1, 5));
+    assert_eq!(missing, None);
+    assert_eq!(after_end, None);
+    assert!(matches!(invalid, Err(Error::InvalidRegex(_))));
+}
+
+#[test]
+#[allow(unused_must_use)]
+fn workspace_switching() {
+    // Test data
+    let size = Size { w: 10, h: 5 };
+    let mut ws = Workspace::new();
+    let a = ws.open(size, "demos/7short.txt").unwrap();
+    let b = ws.open(size, "demos/3.txt").unwrap();
+    let c = ws.add(Document::from_str(size, "scratch\n"));
+    // Output
+    let nothing = Workspace::new().switch_to_previous();
+    ws.switch(a);
+    ws.active_mut().unwrap().goto(&Loc::at(2, 100));
+    let offset = ws.active().unwrap().offset;
+    let to_c = ws.switch_to_previous();
+    let back_to_a = ws.switch_to_previous();
+    let kept = ws.active().unwrap().loc();
+    let order = ws.recent.clone();
+    ws.close(a, ClosePolicy::Force);
+    let after_close = (ws.active, ws.recent.clone());
+    let reopened = ws.open(size, "demos/7short.txt").unwrap();
+    let restored = ws.active().unwrap();
+    // Verification
+    assert_eq!(nothing, None);
+    assert_eq!(to_c, Some(c));
+    assert_eq!(back_to_a, Some(a));
+    assert_eq!(kept, Loc::at(2, 100));
+    assert_eq!(order, vec![a, c, b]);
+    assert_eq!(after_close, (c - 1, vec![c - 1, b - 1]));
+    assert_eq!(reopened, 2);
+    assert_eq!(ws.recent, vec![2, 1, 0]);
+    assert_eq!(restored.loc(), Loc::at(2, 100));
+    assert_eq!(restored.offset, offset);
+    assert!(!ws.switch(10));
+}
+
+#[test]
+#[allow(unused_must_use)]
+fn buffer_list() {
+    // Test data
+    let size = Size { w: 10, h: 10 };
+    let mut ws = Workspace::new();
+    ws.open(size, "demos/8.rs").unwrap();
+    ws.active_mut().unwrap().load_to(5);
+    ws.active_mut().unwrap().goto(&Loc::at(0, 2));
+    ws.add(Document::from_str(size, "scratch\n"));
+    ws.active_mut().unwrap().load_to(2);
+    ws.active_mut().unwrap().exe(Event::Insert(Loc::at(0, 0), "a ".to_string()));
+    // Output
+    let list = ws.list();
+    // Verification
+    assert_eq!(list, vec![
+        BufferEntry {
+            index: 0,
+            name: "8.rs".to_string(),
+            path: Some("demos/8.rs".to_string()),
+            modified: false,
+            line: 3,
+            file_type: Some("Rust".to_string()),
+            active: false,
+        },
+        BufferEntry {
+            index: 1,
+            name: "[No Name]".to_string(),
+            path: None,
+            modified: true,
+            line: 1,
+            file_type: None,
+            active: true,
+        },
+    ]);
+    assert!(Workspace::new().list().is_empty());
+}
+
+#[test]
+#[allow(unused_must_use)]
+fn close_policies() {
+    // Test data
+    let size = Size { w: 10, h: 10 };
+    std::fs::create_dir_all("demos/close");
+    std::fs::write("demos/close/a.txt", "a\n");
+    std::fs::write("demos/close/b.txt", "b\n");
+    let mut ws = Workspace::new();
+    let a = ws.open(size, "demos/close/a.txt").unwrap();
+    ws.open(size, "demos/close/b.txt").unwrap();
+    ws.add(Document::from_str(size, "scratch\n"));
+    for doc in &mut ws.documents {
+        doc.load_to(2);
+        doc.exe(Event::Insert(Loc::at(0, 0), "x".to_string()));
+    }
+    // Output
+    let refused = ws.close(a, ClosePolicy::FailIfModified);
+    let missing = ws.close(10, ClosePolicy::Force);
+    let saved = ws.close(a, ClosePolicy::SaveFirst).unwrap();
+    let on_disk = std::fs::read_to_string("demos/close/a.txt").unwrap();
+    let blocked = ws.close_all(ClosePolicy::FailIfModified);
+    let unnamed = ws.close_all(ClosePolicy::SaveFirst);
+    let forced = ws.close_all(ClosePolicy::Force);
+    // Verification
+    assert!(matches!(refused, Err(Error::UnsavedChanges)));
+    assert!(matches!(missing, Err(Error::OutOfRange)));
+    assert!(!saved.modified);
+    assert_eq!(on_disk, "xa\n");
+    assert_eq!(blocked.len(), 2);
+    assert_eq!(blocked[0].name, "b.txt".to_string());
+    assert_eq!(unnamed.len(), 1);
+    assert_eq!(unnamed[0].name, "[No Name]".to_string());
+    assert!(unnamed[0].modified);
+    assert_eq!(std::fs::read_to_string("demos/close/b.txt").unwrap(), "xb\n");
+    assert!(forced.is_empty());
+    assert!(ws.documents.is_empty());
+    std::fs::remove_dir_all("demos/close");
+}
+
+#[test]
+#[allow(unused_must_use)]
+fn global_undo() {
+    // Test data
+    let size = Size { w: 10, h: 10 };
+    let mut ws = Workspace::new();
+    ws.add(Document::from_str(size, "foo a\n"));
+    ws.add(Document::from_str(size, "foo b\n"));
+    ws.add(Document::from_str(size, "other\n"));
+    for doc in &mut ws.documents {
+        doc.load_to(2);
+    }
+    let disabled = ws.undo().unwrap();
+    ws.enable_history();
+    // Output
+    ws.documents[0].exe(Event::Insert(Loc::at(0, 0), "1".to_string()));
+    ws.record();
+    ws.documents[2].exe(Event::Insert(Loc::at(0, 0), "2".to_string()));
+    ws.record();
+    let plan = ws.plan_replace("foo", "bar", None).unwrap();
+    ws.apply(size, &plan).unwrap();
+    let replaced: Vec<String> = ws.documents.iter().map(|d| d.file.to_string()).collect();
+    ws.undo().unwrap();
+    let undone: Vec<String> = ws.documents.iter().map(|d| d.file.to_string()).collect();
+    ws.undo().unwrap();
+    let last_anywhere = ws.documents[2].file.to_string();
+    let active = ws.active;
+    ws.redo().unwrap();
+    let redone = ws.documents[2].file.to_string();
+    ws.close(1, ClosePolicy::Force);
+    let remaining = ws.history.clone().unwrap();
+    ws.undo().unwrap();
+    ws.undo().unwrap();
+    let nothing = ws.undo().unwrap();
+    // Verification
+    assert!(!disabled);
+    assert_eq!(replaced, vec!["1bar a\n", "bar b\n", "2other\n"]);
+    assert_eq!(undone, vec!["1foo a\n", "foo b\n", "2other\n"]);
+    assert_eq!(last_anywhere, "other\n");
+    assert_eq!(active, 2);
+    assert_eq!(redone, "2other\n");
+    assert_eq!(remaining.undo, vec![
+        GlobalChange { documents: vec![(0, 1)] },
+        GlobalChange { documents: vec![(1, 1)] },
+    ]);
+    assert_eq!(remaining.redo, vec![GlobalChange { documents: vec![(0, 1)] }]);
+    assert_eq!(ws.documents[0].file.to_string(), "foo a\n");
+    assert_eq!(ws.documents[1].file.to_string(), "other\n");
+    assert!(!nothing);
+}
+
+#[test]
+#[allow(unused_must_use)]
+fn paste_detection() {
+    // Test data
+    let size = Size { w: 10, h: 10 };
+    let mut doc = Document::from_str(size, "\n");
+    doc.load_to(2);
+    doc.set_grouping(GroupingPolicy::Word);
+    let mut mgmt = EventMgmt::default();
+    let start = std::time::Instant::now();
+    let gap = Duration::from_millis(10);
+    // Output
+    let mut typed = vec![];
+    for (x, ch) in "a b c".chars().enumerate() {
+        typed.push(doc.exe(Event::Insert(Loc::at(x, 0), ch.to_string())).unwrap());
+    }
+    let typing = doc.event_mgmt.undo_stack().len();
+    doc.set_paste_detection(Some(Duration::from_secs(60)));
+    let mut pasted = vec![];
+    for (x, ch) in "pasted in one go".chars().enumerate() {
+        pasted.push(doc.exe(Event::Insert(Loc::at(x + 5, 0), ch.to_string())).unwrap());
+    }
+    let pasting = doc.is_pasting();
+    let patches = (doc.event_mgmt.undo_stack().len(), doc.event_mgmt.patch.len());
+    doc.undo();
+    let undone = doc.line(0).unwrap();
+    mgmt.paste_gap = Some(gap);
+    for i in 0..PASTE_EVENTS {
+        mgmt.group_before(start + gap * u32::try_from(i).unwrap());
+        mgmt.register(Event::InsertLine(i, "x".to_string()));
+    }
+    let burst = mgmt.is_paste();
+    mgmt.group_before(start + gap * 100);
+    // Verification
+    assert_eq!(typed[1], Status::WordBoundary);
+    assert_eq!(typing, 2);
+    assert_eq!(pasted[5], Status::None);
+    assert!(pasted[6..].iter().all(|s| *s == Status::ProbablyPaste));
+    assert!(pasting);
+    assert_eq!(patches, (2, 17));
+    assert_eq!(undone, "a b ");
+    assert!(burst);
+    assert_eq!(mgmt.burst, 0);
+    assert_eq!(mgmt.undo_stack().len(), 1);
+    assert_eq!(mgmt.undo_stack()[0].events.len(), PASTE_EVENTS);
+}
+
+#[test]
+#[allow(unused_must_use)]
+fn pasting() {
+    // Test data
+    let size = Size { w: 10, h: 10 };
+    let mut doc = Document::from_str(size, "fn main() {\n    let x = ;\n}\n");
+    doc.load_to(4);
+    doc.set_grouping(GroupingPolicy::Word);
+    let mut empty = Document::from_str(size, "");
+    empty.load_to(1);
+    // Output
+    doc.paste(Loc::at(12, 1), "if a {\r\n        b\r\n    }\r\n", true);
+    let reindented: Vec<String> = (0..6).filter_map(|y| doc.line(y)).collect();
+    let cursor = doc.loc();
+    let patches = doc.event_mgmt.undo_stack().len();
+    doc.undo();
+    let undone = doc.file.to_string();
+    doc.paste(Loc::at(12, 1), "1\r2 3", false);
+    let plain = (doc.line(1), doc.line(2), doc.loc());
+    let failed = doc.paste(Loc::at(40, 1), "nope\nnope", false);
+    empty.paste(Loc::at(0, 0), "a\nb", false);
+    // Verification
+    assert_eq!(reindented, vec![
+        "fn main() {".to_string(),
+        "    let x = if a {".to_string(),
+        "        b".to_string(),
+        "    }".to_string(),
+        ";".to_string(),
+        "}".to_string(),
+    ]);
+    assert_eq!(cursor, Loc::at(0, 4));
+    assert_eq!(patches, 1);
+    assert_eq!(undone, "fn main() {\n    let x = ;\n}\n");
+    assert_eq!(plain.0, Some("    let x = 1".to_string()));
+    assert_eq!(plain.1, Some("2 3;".to_string()));
+    assert_eq!(plain.2, Loc::at(3, 2));
+    assert!(matches!(failed, Err(Error::OutOfRange)));
+    assert_eq!(doc.event_mgmt.undo_stack().len(), 1);
+    assert_eq!(empty.file.to_string(), "a\nb\n");
+    assert_eq!(empty.loc(), Loc::at(1, 1));
+}
+
+#[test]
+#[allow(unused_must_use)]
+fn reindented_pasting() {
+    // Test data
+    let size = Size { w: 10, h: 10 };
+    let yanked = "if a {\n    b();\n}\n";
+    let mut spaces = Document::from_str(size, "fn main() {\n    loop {\n        x();\n\n    }\n}\n");
+    spaces.load_to(7);
+    let mut tabs = Document::from_str(size, "fn main() {\n\tx();\n}\n");
+    tabs.load_to(4);
+    // Output
+    spaces.paste_lines(4, yanked, true);
+    let reindented: Vec<String> = (3..7).filter_map(|y| spaces.line(y)).collect();
+    let cursor = spaces.loc();
+    spaces.undo();
+    let undone = spaces.file.to_string();
+    spaces.paste_lines(0, "        deep\r\n            deeper", false);
+    let kept = (spaces.line(0), spaces.line(1));
+    tabs.paste_lines(2, "  if a {\n      b();\n  }", true);
+    let failed = tabs.paste_lines(40, yanked, true);
+    // Verification
+    assert_eq!(reindented, vec![
+        "".to_string(),
+        "        if a {".to_string(),
+        "            b();".to_string(),
+        "        }".to_string(),
+    ]);
+    assert_eq!(cursor, Loc::at(8, 4));
+    assert_eq!(undone, "fn main() {\n    loop {\n        x();\n\n    }\n}\n");
+    assert_eq!(kept, (Some("        deep".to_string()), Some("            deeper".to_string())));
+    assert_eq!(tabs.file.to_string(), "fn main() {\n\tx();\n\tif a {\n\t\tb();\n\t}\n}\n");
+    assert!(matches!(failed, Err(Error::OutOfRange)));
+}
+
+#[test]
+#[allow(unused_must_use)]
+fn patch_merging() {
+    // Test data
+    let size = Size { w: 10, h: 10 };
+    let mut doc = Document::from_str(size, "start\n");
+    doc.load_to(2);
+    // Output
+    doc.exe(Event::Insert(Loc::at(5, 0), "!".to_string()));
+    doc.event_mgmt.commit();
+    doc.paste(Loc::at(0, 1), "pasted  text", false);
+    doc.exe(Event::Delete(Loc::at(7, 1), " ".to_string()));
+    let too_few = doc.event_mgmt.clone().merge_last(1);
+    let merged = doc.event_mgmt.merge_last(2);
+    let patches = doc.event_mgmt.undo_stack().len();
+    doc.undo();
+    let undone = doc.file.to_string();
+    doc.redo();
+    let redone = doc.file.to_string();
+    let everything = doc.event_mgmt.merge_last(10);
+    doc.undo();
+    // Verification
+    assert!(!too_few);
+    assert!(merged);
+    assert_eq!(patches, 2);
+    assert_eq!(undone, "start!\n");
+    assert_eq!(redone, "start!\npasted text\n");
+    assert!(everything);
+    assert_eq!(doc.event_mgmt.undo_stack().len(), 0);
+    assert_eq!(doc.file.to_string(), "start\n");
+}
+
+#[test]
+fn tab_width_changes() {
+    // Test data
+    let size = Size { w: 10, h: 10 };
+    let mut doc = Document::from_str(size, "\tx\n\t\t\tfar\n");
+    doc.load_to(3);
+    doc.goto(&Loc::at(1, 0));
+    doc.take_full_render_request();
+    // Output
+    let before = (doc.cursor, doc.offset);
+    doc.set_tab_width(8);
+    let wider = (doc.cursor, doc.offset, doc.char_loc());
+    let rerender = doc.take_full_render_request();
+    doc.goto(&Loc::at(3, 1));
+    let scrolled = (doc.cursor, doc.offset);
+    doc.set_tab_width(2);
+    let narrower = (doc.cursor, doc.offset, doc.char_loc());
+    // Verification
+    assert_eq!(before, (Loc::at(4, 0), Loc::at(0, 0)));
+    assert_eq!(wider, (Loc::at(8, 0), Loc::at(0, 0), Loc::at(1, 0)));
+    assert!(rerender);
+    assert_eq!(scrolled, (Loc::at(0, 1), Loc::at(24, 0)));
+    assert_eq!(narrower, (Loc::at(6, 1), Loc::at(0, 0), Loc::at(3, 1)));
+}
+
+#[test]
+fn syntax_regions() {
+    // Test data
+    let rust = Syntax::for_filetype("Rust");
+    let lua = Syntax::for_filetype("Lua");
+    let html = Syntax::for_filetype("HTML");
+    let line = r#"f("a \" (", 'b') /* ( */ + x(1) // )"#;
+    // Output
+    let regions = rust.regions(line);
+    let brackets: Vec<usize> = line
+        .chars()
+        .enumerate()
+        .filter(|(x, ch)| "()".contains(*ch) && rust.is_code(line, *x))
+        .map(|(x, _)| x)
+        .collect();
+    let unclosed = rust.regions("let s = \"abc");
+    let lua_regions = lua.regions("x = 1 --[[ a ]] -- b");
+    let html_regions = html.regions("<a href=\"\\\">x<!-- y");
+    let doc = Document::open(Size { w: 10, h: 10 }, "demos/8.rs").unwrap();
+    // Verification
+    assert_eq!(regions, vec![
+        Region { kind: RegionKind::String, range: 2..10 },
+        Region { kind: RegionKind::Comment, range: 17..24 },
+        Region { kind: RegionKind::Comment, range: 32..36 },
+    ]);
+    assert_eq!(brackets, vec![1, 15, 28, 30]);
+    assert_eq!(rust.region_at(line, 12), None);
+    assert_eq!(unclosed, vec![Region { kind: RegionKind::String, range: 8..12 }]);
+    assert_eq!(lua_regions, vec![
+        Region { kind: RegionKind::Comment, range: 6..15 },
+        Region { kind: RegionKind::Comment, range: 16..20 },
+    ]);
+    assert_eq!(html_regions, vec![
+        Region { kind: RegionKind::String, range: 8..11 },
+        Region { kind: RegionKind::Comment, range: 13..19 },
+    ]);
+    assert_eq!(regions[1].styled_span(), StyledSpan::new(17..24, StyledSpan::COMMENT));
+    assert_eq!(doc.syntax(), rust);
+    assert_eq!(Syntax::for_filetype("Plain Text"), Syntax::default());
+}
+
+#[test]
+#[allow(unused_must_use)]
+fn file_insertion() {
+    // Test data
+    let size = Size { w: 10, h: 10 };
+    std::fs::write("demos/insert.txt", "one\r\ntwo\r\n");
+    std::fs::write("demos/insert_bad.txt", [0x66, 0xff, 0x0a]);
+    let mut doc = Document::from_str(size, "first\nlast\n");
+    doc.load_to(3);
+    // Output
+    doc.insert_file(Loc::at(0, 1), "demos/insert.txt");
+    let inserted = doc.file.to_string();
+    let cursor = doc.char_loc();
+    let mid_line = doc.clone().insert_file(Loc::at(2, 0), "demos/insert.txt");
+    let missing = doc.insert_file(Loc::at(0, 0), "demos/nonexistent.txt");
+    let invalid = doc.insert_file(Loc::at(0, 0), "demos/insert_bad.txt");
+    let out_of_range = doc.insert_file(Loc::at(0, 10), "demos/insert.txt");
+    doc.undo();
+    let mut below = Document::from_str(size, "a\nb\n");
+    below.load_to(3);
+    Commands::default().run_line(&mut below, "insert_file demos/insert.txt");
+    let mut end = Document::from_str(size, "a\nb\n");
+    end.load_to(3);
+    end.goto(&Loc::at(0, 1));
+    Commands::default().run_line(&mut end, "insert_file demos/insert.txt");
+    // Verification
+    assert_eq!(inserted, "first\none\ntwo\nlast\n");
+    assert_eq!(cursor, Loc::at(0, 3));
+    assert!(mid_line.is_ok());
+    assert!(matches!(missing, Err(Error::Io(_))));
+    assert!(invalid.is_err());
+    assert!(matches!(out_of_range, Err(Error::OutOfRange)));
+    assert_eq!(doc.file.to_string(), "first\nlast\n");
+    assert_eq!(below.file.to_string(), "a\none\ntwo\nb\n");
+    assert_eq!(end.file.to_string(), "a\nb\none\ntwo\n");
+    std::fs::remove_file("demos/insert.txt");
+    std::fs::remove_file("demos/insert_bad.txt");
+}
+
+#[test]
+#[allow(unused_must_use)]
+fn gutter_rendering() {
+    // Test data
+    let mut doc = Document::from_str(Size::is(20, 10), "a\nb\nc\nd\ne\nf\ng\nh\ni\nj\n");
+    doc.load_to(11);
+    doc.set_baseline("a\nB\nc\nd\ne\nf\ng\nh\ni\nj\n");
+    doc.exe(Event::Insert(Loc::at(0, 2), "new\n".to_string()));
+    doc.place_sign(4, Sign::new("error", 'E', 10));
+    let numbered = LayoutOptions { line_numbers: true, separator: " │", ..LayoutOptions::default() };
+    let signed = LayoutOptions { sign_column: true, ..numbered };
+    // Output
+    let numbered_layout = Layout::new(Size::is(20, 10), &doc, &numbered);
+    let signed_layout = Layout::new(Size::is(20, 10), &doc, &signed);
+    let bare_layout = Layout::new(Size::is(20, 10), &doc, &LayoutOptions::default());
+    let tight_layout = Layout::new(Size::is(3, 10), &doc, &signed);
+    // Verification
+    assert_eq!(numbered_layout.gutter, 4);
+    assert_eq!(numbered_layout.gutter_render(&doc, &numbered, 0), " 1 │");
+    assert_eq!(numbered_layout.gutter_render(&doc, &numbered, 20), " ~ │");
+    assert_eq!(signed_layout.gutter, 5);
+    assert_eq!(signed_layout.gutter_render(&doc, &signed, 0), "  1 │");
+    assert_eq!(signed_layout.gutter_render(&doc, &signed, 1), "~ 2 │");
+    assert_eq!(signed_layout.gutter_render(&doc, &signed, 2), "+ 3 │");
+    assert_eq!(signed_layout.gutter_render(&doc, &signed, 4), "E 5 │");
+    doc.remove_sign(4, "error");
+    assert_eq!(signed_layout.gutter_render(&doc, &signed, 4), "  5 │");
+    assert_eq!(bare_layout.gutter, 0);
+    assert_eq!(bare_layout.gutter_render(&doc, &LayoutOptions::default(), 0), "");
+    assert_eq!(tight_layout.gutter, 3);
+    assert_eq!(tight_layout.gutter_render(&doc, &signed, 0), "  1");
+}
+
+#[test]
+#[allow(unused_must_use)]
+fn sign_placement() {
+    // Test data
+    let mut doc = Document::from_str(Size::is(20, 3), "a\nb\nc\nd\ne\nf\n");
+    doc.load_to(7);
+    doc.place_sign(1, Sign::new("bookmark", 'B', 1));
+    doc.place_sign(1, Sign::new("error", 'E', 10));
+    doc.place_sign(1, Sign::new("breakpoint", '*', 5));
+    doc.place_sign(3, Sign::new("breakpoint", '*', 5));
+    doc.place_sign(5, Sign::new("error", 'E', 10));
+    // Output
+    let order: Vec<char> = doc.signs_at(1).iter().map(|s| s.symbol).collect();
+    let visible: Vec<(usize, char)> =
+        doc.visible_signs().iter().map(|(y, s)| (*y, s.symbol)).collect();
+    doc.exe(Event::InsertLine(0, "new".to_string()));
+    let after_insert = doc.signs_named("breakpoint");
+    doc.exe(Event::DeleteLine(2, "b".to_string()));
+    let after_delete = doc.signs_named("breakpoint");
+    doc.exe(Event::SplitDown(Loc::at(0, 2)));
+    let after_split = doc.signs_named("breakpoint");
+    doc.exe(Event::SpliceUp(Loc::at(0, 3)));
+    let after_splice = doc.signs_named("breakpoint");
+    let removed = doc.remove_sign(3, "breakpoint");
+    let missing = doc.remove_sign(3, "breakpoint");
+    doc.clear_signs("error");
+    // Verification
+    assert_eq!(order, vec!['E', '*', 'B']);
+    assert_eq!(visible, vec![(1, 'E')]);
+    assert_eq!(after_insert, vec![2, 4]);
+    assert_eq!(after_delete, vec![3]);
+    assert_eq!(after_split, vec![4]);
+    assert_eq!(after_splice, vec![3]);
+    assert!(removed);
+    assert!(!missing);
+    assert!(doc.signs.is_empty());
+    assert_eq!(doc.sign(3), None);
+}
+
+#[test]
+#[allow(unused_must_use)]
+fn line_anchors() {
+    // Test data
+    let mut anchors = Anchors::default();
+    let top = anchors.add(0);
+    let middle = anchors.add(3);
+    let bottom = anchors.add(6);
+    let mut doc = Document::from_str(Size::is(20, 10), "a\nb\nc\nd\ne\n");
+    doc.load_to(6);
+    let kept = doc.anchors.add(2);
+    let doomed = doc.anchors.add(4);
+    doc.place_sign(4, Sign::new("error", 'E', 10));
+    // Output
+    anchors.shift(LineShift::Inserted(3));
+    let inserted = (anchors.line(top), anchors.line(middle), anchors.line(bottom));
+    anchors.shift(LineShift::Joined(4));
+    let joined = anchors.at(3);
+    anchors.shift(LineShift::Removed(3));
+    let removed = anchors.take_removed();
+    let within = anchors.within(0..10);
+    doc.exe(Event::InsertLine(0, "new".to_string()));
+    doc.exe(Event::DeleteLine(5, "e".to_string()));
+    doc.exe(Event::Insert(Loc::at(0, 3), "x".to_string()));
+    // Verification
+    assert_eq!(inserted, (Some(0), Some(4), Some(7)));
+    assert_eq!(joined, vec![middle]);
+    assert_eq!(removed, vec![middle]);
+    assert!(anchors.take_removed().is_empty());
+    assert_eq!(within, vec![(0, top), (5, bottom)]);
+    assert_eq!(LineShift::of(&Event::SplitDown(Loc::at(2, 1))), Some(LineShift::Inserted(2)));
+    assert_eq!(LineShift::of(&Event::SplitDown(Loc::at(0, 1))), Some(LineShift::Inserted(1)));
+    assert_eq!(LineShift::of(&Event::Insert(Loc::at(0, 1), "a".to_string())), None);
+    assert_eq!(doc.anchors.line(kept), Some(3));
+    assert_eq!(doc.anchors.line(doomed), None);
+    assert_eq!(doc.anchors.take_removed(), vec![doomed]);
+    assert!(doc.signs.is_empty());
+    assert!(!doc.anchors.is_empty());
+}
+
+#[test]
+#[allow(unused_must_use)]
+fn wrapping_while_typing() {
+    // Test data
+    let mut prose = Document::from_str(Size::is(40, 10), "  \n");
+    prose.file_name = Some("notes.md".to_string());
+    let mut code = Document::from_str(Size::is(40, 10), "  \n");
+    code.file_name = Some("main.rs".to_string());
+    let mut commit = Document::from_str(Size::is(40, 10), "");
+    commit.file_name = Some(".git/COMMIT_EDITMSG".to_string());
+    for doc in [&mut prose, &mut code] {
+        doc.load_to(2);
+        doc.set_text_width(Some(16));
+        doc.goto(&Loc::at(2, 0));
+        for ch in "the quick brown fox jumps".chars() {
+            doc.exe(Event::Insert(doc.char_loc(), ch.to_string()));
+        }
+    }
+    // Output
+    let wrapped = prose.lines[..3].to_vec();
+    let cursor = prose.char_loc();
+    prose.exe(Event::Insert(Loc::at(7, 2), " over".to_string()));
+    let typed = prose.line(2);
+    while !matches!(prose.undo(), Ok(Status::NothingToUndo) | Err(_)) {}
+    let undone = prose.lines[..2].to_vec();
+    // Verification
+    assert_eq!(wrapped, vec!["  the quick", "  brown fox", "  jumps"]);
+    assert_eq!(cursor, Loc::at(7, 2));
+    assert_eq!(typed, Some("  jumps over".to_string()));
+    assert_eq!(undone, vec!["  ", ""]);
+    assert_eq!(code.line(0), Some("  the quick brown fox jumps".to_string()));
+    assert!(prose.is_prose());
+    assert!(commit.is_prose());
+    assert!(!code.is_prose());
+}
+
+#[test]
+fn document_outline() {
+    // Test data
+    let code = [
+        "pub struct Foo;",
+        "",
+        "impl Foo {",
+        "    pub fn new() -> Self {",
+        "        Foo",
+        "    }",
+        "",
+        "    fn helper() {}",
+        "}",
+        "",
+        "fn main() {}",
+    ];
+    let mut rust = Document::from_str(Size::is(80, 10), &code.join("\n"));
+    rust.file_name = Some("main.rs".to_string());
+    let headings = "# Title\ntext\n## Usage ##\n### Flags\n## Licence\n";
+    let mut markdown = Document::from_str(Size::is(80, 10), headings);
+    markdown.file_name = Some("README.md".to_string());
+    let toml = "[package]\nname = \"a\"\n\n[[bin]]\n";
+    let symbol = |x, y, kind: &str, name: &str, level| Symbol {
+        loc: Loc::at(x, y),
+        kind: kind.to_string(),
+        name: name.to_string(),
+        level,
+    };
+    // Output
+    let rust_outline = rust.outline();
+    let markdown_outline = markdown.outline();
+    let toml_outline = outline("TOML", toml.lines(), 4);
+    let plain = Document::from_str(Size::is(80, 10), "# not a heading\n").outline();
+    // Verification
+    assert_eq!(rust_outline, vec![
+        symbol(11, 0, "struct", "Foo", 0),
+        symbol(11, 3, "fn", "new", 1),
+        symbol(7, 7, "fn", "helper", 1),
+        symbol(3, 10, "fn", "main", 0),
+    ]);
+    assert_eq!(markdown_outline, vec![
+        symbol(2, 0, "heading", "Title", 0),
+        symbol(3, 2, "heading", "Usage", 1),
+        symbol(4, 3, "heading", "Flags", 2),
+        symbol(3, 4, "heading", "Licence", 1),
+    ]);
+    assert_eq!(toml_outline, vec![
+        symbol(1, 0, "section", "package", 0),
+        symbol(2, 3, "section", "bin", 0),
+    ]);
+    assert!(plain.is_empty());
+}
+
+#[test]
+#[allow(unused_must_use)]
+fn document_offsets() {
+    // Test data
+    let mut doc = Document::from_str(Size::is(20, 10), "héllo\r\nwörld\nend\n");
+    // Output
+    let chars = doc.char_offset(&Loc::at(2, 1));
+    let bytes = doc.byte_offset(&Loc::at(2, 1));
+    let from_chars = doc.loc_from_char_offset(9);
+    let from_bytes = doc.loc_from_byte_offset(11);
+    let mid_char = doc.loc_from_byte_offset(10);
+    let inside_ending = doc.loc_from_char_offset(6);
+    let inside_char = doc.loc_from_byte_offset(2);
+    let past_line = doc.char_offset(&Loc::at(6, 0));
+    let past_end = doc.loc_from_char_offset(100);
+    doc.load_to(4);
+    doc.exe(Event::InsertLine(0, "ñew".to_string()));
+    let shifted = doc.byte_offset(&Loc::at(0, 3));
+    // Verification
+    assert_eq!(chars.unwrap(), 9);
+    assert_eq!(bytes.unwrap(), 11);
+    assert_eq!(from_chars.unwrap(), Loc::at(2, 1));
+    assert_eq!(from_bytes.unwrap(), Loc::at(2, 1));
+    assert_eq!(mid_char.unwrap(), Loc::at(1, 1));
+    assert_eq!(inside_ending.unwrap(), Loc::at(5, 0));
+    assert_eq!(inside_char.unwrap(), Loc::at(1, 0));
+    assert!(matches!(past_line, Err(Error::OutOfRange)));
+    assert!(past_end.is_err());
+    assert_eq!(shifted.unwrap(), 20);
+    assert_eq!(doc.char_offset(&Loc::at(3, 3)).unwrap(), 20);
+}
+
+#[test]
+#[allow(unused_must_use)]
+fn pending_events() {
+    // Test data
+    let mut doc = Document::from_str(Size::is(20, 10), "a b\nb a\na\n");
+    doc.load_to(4);
+    // Output
+    let queued = doc.queue_replace_all("a", "cc").unwrap();
+    let invalid_queue = doc.queue_replace_all("a(", "cc");
+    let started = doc.pending_progress();
+    let first = doc.apply_pending(Duration::ZERO).unwrap();
+    let part_way = doc.pending_progress();
+    let rest = doc.apply_pending(Duration::from_secs(10)).unwrap();
+    let replaced = doc.file.to_string();
+    let finished = doc.pending_progress();
+    doc.undo();
+    let undone = doc.file.to_string();
+    doc.queue_events(vec![
+        Event::Insert(Loc::at(0, 0), "x".to_string()),
+        Event::Insert(Loc::at(0, 1), "y".to_string()),
+    ]);
+    doc.apply_pending(Duration::ZERO);
+    let refused = doc.exe(Event::Insert(Loc::at(0, 2), "z".to_string()));
+    let refused_undo = doc.undo();
+    doc.cancel_pending();
+    let cancelled = doc.file.to_string();
+    doc.queue_events(vec![
+        Event::Insert(Loc::at(0, 0), "x".to_string()),
+        Event::Insert(Loc::at(0, 20), "y".to_string()),
+    ]);
+    let failed = doc.apply_pending(Duration::from_secs(10));
+    // Verification
+    assert_eq!(queued, 3);
+    assert!(matches!(invalid_queue, Err(Error::InvalidRegex(_))));
+    assert_eq!(started, (0, 6));
+    assert!(!first);
+    assert_eq!(part_way, (1, 6));
+    assert!(rest);
+    assert_eq!(replaced, "cc b\nb cc\ncc\n");
+    assert_eq!(finished, (0, 0));
+    assert_eq!(undone, "a b\nb a\na\n");
+    assert!(matches!(refused, Err(Error::EventsPending)));
+    assert!(matches!(refused_undo, Err(Error::EventsPending)));
+    assert_eq!(cancelled, "a b\nb a\na\n");
+    assert!(failed.is_err());
+    assert_eq!(doc.file.to_string(), "a b\nb a\na\n");
+    assert!(doc.pending.is_empty());
+}
+
+#[test]
+#[allow(unused_must_use)]
+fn tab_backspace_undo() {
+    // Test data
+    let mut doc = Document::from_str(Size::is(20, 10), "    a\n");
+    doc.set_tab_width(4);
+    doc.load_to(2);
+    // Output
+    doc.exe(Event::Delete(Loc::at(3, 0), " ".to_string()));
+    let deleted = doc.line(0);
+    let undone = doc.undo();
+    let restored = doc.line(0);
+    doc.redo();
+    // Verification
+    assert_eq!(deleted, Some("a".to_string()));
+    assert!(undone.is_ok());
+    assert_eq!(restored, Some("    a".to_string()));
+    assert_eq!(doc.line(0), Some("a".to_string()));
+}
+
+#[test]
+#[allow(unused_must_use)]
+fn batch_edits_under_grouping() {
+    // Test data
+    let mut doc = Document::from_str(Size::is(20, 10), "a\nb\nc\nd\n");
+    doc.set_grouping(GroupingPolicy::Word);
+    doc.load_to(5);
+    let mut other = doc.clone();
+    // Output
+    doc.format_with(str::to_uppercase);
+    let formatted = doc.file.to_string();
+    doc.undo();
+    let undone = doc.file.to_string();
+    doc.remove_range(Loc::at(0, 0), Loc::at(1, 2));
+    let removed = doc.file.to_string();
+    doc.undo();
+    doc.exe(Event::Insert(Loc::at(0, 0), "x ".to_string()));
+    other.exe(Event::Insert(Loc::at(0, 0), "x ".to_string()));
+    let mut clone = other.clone();
+    clone.exe(Event::Insert(Loc::at(0, 1), "y".to_string()));
+    other.exe(Event::Insert(Loc::at(0, 1), "y".to_string()));
+    // Verification
+    assert_eq!(formatted, "A\nB\nC\nD\n");
+    assert_eq!(undone, "a\nb\nc\nd\n");
+    assert_eq!(removed, "\nd\n");
+    assert_eq!(doc.file.to_string(), "x a\nb\nc\nd\n");
+    assert_eq!(doc.event_mgmt.batch, 0);
+    assert_eq!(clone, other);
+}
+
+#[test]
+fn splice_undo_inside_grapheme() {
+    // Test data
+    let size = Size { w: 10, h: 10 };
+    let mut doc = Document::from_str(size, "cafe\n\u{301}x\n");
+    doc.load_to(3);
+    // Output
+    doc.exe(Event::SpliceUp(Loc::at(4, 0))).unwrap();
+    let spliced = doc.line(0);
+    let undone = doc.undo();
+    // Verification
+    assert_eq!(spliced, Some("cafe\u{301}x".to_string()));
+    assert!(undone.is_ok());
+    assert_eq!(doc.line(0), Some("cafe".to_string()));
+    assert_eq!(doc.line(1), Some("\u{301}x".to_string()));
+    assert!(doc.redo().is_ok());
+    assert_eq!(doc.line(0), Some("cafe\u{301}x".to_string()));
+    assert!(matches!(doc.exe(Event::SplitDown(Loc::at(4, 0))), Err(Error::NotBoundary)));
+}
+
+#[test]
+#[allow(unused_must_use)]
+fn global_undo_after_local_changes() {
+    // Test data
+    let size = Size { w: 10, h: 10 };
+    let mut ws = Workspace::new();
+    ws.add(Document::from_str(size, "a\n"));
+    ws.documents[0].load_to(2);
+    ws.enable_history();
+    // Output
+    ws.documents[0].exe(Event::Insert(Loc::at(1, 0), "b".to_string()));
+    ws.record();
+    ws.documents[0].undo();
+    ws.documents[0].exe(Event::Insert(Loc::at(1, 0), "c".to_string()));
+    ws.record();
+    ws.documents[0].exe(Event::Insert(Loc::at(2, 0), "d".to_string()));
+    ws.documents[0].event_mgmt.commit();
+    ws.documents[0].exe(Event::Insert(Loc::at(3, 0), "e".to_string()));
+    ws.documents[0].event_mgmt.merge_last(2);
+    ws.record();
+    let changes = ws.history.clone().unwrap().undo;
+    ws.undo().unwrap();
+    let undone = ws.documents[0].file.to_string();
+    // Verification
+    assert_eq!(changes, vec![
+        GlobalChange { documents: vec![(0, 1)] },
+        GlobalChange { documents: vec![(0, 1)] },
+        GlobalChange { documents: vec![(0, 1)] },
+    ]);
+    assert_eq!(undone, "ac\n");
+}
+
 /*
 Template:
 